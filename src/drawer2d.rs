@@ -1,9 +1,11 @@
 use crate::Float;
 
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
 use crate::point2d::{CanvasPoint2D, Point2D};
+use crate::pointcloud2d::PointCloud2D;
 use crate::utils;
 
 #[wasm_bindgen]
@@ -19,34 +21,96 @@ pub struct Drawer2D {
 
     /// Real world width in meters
     width: Float,
+
+    /// Center of the viewport restored by `reset_view()`
+    default_center: Point2D,
+
+    /// Real world width restored by `reset_view()`
+    default_width: Float,
+
+    /// Smallest real world width that can be zoomed into
+    min_width: Float,
+
+    /// Largest real world width that can be zoomed out to
+    max_width: Float,
+
+    /// How many canvas pixels there are per CSS (i.e., logical) pixel.
+    /// This is more than 1 on HiDPI screens, after `setup_canvas_hidpi()`
+    pixel_ratio: Float,
+
+    /// Whether the canvas was set up by `setup_canvas_hidpi()`, even if the
+    /// `pixel_ratio` turned out to be 1 (so its CSS size is set)
+    hidpi: bool,
+
+    /// How the ends of the lines are drawn (i.e., the canvas' `lineCap`)
+    line_cap: String,
+
+    /// How the corners of the lines are drawn (i.e., the canvas' `lineJoin`)
+    line_join: String,
 }
 
-impl Drawer2D {
-    /// Returns the (height, width) of the viewport in meters
-    pub fn viewport_size(&self) -> (Float, Float) {
-        let canvas_width = self.canvas.width() as Float;
-        let canvas_height = self.canvas.height() as Float;
-        let r = canvas_width / canvas_height;
+/// The values of the canvas' `lineCap`
+const LINE_CAPS: [&str; 3] = ["butt", "round", "square"];
 
-        // height = self.width/r
-        (self.width / r, self.width)
-    }
+/// The values of the canvas' `lineJoin`
+const LINE_JOINS: [&str; 3] = ["miter", "round", "bevel"];
 
-    /// Clears the canvas
-    pub fn clear(&self) {
-        let height = self.canvas.height() as Float;
-        let width = self.canvas.width() as Float;
-        self.context.clear_rect(0.0, 0.0, width.into(), height.into());
+/// What part of the World a Drawer2D shows (i.e., the pan and the zoom),
+/// regardless of the size of the canvas
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Camera2D {
+    /// Center of the viewport in meters
+    pub center: Point2D,
+
+    /// Real world width in meters
+    pub width: Float,
+}
+
+#[wasm_bindgen]
+impl Camera2D {
+    /// Builds a new Camera2D
+    #[wasm_bindgen(constructor)]
+    pub fn new(center: Point2D, width: Float) -> Self {
+        Self { center, width }
     }
+}
 
-    /// Borrows the canvas
-    pub fn canvas(&self) -> &web_sys::HtmlCanvasElement {
-        &self.canvas
+/// The relation between the World and the Canvas at a certain moment
+///
+/// This is what the coordinate transformations of the Drawer2D
+/// are based on, detached from the HTML elements.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Viewport {
+    /// Center of the viewport in meters
+    pub center: Point2D,
+
+    /// Real world width in meters
+    pub width: Float,
+
+    /// Width of the canvas in pixels
+    pub canvas_width: Float,
+
+    /// Height of the canvas in pixels
+    pub canvas_height: Float,
+}
+
+impl Viewport {
+    /// Returns the (height, width) of the viewport in meters
+    pub fn size(&self) -> (Float, Float) {
+        let r = self.canvas_width / self.canvas_height;
+
+        // height = self.width/r
+        (self.width / r, self.width)
     }
 
-    /// Borrows the context
-    pub fn context(&self) -> &web_sys::CanvasRenderingContext2d {
-        &self.context
+    /// Returns the (min, max) corners of the visible World rectangle
+    pub fn world_rect(&self) -> (Point2D, Point2D) {
+        let (height, width) = self.size();
+        (
+            Point2D::new(self.center.x - width / 2., self.center.y - height / 2.),
+            Point2D::new(self.center.x + width / 2., self.center.y + height / 2.),
+        )
     }
 
     /// Calculates the position of a Point2D in meters within the
@@ -57,10 +121,10 @@ impl Drawer2D {
     /// out of the (width,height) tuple)
     pub fn as_canvas_point(&self, p: &Point2D) -> (CanvasPoint2D, bool) {
         // Viewport size
-        let (vp_height, vp_width) = self.viewport_size();
+        let (vp_height, vp_width) = self.size();
 
         // Canvas/World Aspect ratio
-        let r = self.canvas.width() as Float / self.width;
+        let r = self.pixels_per_meter();
 
         // find origin of the viewport reference system
         let ocx = self.center.x - vp_width / 2.;
@@ -73,17 +137,660 @@ impl Drawer2D {
         };
 
         let is_visible = pt.x >= 0.0
-            && pt.x <= self.canvas.width() as Float
+            && pt.x <= self.canvas_width
             && pt.y >= 0.0 as Float
-            && pt.y <= self.canvas.height() as Float;
+            && pt.y <= self.canvas_height;
 
         (pt, is_visible)
     }
+
+    /// Calculates how many pixels are there in a meter (i.e., the
+    /// Canvas/World aspect ratio)
+    ///
+    /// The width is never zero or negative (see `Drawer2D::set_width()`),
+    /// as that would make every transformation infinite or NaN.
+    pub fn pixels_per_meter(&self) -> Float {
+        debug_assert!(self.width > 0., "the viewport width must be positive");
+        self.canvas_width / self.width
+    }
+
+    /// Converts a length in meters into pixels
+    pub fn length_in_pixels(&self, length: Float) -> Float {
+        length * self.pixels_per_meter()
+    }
+
+    /// Finds the part of the canvas showing the World rectangle with corners
+    /// `a` and `b` (in any order), as its top-left corner and its (width, height),
+    /// in pixels.
+    ///
+    /// The canvas' Y axis goes down, so the top-left corner of the rectangle in
+    /// the canvas is the one with the smallest X and the largest Y in the World.
+    pub fn canvas_rect(&self, a: &Point2D, b: &Point2D) -> (CanvasPoint2D, Float, Float) {
+        let top_left = Point2D::new(a.x.min(b.x), a.y.max(b.y));
+        let (corner, _) = self.as_canvas_point(&top_left);
+        let width = self.length_in_pixels((a.x - b.x).abs());
+        let height = self.length_in_pixels((a.y - b.y).abs());
+        (corner, width, height)
+    }
+
+    /// Changes the size of the canvas (in pixels), keeping the center.
+    ///
+    /// The width of the viewport (in meters) is kept too, so only the visible
+    /// height changes with the new aspect ratio... unless `preserve_area`, in
+    /// which case the width changes so the visible area stays the same.
+    pub fn resize(&mut self, canvas_height: Float, canvas_width: Float, preserve_area: bool) {
+        if preserve_area {
+            // The area is width * width * canvas_height / canvas_width
+            let scale =
+                ((self.canvas_height * canvas_width) / (self.canvas_width * canvas_height)).sqrt();
+            if scale.is_finite() && scale > 0. {
+                self.width *= scale;
+            }
+        }
+        self.canvas_height = canvas_height;
+        self.canvas_width = canvas_width;
+    }
+
+    /// Moves the viewport so that the World moves `dx_px` pixels to the right
+    /// and `dy_px` pixels down the canvas (e.g., following a drag)
+    pub fn pan_pixels(&mut self, dx_px: Float, dy_px: Float) {
+        // Meters per pixel
+        let r = self.width / self.canvas_width;
+
+        // The canvas' Y axis goes down
+        self.center.x -= dx_px * r;
+        self.center.y += dy_px * r;
+    }
+
+    /// Centers the viewport in the `[min, max]` rectangle, making it wide
+    /// enough to show all of it plus `padding_fraction` of its size on
+    /// each side.
+    ///
+    /// If the rectangle has no size (e.g., it is a single point), the
+    /// viewport becomes `fallback_width` wide.
+    pub fn fit_to_bounds(
+        &mut self,
+        min: &Point2D,
+        max: &Point2D,
+        padding_fraction: Float,
+        fallback_width: Float,
+    ) {
+        // The height of the rectangle, expressed as a viewport width
+        let r = self.canvas_width / self.canvas_height;
+        let width = (max.x - min.x).max((max.y - min.y) * r);
+
+        self.center = Point2D::new((min.x + max.x) / 2., (min.y + max.y) / 2.);
+        self.width = if width > 0. {
+            width * (1. + 2. * padding_fraction.max(0.))
+        } else {
+            fallback_width
+        };
+    }
+
+    /// Changes the width of the viewport to `width`, moving its center
+    /// so that the canvas point `p` keeps showing the same world point
+    pub fn zoom_at(&mut self, width: Float, p: &CanvasPoint2D) {
+        let anchor = self.as_world_point(p);
+        let scale = width / self.width;
+        self.center = Point2D::new(
+            anchor.x + (self.center.x - anchor.x) * scale,
+            anchor.y + (self.center.y - anchor.y) * scale,
+        );
+        self.width = width;
+    }
+
+    /// Transforms the (`x`, `y`) pixel of the canvas into a world point
+    pub fn world_point_from_pixels(&self, x: u32, y: u32) -> Point2D {
+        self.as_world_point(&CanvasPoint2D::new(x as Float, y as Float))
+    }
+
+    /// Transforms a canvas point into a world point
+    pub fn as_world_point(&self, p: &CanvasPoint2D) -> Point2D {
+        // Viewport size
+        let (vp_height, vp_width) = self.size();
+
+        // Canvas/World Aspect ratio
+        let r = self.pixels_per_meter();
+
+        // find origin of the viewport reference system
+        let ocx = self.center.x - vp_width / 2.;
+        let ocy = -(self.center.y + vp_height / 2.);
+
+        // return
+        Point2D {
+            x: ocx + p.x / r,
+            y: -ocy - p.y / r,
+        }
+    }
+}
+
+impl Drawer2D {
+    /// Creates a Drawer2D that is not attached to any canvas, for testing
+    /// whatever does not touch the HTML elements
+    #[cfg(test)]
+    pub(crate) fn detached() -> Self {
+        Self {
+            context: JsValue::UNDEFINED.unchecked_into(),
+            canvas: JsValue::UNDEFINED.unchecked_into(),
+            center: Point2D { x: 0.0, y: 0.0 },
+            width: 10.,
+            default_center: Point2D { x: 0.0, y: 0.0 },
+            default_width: 10.,
+            min_width: 1e-2,
+            max_width: 1e5,
+            pixel_ratio: 1.,
+            hidpi: false,
+            line_cap: "butt".to_string(),
+            line_join: "miter".to_string(),
+        }
+    }
+
+    /// Returns the (width, height) of the canvas in logical pixels, which
+    /// is what drawing and the coordinate transformations work with
+    fn canvas_size(&self) -> (Float, Float) {
+        (
+            self.canvas.width() as Float / self.pixel_ratio,
+            self.canvas.height() as Float / self.pixel_ratio,
+        )
+    }
+
+    /// Captures the current relation between the World and the Canvas
+    pub(crate) fn viewport(&self) -> Viewport {
+        let (canvas_width, canvas_height) = self.canvas_size();
+        Viewport {
+            center: self.center,
+            width: self.width,
+            canvas_width,
+            canvas_height,
+        }
+    }
+
+    /// Fills the current path with the CSS style `fill` and outlines it with the
+    /// CSS style `stroke`, `line_width` pixels wide... skipping any of them if empty
+    fn fill_and_stroke(&self, fill: &str, stroke: &str, line_width: f64) {
+        if !fill.is_empty() {
+            self.context.set_fill_style_str(fill);
+            self.context.fill();
+        }
+        if !stroke.is_empty() {
+            self.context.set_line_width(line_width);
+            self.context.set_stroke_style_str(stroke);
+            self.context.stroke();
+        }
+    }
+
+    /// Outlines the current path with the CSS style `color`, `width` pixels wide,
+    /// alternating dashes and gaps of the lengths (in pixels) in `dash`. An empty
+    /// `dash` draws a solid line.
+    ///
+    /// The styling is restored afterwards, so it does not leak into whatever
+    /// is drawn next.
+    fn stroke(&self, color: &str, width: f64, dash: &[f64]) {
+        self.context.save();
+        self.context.set_line_width(width);
+        self.context.set_stroke_style_str(color);
+        self.context.set_line_cap(&self.line_cap);
+        self.context.set_line_join(&self.line_join);
+        if !dash.is_empty() {
+            // Patterns with negative or non-finite lengths are ignored
+            self.context
+                .set_line_dash(&JsValue::from(dash.to_vec()))
+                .unwrap();
+        }
+        self.context.stroke();
+        self.context.restore();
+    }
+
+    /// Sets up the canvas with a new size, just like it was set up before
+    /// (i.e., HiDPI or not), and adapts the viewport to it (see `Viewport::resize()`)
+    fn resize_viewport(&mut self, height: u32, width: u32, preserve_area: bool) {
+        let mut viewport = self.viewport();
+        if self.hidpi {
+            self.setup_canvas_hidpi(height, width);
+        } else {
+            self.setup_canvas(height, width);
+        }
+        viewport.resize(height as Float, width as Float, preserve_area);
+        self.center = viewport.center;
+        self.width = viewport.width.clamp(self.min_width, self.max_width);
+    }
+
+    /// Calculates the width of the viewport after zooming by `factor`,
+    /// within the zoom limits. Returns `None` if the factor is not positive.
+    fn zoomed_width(&self, factor: Float) -> Option<Float> {
+        if factor.is_nan() || factor <= 0. {
+            return None;
+        }
+        Some((self.width * factor).clamp(self.min_width, self.max_width))
+    }
+
+    /// Returns the (height, width) of the viewport in meters
+    pub fn viewport_size(&self) -> (Float, Float) {
+        self.viewport().size()
+    }
+
+    /// Returns the (min, max) corners of the visible World rectangle, in meters
+    pub fn visible_world_rect(&self) -> (Point2D, Point2D) {
+        self.viewport().world_rect()
+    }
+
+    /// Clears the canvas
+    ///
+    /// Note that `PointCloud2D::redraw()` clears the canvas before drawing, so
+    /// it wipes whatever was drawn before. When showing several clouds at once,
+    /// clear once and then `draw()` each of them... or use `draw_layers()`.
+    pub fn clear(&self) {
+        let (width, height) = self.canvas_size();
+        self.context
+            .clear_rect(0.0, 0.0, width.into(), height.into());
+    }
+
+    /// Borrows the canvas
+    pub fn canvas(&self) -> &web_sys::HtmlCanvasElement {
+        &self.canvas
+    }
+
+    /// Borrows the context
+    pub fn context(&self) -> &web_sys::CanvasRenderingContext2d {
+        &self.context
+    }
+
+    /// Calculates the position of a Point2D in meters within the
+    /// canvas (in pixels). Returns a tuple with the CanvasPoint2D and
+    /// a boolean stating whether the point is visible or not
+    ///
+    /// The result can be OUT of the canvas (e.g., negative values or
+    /// out of the (width,height) tuple)
+    pub fn as_canvas_point(&self, p: &Point2D) -> (CanvasPoint2D, bool) {
+        self.viewport().as_canvas_point(p)
+    }
+
+    /// Finds the node of a grid with a certain `spacing` that is
+    /// closest to `p`, returning it along with its (column, row) indexes.
+    ///
+    /// A non-positive spacing returns `p` itself, with indexes (0, 0).
+    pub fn nearest_grid_node(&self, p: &Point2D, spacing: Float) -> (Point2D, i64, i64) {
+        if spacing <= 0. {
+            return (*p, 0, 0);
+        }
+        let col = (p.x / spacing).round();
+        let row = (p.y / spacing).round();
+        (
+            Point2D::new(col * spacing, row * spacing),
+            col as i64,
+            row as i64,
+        )
+    }
+
+    /// Finds the points of `cloud` that became visible and those that got hidden,
+    /// respectively, since the `previous` state of the Drawer2D.
+    ///
+    /// This allows updating overlays without redrawing everything on each pan.
+    pub fn visibility_delta(
+        &self,
+        cloud: &PointCloud2D,
+        previous: &Self,
+    ) -> (Vec<usize>, Vec<usize>) {
+        visibility_delta(cloud, &previous.viewport(), &self.viewport())
+    }
+
+    /// Draws lines joining the `points` (in world coordinates), in order,
+    /// with a certain `color` and `width` (in pixels). When `closed`, the
+    /// last point is joined with the first one.
+    ///
+    /// The line alternates dashes and gaps of the lengths (in pixels) in
+    /// `dash` (e.g., `&[4., 2.]`), or it is solid if `dash` is empty. Its ends
+    /// and corners are drawn as set by `set_line_cap()` and `set_line_join()`.
+    ///
+    /// Nothing is drawn if there are less than two points.
+    pub fn draw_polyline(
+        &self,
+        points: &[Point2D],
+        color: &str,
+        width: f64,
+        dash: &[f64],
+        closed: bool,
+    ) {
+        if points.len() < 2 {
+            return;
+        }
+
+        // Points out of the canvas are fine... it clips the lines
+        self.context.begin_path();
+        for (i, p) in points.iter().enumerate() {
+            let (p, _) = self.as_canvas_point(p);
+            if i == 0 {
+                self.context.move_to(p.x.into(), p.y.into());
+            } else {
+                self.context.line_to(p.x.into(), p.y.into());
+            }
+        }
+        if closed {
+            self.context.close_path();
+        }
+        self.stroke(color, width, dash);
+    }
+
+    /// Clears the canvas once and then draws each cloud, in order, so
+    /// that the latter ones are drawn on top of the former ones.
+    pub fn draw_layers(&self, clouds: &[&PointCloud2D]) {
+        composite(clouds, || self.clear(), |cloud| cloud.draw(self));
+    }
+
+    /// Draws a dimension annotation between `a` and `b`: extension lines, a
+    /// dimension line offset by `offset_px` pixels, arrowheads and the distance.
+    ///
+    /// Positive offsets put the dimension line to the left of the `a`-`b`
+    /// direction, as seen on the screen.
+    pub fn draw_dimension(
+        &self,
+        a: &Point2D,
+        b: &Point2D,
+        offset_px: f64,
+        font: &str,
+        color: &str,
+    ) {
+        const ARROW_LENGTH: f64 = 8.;
+        const ARROW_ANGLE: f64 = 0.4;
+        const OVERSHOOT: f64 = 4.;
+
+        let (ca, _) = self.as_canvas_point(a);
+        let (cb, _) = self.as_canvas_point(b);
+        let ca: (f64, f64) = (ca.x.into(), ca.y.into());
+        let cb: (f64, f64) = (cb.x.into(), cb.y.into());
+        let (da, db) = match dimension_line(ca, cb, offset_px) {
+            Some(line) => line,
+            None => return,
+        };
+
+        let context = &self.context;
+        context.begin_path();
+
+        // Extension lines, slightly beyond the dimension line
+        let overshoot = OVERSHOOT.copysign(offset_px);
+        if let Some((ea, eb)) = dimension_line(ca, cb, offset_px + overshoot) {
+            for (from, to) in [(ca, ea), (cb, eb)].iter() {
+                context.move_to(from.0, from.1);
+                context.line_to(to.0, to.1);
+            }
+        }
+
+        // Dimension line
+        context.move_to(da.0, da.1);
+        context.line_to(db.0, db.1);
+
+        // Arrowheads, pointing outwards
+        let angle = (db.1 - da.1).atan2(db.0 - da.0);
+        for (tip, direction) in [(da, angle + std::f64::consts::PI), (db, angle)].iter() {
+            for side in [-1., 1.].iter() {
+                let back = direction + std::f64::consts::PI + side * ARROW_ANGLE;
+                context.move_to(tip.0, tip.1);
+                context.line_to(
+                    tip.0 + ARROW_LENGTH * back.cos(),
+                    tip.1 + ARROW_LENGTH * back.sin(),
+                );
+            }
+        }
+
+        context.set_line_width(1.);
+        context.set_stroke_style_str(color);
+        context.stroke();
+
+        // Label
+        let distance = a.distance_to(b);
+        context.set_font(font);
+        context.set_fill_style_str(color);
+        context.set_text_align("center");
+        context.set_text_baseline("bottom");
+        context
+            .fill_text(
+                &format!("{:.2}", distance),
+                (da.0 + db.0) / 2.,
+                (da.1 + db.1) / 2.,
+            )
+            .unwrap();
+    }
+
+    /// Draws a box with some lines of text (e.g., the coordinates of a point)
+    /// next to the `anchor`, connected to it by a leader line.
+    ///
+    /// The box goes above and to the right of the anchor, unless that would
+    /// put it out of the canvas... in which case it is flipped.
+    /// Nothing is drawn if the anchor is not visible.
+    pub fn draw_callout(
+        &self,
+        anchor: &Point2D,
+        lines: &[String],
+        font: &str,
+        text_color: &str,
+        bg_color: &str,
+    ) {
+        const PADDING: f64 = 4.;
+
+        let (a, is_visible) = self.as_canvas_point(anchor);
+        if !is_visible || lines.is_empty() {
+            return;
+        }
+        let anchor: (f64, f64) = (a.x.into(), a.y.into());
+
+        // Measure the text
+        self.context.set_font(font);
+        let mut text_width: f64 = 0.;
+        let mut line_height: f64 = 0.;
+        for line in lines {
+            if let Ok(metrics) = self.context.measure_text(line) {
+                text_width = text_width.max(metrics.width());
+                line_height = line_height
+                    .max(metrics.font_bounding_box_ascent() + metrics.font_bounding_box_descent());
+            }
+        }
+        let box_width = text_width + 2. * PADDING;
+        let box_height = line_height * lines.len() as f64 + 2. * PADDING;
+
+        let (canvas_width, canvas_height) = self.canvas_size();
+        let canvas_size = (canvas_width.into(), canvas_height.into());
+        let (x, y) = callout_position(anchor, (box_width, box_height), canvas_size);
+
+        // Leader line, towards the closest point of the box
+        let leader_end = (
+            anchor.0.clamp(x, x + box_width),
+            anchor.1.clamp(y, y + box_height),
+        );
+        self.context.begin_path();
+        self.context.move_to(anchor.0, anchor.1);
+        self.context.line_to(leader_end.0, leader_end.1);
+        self.context.set_line_width(1.);
+        self.context.set_stroke_style_str(text_color);
+        self.context.stroke();
+
+        // Box
+        self.context.set_fill_style_str(bg_color);
+        self.context.fill_rect(x, y, box_width, box_height);
+        self.context.stroke_rect(x, y, box_width, box_height);
+
+        // Text
+        self.context.set_fill_style_str(text_color);
+        self.context.set_text_baseline("top");
+        self.context.set_text_align("left");
+        for (i, line) in lines.iter().enumerate() {
+            let line_y = y + PADDING + i as f64 * line_height;
+            self.context.fill_text(line, x + PADDING, line_y).unwrap();
+        }
+    }
+}
+
+/// Builds the CSS (property, value) pair that sets the `css_cursor`
+/// (e.g., `"crosshair"`), going back to the `"default"` one if empty
+fn cursor_declaration(css_cursor: &str) -> (&'static str, &str) {
+    let value = css_cursor.trim();
+    if value.is_empty() {
+        ("cursor", "default")
+    } else {
+        ("cursor", value)
+    }
+}
+
+/// Calculates the endpoints of a dimension line between the canvas points `a` and `b`,
+/// offset by `offset` pixels perpendicularly to them.
+///
+/// Positive offsets go to the left of the `a`-`b` direction, as seen on
+/// the screen (e.g., above a dimension going to the right). Returns `None`
+/// if the points are coincident.
+fn dimension_line(a: (f64, f64), b: (f64, f64), offset: f64) -> Option<((f64, f64), (f64, f64))> {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length == 0. {
+        return None;
+    }
+    // The canvas' Y axis goes down
+    let (nx, ny) = (dy / length * offset, -dx / length * offset);
+    Some(((a.0 + nx, a.1 + ny), (b.0 + nx, b.1 + ny)))
+}
+
+/// Finds the top-left corner of a callout box of `box_size` (width, height)
+/// pixels pointing at `anchor`, within a canvas of `canvas_size` pixels.
+///
+/// The box goes above and to the right of the anchor, flipping
+/// horizontally and/or vertically if it does not fit in the canvas.
+fn callout_position(
+    anchor: (f64, f64),
+    box_size: (f64, f64),
+    canvas_size: (f64, f64),
+) -> (f64, f64) {
+    const OFFSET: f64 = 12.;
+
+    let (ax, ay) = anchor;
+    let (width, height) = box_size;
+
+    let mut x = ax + OFFSET;
+    if x + width > canvas_size.0 {
+        x = ax - OFFSET - width;
+    }
+
+    let mut y = ay - OFFSET - height;
+    if y < 0. {
+        y = ay + OFFSET;
+    }
+
+    (x, y)
+}
+
+/// Finds the points of `cloud` that are visible in the `current` Viewport
+/// but were not in the `previous` one, and vice versa.
+///
+/// Both lists are sorted by index.
+fn visibility_delta(
+    cloud: &PointCloud2D,
+    previous: &Viewport,
+    current: &Viewport,
+) -> (Vec<usize>, Vec<usize>) {
+    let visible_in = |vp: &Viewport| {
+        let (min, max) = vp.world_rect();
+        let mut visible = cloud.points_in_rect(&min, &max);
+        visible.sort_unstable();
+        visible
+    };
+    let before = visible_in(previous);
+    let after = visible_in(current);
+
+    let shown = after
+        .iter()
+        .filter(|i| before.binary_search(i).is_err())
+        .copied()
+        .collect();
+    let hidden = before
+        .iter()
+        .filter(|i| after.binary_search(i).is_err())
+        .copied()
+        .collect();
+    (shown, hidden)
+}
+
+/// Calculates the (pixel ratio, height, width) of a canvas showing `height` by
+/// `width` logical pixels on a screen with a certain pixel `ratio`.
+///
+/// Ratios that are not positive and finite are taken as 1.
+fn hidpi_size(height: u32, width: u32, ratio: Float) -> (Float, u32, u32) {
+    let ratio = if ratio.is_finite() && ratio > 0. {
+        ratio
+    } else {
+        1.
+    };
+    let scale = |logical: u32| (logical as Float * ratio).round() as u32;
+    (ratio, scale(height), scale(width))
+}
+
+/// Builds points from flat (x, y) pairs of coordinates, ignoring
+/// an unpaired last value
+pub(crate) fn points_from_flat(coordinates: &[Float]) -> Vec<Point2D> {
+    coordinates
+        .chunks_exact(2)
+        .map(|xy| Point2D::new(xy[0], xy[1]))
+        .collect()
+}
+
+/// Finds the multiples of `spacing` within `[from, to]`, in order.
+///
+/// Returns nothing if `spacing` is not positive or if there would be
+/// more than `max_count` of them.
+fn grid_positions(from: Float, to: Float, spacing: Float, max_count: usize) -> Vec<Float> {
+    if spacing.is_nan() || spacing <= 0. || from > to {
+        return Vec::new();
+    }
+    let first = (from / spacing).ceil();
+    let last = (to / spacing).floor();
+    let count = last - first + 1.;
+    if !count.is_finite() || count < 1. || count > max_count as Float {
+        return Vec::new();
+    }
+    (0..count as usize)
+        .map(|i| (first + i as Float) * spacing)
+        .collect()
+}
+
+/// Checks that `value` is one of the `allowed` ones (e.g., for the
+/// keyword-like properties of the canvas), returning it as a String
+fn one_of(value: &str, allowed: &[&str]) -> Result<String, String> {
+    if allowed.contains(&value) {
+        Ok(value.to_string())
+    } else {
+        Err(format!(
+            "invalid value '{}' (expected one of {})",
+            value,
+            allowed.join(", ")
+        ))
+    }
+}
+
+/// Rounds each coordinate of `p` to the nearest multiple of `spacing`
+/// (halfway values go away from zero).
+///
+/// Returns `p` itself if `spacing` is not positive.
+pub(crate) fn snap_to_grid(p: &Point2D, spacing: Float) -> Point2D {
+    if spacing.is_nan() || spacing <= 0. {
+        return *p;
+    }
+    Point2D::new(
+        (p.x / spacing).round() * spacing,
+        (p.y / spacing).round() * spacing,
+    )
+}
+
+/// Calls `clear` once and then `draw` on each of the `layers`, in order.
+fn composite<L>(layers: &[L], mut clear: impl FnMut(), mut draw: impl FnMut(&L)) {
+    clear();
+    for layer in layers {
+        draw(layer);
+    }
 }
 
 #[wasm_bindgen]
 impl Drawer2D {
     /// Creates a new drawer
+    ///
+    /// There is no `Default`, as this panics outside of a browser (i.e.,
+    /// without a document and a canvas).
+    #[allow(clippy::new_without_default)]
     #[wasm_bindgen(constructor)]
     pub fn new() -> Self {
         utils::set_panic_hook();
@@ -109,26 +816,27 @@ impl Drawer2D {
             canvas,
             center: Point2D { x: 0.0, y: 0.0 },
             width: 10.,
+            default_center: Point2D { x: 0.0, y: 0.0 },
+            default_width: 10.,
+            min_width: 1e-2,
+            max_width: 1e5,
+            pixel_ratio: 1.,
+            hidpi: false,
+            line_cap: "butt".to_string(),
+            line_join: "miter".to_string(),
         }
     }
 
     /// Transforms a canvas point into a world point
     pub fn as_world_point(&self, p: &CanvasPoint2D) -> Point2D {
-        // Viewport size
-        let (vp_height, vp_width) = self.viewport_size();
-
-        // Canvas/World Aspect ratio
-        let r = self.canvas.width() as Float / self.width;
-
-        // find origin of the viewport reference system
-        let ocx = self.center.x - vp_width / 2.;
-        let ocy = -(self.center.y + vp_height / 2.);
+        self.viewport().as_world_point(p)
+    }
 
-        // return
-        Point2D {
-            x: ocx + p.x / r,
-            y: -ocy - p.y / r,
-        }
+    /// Transforms the (`x`, `y`) pixel of the canvas into a world point,
+    /// just like `as_world_point()`. This takes the coordinates that the
+    /// mouse events of the tools receive.
+    pub fn world_point_from_pixels(&self, x: u32, y: u32) -> Point2D {
+        self.viewport().world_point_from_pixels(x, y)
     }
 
     /// Sets up the size of the canvas and
@@ -136,12 +844,62 @@ impl Drawer2D {
     pub fn setup_canvas(&mut self, height: u32, width: u32) {
         self.canvas.set_width(width);
         self.canvas.set_height(height);
+
+        // Drop the CSS size set by `setup_canvas_hidpi()`, if any, or the
+        // canvas would be stretched to it
+        let style = self.canvas.style();
+        style.remove_property("width").unwrap();
+        style.remove_property("height").unwrap();
+        self.pixel_ratio = 1.;
+        self.hidpi = false;
+    }
+
+    /// Sets up the size of the canvas, in logical (i.e., CSS) pixels, for
+    /// sharp drawing on HiDPI screens.
+    ///
+    /// The canvas gets as many pixels as the screen has (i.e., the logical
+    /// size times `window.devicePixelRatio`, which is read here... so call
+    /// this again if it changes), while it is shown with the logical size.
+    /// Drawing and the coordinate transformations keep working in logical pixels.
+    pub fn setup_canvas_hidpi(&mut self, height: u32, width: u32) {
+        let ratio = web_sys::window().unwrap().device_pixel_ratio() as Float;
+        let (ratio, backing_height, backing_width) = hidpi_size(height, width, ratio);
+
+        // Resizing resets the context, including its scale
+        self.canvas.set_width(backing_width);
+        self.canvas.set_height(backing_height);
+        let style = self.canvas.style();
+        style
+            .set_property("width", &format!("{}px", width))
+            .unwrap();
+        style
+            .set_property("height", &format!("{}px", height))
+            .unwrap();
+        self.context.scale(ratio.into(), ratio.into()).unwrap();
+        self.pixel_ratio = ratio;
+        self.hidpi = true;
+    }
+
+    /// Changes the size of the canvas (in logical pixels, like `setup_canvas()`
+    /// or `setup_canvas_hidpi()`, whichever was used) after the page changes
+    /// (e.g., the window is resized), keeping the center of the viewport.
+    ///
+    /// The width of the viewport (in meters) is held fixed, so only the
+    /// visible height changes with the new aspect ratio.
+    pub fn resize(&mut self, height: u32, width: u32) {
+        self.resize_viewport(height, width, false);
+    }
+
+    /// Changes the size of the canvas just like `resize()`, but changing the
+    /// width of the viewport (within the zoom limits) so that the visible
+    /// area of the World stays (roughly) the same.
+    pub fn resize_preserve_area(&mut self, height: u32, width: u32) {
+        self.resize_viewport(height, width, true);
     }
 
     /// Retreives the width of the viewport in World dimensions
     pub fn width(&self) -> Float {
-        let (_height, width) = self.viewport_size();
-        width
+        self.width
     }
 
     /// Retreives the height of the viewport in World dimensions
@@ -155,10 +913,907 @@ impl Drawer2D {
         self.center.x += x;
         self.center.y += y;
     }
+
+    /// Scales the width of the viewport by `factor`, keeping its center
+    /// (e.g., `0.9` zooms in and `1.1` zooms out).
+    ///
+    /// The resulting width is clamped to the zoom limits.
+    pub fn zoom(&mut self, factor: Float) {
+        if let Some(width) = self.zoomed_width(factor) {
+            self.width = width;
+        }
+    }
+
+    /// Scales the width of the viewport by `factor`, just like `zoom()`, but
+    /// keeping the world point under the (`canvas_x`, `canvas_y`) pixel
+    /// (e.g., the mouse) in place.
+    pub fn zoom_at(&mut self, factor: Float, canvas_x: Float, canvas_y: Float) {
+        if let Some(width) = self.zoomed_width(factor) {
+            let cursor = CanvasPoint2D {
+                x: canvas_x,
+                y: canvas_y,
+            };
+            let mut viewport = self.viewport();
+            viewport.zoom_at(width, &cursor);
+            self.center = viewport.center;
+            self.width = viewport.width;
+        }
+    }
+
+    /// Centers the viewport in the `[min, max]` rectangle (e.g., the `bounds()`
+    /// of a PointCloud2D), zooming so that all of it is visible with a margin
+    /// of `padding_fraction` of its size on each side.
+    ///
+    /// A rectangle with no size (e.g., a single point) gets the default width.
+    /// The resulting width is clamped to the zoom limits.
+    pub fn fit_to_bounds(&mut self, min: &Point2D, max: &Point2D, padding_fraction: Float) {
+        let mut viewport = self.viewport();
+        viewport.fit_to_bounds(min, max, padding_fraction, self.default_width);
+        self.center = viewport.center;
+        self.width = viewport.width.clamp(self.min_width, self.max_width);
+    }
+
+    /// Sets the smallest and largest real world widths that can be zoomed
+    /// to, which are `0.01` and `100000` by default.
+    ///
+    /// Limits that are not positive and finite, or with `min > max`, are
+    /// ignored. The current width is brought within the new limits.
+    pub fn set_zoom_limits(&mut self, min: Float, max: Float) {
+        if min.is_nan() || min <= 0. || min > max || !max.is_finite() {
+            return;
+        }
+        self.min_width = min;
+        self.max_width = max;
+        self.width = self.width.clamp(min, max);
+    }
+
+    /// Sets the real world width of the viewport (in meters), keeping its
+    /// center, and brought within the zoom limits (see `set_zoom_limits()`).
+    ///
+    /// Returns an error (and keeps the current width) if `width` is not
+    /// positive and finite, as the World could not be drawn.
+    pub fn set_width(&mut self, width: Float) -> Result<(), String> {
+        if width.is_nan() || width <= 0. || width.is_infinite() {
+            return Err(format!("invalid viewport width {}", width));
+        }
+        self.width = width.clamp(self.min_width, self.max_width);
+        Ok(())
+    }
+
+    /// Returns the current center and width of the viewport (e.g., for
+    /// restoring them later with `set_camera()`)
+    pub fn camera(&self) -> Camera2D {
+        Camera2D::new(self.center, self.width)
+    }
+
+    /// Moves the viewport to the center and width of `camera`, the latter
+    /// brought within the zoom limits like in `set_width()`.
+    ///
+    /// Returns an error (and keeps the current viewport) if the width
+    /// is not positive and finite.
+    pub fn set_camera(&mut self, camera: Camera2D) -> Result<(), String> {
+        self.set_width(camera.width)?;
+        self.center = camera.center;
+        Ok(())
+    }
+
+    /// Moves the center of the viewport to (`x`, `y`)
+    pub fn set_center(&mut self, x: Float, y: Float) {
+        self.center = Point2D::new(x, y);
+    }
+
+    /// Moves the viewport so that the contents move `dx_px` pixels to the right
+    /// and `dy_px` pixels down the canvas, regardless of the zoom (e.g., for
+    /// following a drag of the mouse).
+    pub fn pan_pixels(&mut self, dx_px: Float, dy_px: Float) {
+        let mut viewport = self.viewport();
+        viewport.pan_pixels(dx_px, dy_px);
+        self.center = viewport.center;
+    }
+
+    /// Brings the viewport back to its default center and width, with the
+    /// width brought within the zoom limits (see `set_zoom_limits()`)
+    pub fn reset_view(&mut self) {
+        self.center = self.default_center;
+        self.width = self.default_width.clamp(self.min_width, self.max_width);
+    }
+
+    /// Makes the current center and width the ones `reset_view()` goes back to
+    pub fn set_default_view(&mut self) {
+        self.default_center = self.center;
+        self.default_width = self.width;
+    }
+
+    /// Sets the CSS cursor shown over the canvas (e.g., `"crosshair"` or `"grab"`).
+    ///
+    /// An empty string goes back to the default cursor.
+    pub fn set_cursor(&self, css_cursor: &str) {
+        let (property, value) = cursor_declaration(css_cursor);
+        self.canvas.style().set_property(property, value).unwrap();
+    }
+
+    /// Draws a line between the world points `a` and `b`, with a certain
+    /// `color` and `width` (in pixels), even if they are out of the canvas.
+    ///
+    /// The line is dashed like in `draw_polyline()`.
+    pub fn draw_line(&self, a: &Point2D, b: &Point2D, color: &str, width: f64, dash: &[f64]) {
+        self.draw_polyline(&[*a, *b], color, width, dash, false);
+    }
+
+    /// Draws a polyline like `draw_polyline()`, taking the points
+    /// as a flat list of coordinates (i.e., `[x0, y0, x1, y1, ...]`).
+    pub fn draw_polyline_flat(
+        &self,
+        coordinates: Vec<Float>,
+        color: &str,
+        width: f64,
+        dash: &[f64],
+        closed: bool,
+    ) {
+        self.draw_polyline(&points_from_flat(&coordinates), color, width, dash, closed);
+    }
+
+    /// Sets how the ends of the lines drawn by `draw_polyline()`, `draw_line()`
+    /// and `draw_grid()` look: `"butt"` (the default), `"round"` or `"square"`.
+    ///
+    /// Returns an error (and keeps the current one) for any other value.
+    pub fn set_line_cap(&mut self, cap: &str) -> Result<(), String> {
+        self.line_cap = one_of(cap, &LINE_CAPS)?;
+        Ok(())
+    }
+
+    /// Sets how the corners of the lines drawn by `draw_polyline()` look:
+    /// `"miter"` (the default), `"round"` or `"bevel"`.
+    ///
+    /// Returns an error (and keeps the current one) for any other value.
+    pub fn set_line_join(&mut self, join: &str) -> Result<(), String> {
+        self.line_join = one_of(join, &LINE_JOINS)?;
+        Ok(())
+    }
+
+    /// Draws a circle around `center` whose radius is `radius_world` meters (so
+    /// it grows and shrinks with the zoom), filled with the CSS style `fill` and
+    /// outlined with the CSS style `stroke`, `line_width` pixels wide.
+    ///
+    /// An empty `fill` or `stroke` skips the filling or the outline, and
+    /// nothing is drawn if `radius_world` is not positive.
+    pub fn draw_circle(
+        &self,
+        center: &Point2D,
+        radius_world: Float,
+        fill: &str,
+        stroke: &str,
+        line_width: f64,
+    ) {
+        if radius_world.is_nan() || radius_world <= 0. {
+            return;
+        }
+        let viewport = self.viewport();
+        let (c, _) = viewport.as_canvas_point(center);
+        let radius = viewport.length_in_pixels(radius_world);
+
+        self.context.begin_path();
+        self.context
+            .arc(
+                c.x.into(),
+                c.y.into(),
+                radius.into(),
+                0.,
+                2.0 * std::f64::consts::PI,
+            )
+            .unwrap();
+        self.fill_and_stroke(fill, stroke, line_width);
+    }
+
+    /// Draws the rectangle with corners `min` and `max` (in meters), filled and
+    /// outlined like in `draw_circle()`.
+    ///
+    /// The corners can actually be any two opposite ones (e.g., those of a
+    /// selection box dragged in any direction): the World's Y axis goes up
+    /// and the canvas' one goes down, so the corners are sorted before drawing.
+    pub fn draw_rect(
+        &self,
+        min: &Point2D,
+        max: &Point2D,
+        fill: &str,
+        stroke: &str,
+        line_width: f64,
+    ) {
+        let (corner, width, height) = self.viewport().canvas_rect(min, max);
+
+        self.context.begin_path();
+        self.context.rect(
+            corner.x.into(),
+            corner.y.into(),
+            width.into(),
+            height.into(),
+        );
+        self.fill_and_stroke(fill, stroke, line_width);
+    }
+
+    /// Writes `text` at the World point `p`, with a CSS `font` (e.g.,
+    /// `"14px sans-serif"`) and `color`.
+    ///
+    /// By default, `p` is at the left end of the baseline of the text (i.e.,
+    /// the text goes to the right of `p`, and above it except for the descenders).
+    /// `align` and `baseline` change that, taking the values of the canvas'
+    /// `textAlign` (e.g., `"center"` puts `p` in the middle of the text) and
+    /// `textBaseline` (e.g., `"middle"` or `"top"`). Unknown values are ignored.
+    ///
+    /// Nothing is drawn if `p` is not visible.
+    pub fn draw_text(
+        &self,
+        p: &Point2D,
+        text: &str,
+        font: &str,
+        color: &str,
+        align: Option<String>,
+        baseline: Option<String>,
+    ) {
+        let (p, is_visible) = self.as_canvas_point(p);
+        if !is_visible {
+            return;
+        }
+
+        self.context.set_font(font);
+        self.context.set_fill_style_str(color);
+        self.context
+            .set_text_align(align.as_deref().unwrap_or("left"));
+        self.context
+            .set_text_baseline(baseline.as_deref().unwrap_or("alphabetic"));
+        self.context
+            .fill_text(text, p.x.into(), p.y.into())
+            .unwrap();
+    }
+
+    /// Moves `p` to the closest node of a grid of a certain `spacing` (in
+    /// meters), such as the one drawn by `draw_grid()`.
+    ///
+    /// A `spacing` that is not positive returns `p` unchanged.
+    pub fn snap_to_grid(&self, p: &Point2D, spacing: Float) -> Point2D {
+        snap_to_grid(p, spacing)
+    }
+
+    /// Draws a grid of lines at every multiple of `spacing_world` (in meters)
+    /// within the viewport, with a certain `color` and `line_width` (in pixels),
+    /// dashed like in `draw_polyline()`.
+    ///
+    /// Nothing is drawn if `spacing_world` is not positive.
+    pub fn draw_grid(&self, spacing_world: Float, color: &str, line_width: f64, dash: &[f64]) {
+        let (min, max) = self.visible_world_rect();
+
+        // Lines closer than a pixel cannot be told apart. Not drawing those
+        // also bounds the loops when zoomed out far enough.
+        let (canvas_width, canvas_height) = self.canvas_size();
+        let max_lines = canvas_width.max(canvas_height) as usize;
+        let xs = grid_positions(min.x, max.x, spacing_world, max_lines);
+        let ys = grid_positions(min.y, max.y, spacing_world, max_lines);
+
+        self.context.begin_path();
+        for x in xs {
+            let (a, _) = self.as_canvas_point(&Point2D::new(x, min.y));
+            let (b, _) = self.as_canvas_point(&Point2D::new(x, max.y));
+            self.context.move_to(a.x.into(), a.y.into());
+            self.context.line_to(b.x.into(), b.y.into());
+        }
+        for y in ys {
+            let (a, _) = self.as_canvas_point(&Point2D::new(min.x, y));
+            let (b, _) = self.as_canvas_point(&Point2D::new(max.x, y));
+            self.context.move_to(a.x.into(), a.y.into());
+            self.context.line_to(b.x.into(), b.y.into());
+        }
+        self.stroke(color, line_width, dash);
+    }
+
+    /// Draws a marker (i.e., a dot with short axis ticks) on the
+    /// World's origin, so that users can re-orient themselves
+    ///
+    /// Nothing is drawn if the origin is not visible.
+    pub fn draw_origin(&self, size_px: f64, color: &str) {
+        let (o, is_visible) = self.as_canvas_point(&Point2D::new(0., 0.));
+        if !is_visible {
+            return;
+        }
+        let (x, y): (f64, f64) = (o.x.into(), o.y.into());
+
+        // Axis ticks
+        self.context.begin_path();
+        self.context.move_to(x - size_px, y);
+        self.context.line_to(x + size_px, y);
+        self.context.move_to(x, y - size_px);
+        self.context.line_to(x, y + size_px);
+        self.context.set_line_width(1.);
+        self.context.set_stroke_style_str(color);
+        self.context.stroke();
+
+        // Dot
+        self.context.begin_path();
+        self.context
+            .arc(x, y, size_px / 4., 0., 2.0 * std::f64::consts::PI)
+            .unwrap();
+        self.context.set_fill_style_str(color);
+        self.context.fill();
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    //use super::*;
+    use super::*;
+
+    #[test]
+    fn test_origin_visibility() {
+        let origin = Point2D::new(0., 0.);
+        let mut vp = Viewport {
+            center: Point2D::new(0., 0.),
+            width: 10.,
+            canvas_width: 200.,
+            canvas_height: 100.,
+        };
+
+        // Centered... the origin is in the middle of the canvas
+        let (p, is_visible) = vp.as_canvas_point(&origin);
+        assert!(is_visible);
+        assert!((p.x - 100.).abs() < 1e-9);
+        assert!((p.y - 50.).abs() < 1e-9);
+
+        // Still visible, on the left edge
+        vp.center = Point2D::new(5., 0.);
+        let (p, is_visible) = vp.as_canvas_point(&origin);
+        assert!(is_visible);
+        assert!(p.x.abs() < 1e-9);
+
+        // Panned far away
+        vp.center = Point2D::new(100., 0.);
+        assert!(!vp.as_canvas_point(&origin).1);
+
+        vp.center = Point2D::new(0., -3.);
+        assert!(!vp.as_canvas_point(&origin).1);
+    }
+
+    #[test]
+    fn test_composite() {
+        let layers = ["walls", "furniture", "annotations"];
+        let mut clears = 0;
+        let mut drawn: Vec<&str> = Vec::new();
+        composite(&layers, || clears += 1, |layer| drawn.push(layer));
+
+        assert_eq!(clears, 1);
+        assert_eq!(drawn, layers.to_vec());
+    }
+
+    #[test]
+    fn test_default_view() {
+        let mut drawer = Drawer2D::detached();
+
+        // Constructor defaults
+        drawer.translate_viewport(3., 4.);
+        drawer.reset_view();
+        assert_eq!(drawer.center, Point2D::new(0., 0.));
+        assert_eq!(drawer.width, 10.);
+
+        // Capture a new home
+        drawer.translate_viewport(3., 4.);
+        drawer.width = 2.;
+        drawer.set_default_view();
+
+        drawer.translate_viewport(-10., 1.);
+        drawer.width = 25.;
+        drawer.reset_view();
+        assert_eq!(drawer.center, Point2D::new(3., 4.));
+        assert_eq!(drawer.width, 2.);
+
+        // Within the zoom limits set afterwards
+        drawer.set_zoom_limits(5., 20.);
+        drawer.reset_view();
+        assert_eq!(drawer.center, Point2D::new(3., 4.));
+        assert_eq!(drawer.width, 5.);
+        drawer.set_zoom_limits(0.5, 1.);
+        drawer.reset_view();
+        assert_eq!(drawer.width, 1.);
+    }
 
+    #[test]
+    fn test_callout_position() {
+        let canvas = (200., 100.);
+        let size = (50., 20.);
+
+        // Plenty of space: above and to the right
+        assert_eq!(callout_position((100., 50.), size, canvas), (112., 18.));
+
+        // Near the right edge: flipped to the left
+        assert_eq!(callout_position((190., 50.), size, canvas), (128., 18.));
+
+        // Near the top edge: flipped below
+        assert_eq!(callout_position((100., 10.), size, canvas), (112., 22.));
+
+        // Near the top-right corner: flipped both ways
+        assert_eq!(callout_position((190., 10.), size, canvas), (128., 22.));
+    }
+
+    #[test]
+    fn test_dimension_line() {
+        // Horizontal, going right... positive offsets go up the screen
+        let (a, b) = dimension_line((0., 50.), (100., 50.), 10.).unwrap();
+        assert_eq!(a, (0., 40.));
+        assert_eq!(b, (100., 40.));
+
+        // ... and negative ones go down
+        let (a, b) = dimension_line((0., 50.), (100., 50.), -10.).unwrap();
+        assert_eq!(a, (0., 60.));
+        assert_eq!(b, (100., 60.));
+
+        // Vertical, going down the screen... positive offsets go right
+        let (a, b) = dimension_line((20., 0.), (20., 30.), 5.).unwrap();
+        assert_eq!(a, (25., 0.));
+        assert_eq!(b, (25., 30.));
+
+        // Diagonal: parallel and at the right distance
+        let (a, b) = dimension_line((0., 0.), (30., 40.), 5.).unwrap();
+        assert!((a.0 - 4.).abs() < 1e-12 && (a.1 + 3.).abs() < 1e-12);
+        assert!((b.0 - 34.).abs() < 1e-12 && (b.1 - 37.).abs() < 1e-12);
+
+        // Coincident
+        assert!(dimension_line((1., 1.), (1., 1.), 5.).is_none());
+    }
+
+    #[test]
+    fn test_cursor_declaration() {
+        assert_eq!(cursor_declaration("crosshair"), ("cursor", "crosshair"));
+        assert_eq!(cursor_declaration(" grab "), ("cursor", "grab"));
+        assert_eq!(cursor_declaration(""), ("cursor", "default"));
+        assert_eq!(cursor_declaration("  "), ("cursor", "default"));
+    }
+
+    #[test]
+    fn test_nearest_grid_node() {
+        let drawer = Drawer2D::detached();
+
+        let check = |x: Float, y: Float, expected: (Float, Float, i64, i64)| {
+            let (node, col, row) = drawer.nearest_grid_node(&Point2D::new(x, y), 1.0);
+            assert_eq!(node, Point2D::new(expected.0, expected.1));
+            assert_eq!((col, row), (expected.2, expected.3));
+        };
+        check(0.2, 0.3, (0., 0., 0, 0));
+        check(0.6, 1.4, (1., 1., 1, 1));
+        check(2.9, -0.4, (3., 0., 3, 0));
+        check(-1.2, -2.7, (-1., -3., -1, -3));
+
+        // Other spacings
+        let (node, col, row) = drawer.nearest_grid_node(&Point2D::new(1.3, -0.8), 0.5);
+        assert_eq!(node, Point2D::new(1.5, -1.0));
+        assert_eq!((col, row), (3, -2));
+
+        // No grid
+        let p = Point2D::new(1.3, -0.8);
+        assert_eq!(drawer.nearest_grid_node(&p, 0.0), (p, 0, 0));
+        assert_eq!(drawer.nearest_grid_node(&p, -1.0), (p, 0, 0));
+    }
+
+    #[test]
+    fn test_visibility_delta() {
+        // A static row of points, one every meter
+        let mut cloud = PointCloud2D::new();
+        for i in 0..10 {
+            cloud.push(Point2D::new(i as Float, 0.));
+        }
+
+        // Showing X from -1 to 3
+        let before = Viewport {
+            center: Point2D::new(1., 0.),
+            width: 4.,
+            canvas_width: 200.,
+            canvas_height: 100.,
+        };
+
+        // Nothing moved, nothing changed
+        let (shown, hidden) = visibility_delta(&cloud, &before, &before);
+        assert!(shown.is_empty());
+        assert!(hidden.is_empty());
+
+        // Pan to show X from 1.5 to 5.5
+        let mut after = before;
+        after.center = Point2D::new(3.5, 0.);
+        let (shown, hidden) = visibility_delta(&cloud, &before, &after);
+        assert_eq!(shown, vec![4, 5]);
+        assert_eq!(hidden, vec![0, 1]);
+
+        // ... and back
+        let (shown, hidden) = visibility_delta(&cloud, &after, &before);
+        assert_eq!(shown, vec![0, 1]);
+        assert_eq!(hidden, vec![4, 5]);
+
+        // Zooming out shows everything else
+        after.center = Point2D::new(4.5, 0.);
+        after.width = 20.;
+        let (shown, hidden) = visibility_delta(&cloud, &before, &after);
+        assert_eq!(shown, vec![4, 5, 6, 7, 8, 9]);
+        assert!(hidden.is_empty());
+    }
+
+    #[test]
+    fn test_zoom() {
+        let mut drawer = Drawer2D::detached();
+        drawer.translate_viewport(2., 3.);
+        let width = drawer.width();
+
+        // There and back again
+        drawer.zoom(0.9);
+        assert!((drawer.width() - 9.).abs() < 1e-5);
+        drawer.zoom(1. / 0.9);
+        assert!((drawer.width() - width).abs() < 1e-5);
+        drawer.zoom(1.1);
+        drawer.zoom(1. / 1.1);
+        assert!((drawer.width() - width).abs() < 1e-5);
+        assert_eq!(drawer.center, Point2D::new(2., 3.));
+
+        // Invalid factors do nothing
+        drawer.zoom(0.);
+        drawer.zoom(-2.);
+        drawer.zoom(Float::NAN);
+        assert!((drawer.width() - width).abs() < 1e-5);
+
+        // Limits
+        drawer.set_zoom_limits(1., 20.);
+        drawer.zoom(1e-9);
+        assert_eq!(drawer.width(), 1.);
+        drawer.zoom(1e9);
+        assert_eq!(drawer.width(), 20.);
+        drawer.set_zoom_limits(2., 5.);
+        assert_eq!(drawer.width(), 5.);
+
+        // ... invalid ones are ignored
+        drawer.set_zoom_limits(0., 5.);
+        drawer.set_zoom_limits(3., 1.);
+        drawer.set_zoom_limits(1., Float::INFINITY);
+        assert_eq!((drawer.min_width, drawer.max_width), (2., 5.));
+    }
+
+    #[test]
+    fn test_zoom_at() {
+        let mut vp = Viewport {
+            center: Point2D::new(3., -1.),
+            width: 10.,
+            canvas_width: 200.,
+            canvas_height: 100.,
+        };
+
+        let pixels = [(0., 0.), (150., 20.), (100., 50.), (37., 99.)];
+        for (x, y) in pixels.iter() {
+            for width in [9., 2.5, 40.].iter() {
+                let p = CanvasPoint2D { x: *x, y: *y };
+                let before = vp.as_world_point(&p);
+                vp.zoom_at(*width, &p);
+                let after = vp.as_world_point(&p);
+
+                assert_eq!(vp.width, *width);
+                assert!((before.x - after.x).abs() < 1e-4);
+                assert!((before.y - after.y).abs() < 1e-4);
+            }
+        }
+
+        // Zooming at the center keeps it
+        vp.center = Point2D::new(3., -1.);
+        vp.zoom_at(5., &CanvasPoint2D { x: 100., y: 50. });
+        assert!((vp.center.x - 3.).abs() < 1e-5);
+        assert!((vp.center.y + 1.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_fit_to_bounds() {
+        let mut vp = Viewport {
+            center: Point2D::new(100., 100.),
+            width: 1.,
+            canvas_width: 200.,
+            canvas_height: 100.,
+        };
+
+        let rects = [
+            ((0., 0.), (10., 1.)),    // wide
+            ((-3., 2.), (-2., 30.)),  // tall
+            ((5., 5.), (25., 15.)),   // same aspect ratio as the canvas
+            ((-1e4, -1.), (1e4, 1.)), // huge
+        ];
+        for (min, max) in rects.iter() {
+            let min = Point2D::new(min.0, min.1);
+            let max = Point2D::new(max.0, max.1);
+            for padding in [0., 0.1, 0.5].iter() {
+                vp.fit_to_bounds(&min, &max, *padding, 10.);
+                assert!(vp.as_canvas_point(&min).1);
+                assert!(vp.as_canvas_point(&max).1);
+                assert!(vp.as_canvas_point(&Point2D::new(min.x, max.y)).1);
+                assert!(vp.as_canvas_point(&Point2D::new(max.x, min.y)).1);
+            }
+
+            // Tight: nothing else fits on one of the axes
+            vp.fit_to_bounds(&min, &max, 0., 10.);
+            let (height, width) = vp.size();
+            let fits_x = (width - (max.x - min.x)).abs() < 1e-6 * width;
+            let fits_y = (height - (max.y - min.y)).abs() < 1e-6 * height;
+            assert!(fits_x || fits_y);
+        }
+
+        // A single point
+        let p = Point2D::new(4., 2.);
+        vp.fit_to_bounds(&p, &p, 0.1, 10.);
+        assert_eq!(vp.center, p);
+        assert_eq!(vp.width, 10.);
+        assert!(vp.as_canvas_point(&p).1);
+    }
+
+    #[test]
+    fn test_grid_positions() {
+        assert_eq!(grid_positions(-1.5, 2.2, 1., 100), vec![-1., 0., 1., 2.]);
+        assert_eq!(grid_positions(0., 1., 0.5, 100), vec![0., 0.5, 1.]);
+        assert_eq!(grid_positions(0.1, 0.9, 1., 100), Vec::<Float>::new());
+
+        // Non-positive spacing
+        assert!(grid_positions(-1., 1., 0., 100).is_empty());
+        assert!(grid_positions(-1., 1., -1., 100).is_empty());
+        assert!(grid_positions(-1., 1., Float::NAN, 100).is_empty());
+
+        // Too many lines
+        assert_eq!(grid_positions(0., 10., 1., 11).len(), 11);
+        assert!(grid_positions(0., 10., 1., 10).is_empty());
+        assert!(grid_positions(-1e30, 1e30, 1e-9, 1000).is_empty());
+    }
+
+    #[test]
+    fn test_points_from_flat() {
+        assert!(points_from_flat(&[]).is_empty());
+        assert!(points_from_flat(&[1.]).is_empty());
+        assert_eq!(
+            points_from_flat(&[1., 2., -3., 4.5, 7.]),
+            vec![Point2D::new(1., 2.), Point2D::new(-3., 4.5)]
+        );
+    }
+
+    #[test]
+    fn test_hidpi_size() {
+        assert_eq!(hidpi_size(100, 200, 1.), (1., 100, 200));
+        assert_eq!(hidpi_size(100, 200, 2.), (2., 200, 400));
+        assert_eq!(hidpi_size(101, 33, 1.5), (1.5, 152, 50));
+
+        // Nonsense ratios
+        assert_eq!(hidpi_size(100, 200, 0.), (1., 100, 200));
+        assert_eq!(hidpi_size(100, 200, -2.), (1., 100, 200));
+        assert_eq!(hidpi_size(100, 200, Float::NAN), (1., 100, 200));
+    }
+
+    #[test]
+    fn test_pan_pixels() {
+        let mut vp = Viewport {
+            center: Point2D::new(1., 2.),
+            width: 10.,
+            canvas_width: 200.,
+            canvas_height: 100.,
+        };
+
+        // A whole canvas to the right
+        vp.pan_pixels(200., 0.);
+        assert_eq!(vp.center, Point2D::new(-9., 2.));
+
+        // The contents follow the pixels, at any zoom
+        for width in [10., 0.3, 250.].iter() {
+            vp.width = *width;
+            let p = Point2D::new(3., 4.);
+            let (before, _) = vp.as_canvas_point(&p);
+            vp.pan_pixels(-13., 27.);
+            let (after, _) = vp.as_canvas_point(&p);
+            assert!((after.x - before.x + 13.).abs() < 1e-2);
+            assert!((after.y - before.y - 27.).abs() < 1e-2);
+        }
+
+        let mut drawer = Drawer2D::detached();
+        drawer.set_center(-3., 8.);
+        assert_eq!(drawer.center, Point2D::new(-3., 8.));
+    }
+
+    #[test]
+    fn test_canvas_rect() {
+        let vp = Viewport {
+            center: Point2D::new(1., 2.),
+            width: 10.,
+            canvas_width: 200.,
+            canvas_height: 100.,
+        };
+        assert_eq!(vp.length_in_pixels(1.), 20.);
+        assert_eq!(vp.length_in_pixels(0.25), 5.);
+
+        // The same rectangle, given by any two opposite corners
+        let corners = [
+            ((-2., -1.), (3., 1.)),
+            ((3., 1.), (-2., -1.)),
+            ((-2., 1.), (3., -1.)),
+            ((3., -1.), (-2., 1.)),
+        ];
+        for (a, b) in corners.iter() {
+            let a = Point2D::new(a.0, a.1);
+            let b = Point2D::new(b.0, b.1);
+            let (corner, width, height) = vp.canvas_rect(&a, &b);
+            let (expected, _) = vp.as_canvas_point(&Point2D::new(-2., 1.));
+            assert_eq!((corner.x, corner.y), (expected.x, expected.y));
+            assert_eq!((corner.x, corner.y), (40., 70.));
+            assert_eq!((width, height), (100., 40.));
+        }
+    }
+
+    #[test]
+    fn test_set_width() {
+        let mut drawer = Drawer2D::detached();
+        for width in [0., -0., -3., Float::NAN, Float::INFINITY].iter() {
+            assert!(drawer.set_width(*width).is_err());
+            assert_eq!(drawer.width, 10.);
+        }
+
+        assert!(drawer.set_width(25.).is_ok());
+        assert_eq!(drawer.width, 25.);
+
+        // Within the zoom limits
+        assert!(drawer.set_width(1e-9).is_ok());
+        assert_eq!(drawer.width, drawer.min_width);
+        assert!(drawer.set_width(1e9).is_ok());
+        assert_eq!(drawer.width, drawer.max_width);
+
+        // The transformations stay finite at the limits
+        for width in [drawer.min_width, drawer.max_width].iter() {
+            let vp = Viewport {
+                center: Point2D::new(1., 2.),
+                width: *width,
+                canvas_width: 200.,
+                canvas_height: 100.,
+            };
+            let (c, _) = vp.as_canvas_point(&Point2D::new(-3., 4.));
+            assert!(c.x.is_finite() && c.y.is_finite());
+            let p = vp.as_world_point(&CanvasPoint2D { x: 13., y: 170. });
+            assert!(p.x.is_finite() && p.y.is_finite());
+            assert!(vp.pixels_per_meter().is_finite());
+        }
+    }
+
+    #[test]
+    fn test_world_point_from_pixels() {
+        let vp = Viewport {
+            center: Point2D::new(1., 2.),
+            width: 10.,
+            canvas_width: 200.,
+            canvas_height: 100.,
+        };
+        assert_eq!(vp.world_point_from_pixels(100, 50), vp.center);
+        assert_eq!(vp.world_point_from_pixels(0, 0), Point2D::new(-4., 4.5));
+        for (x, y) in [(0, 0), (13, 170), (200, 100), (3, 99)].iter() {
+            let canvas_p = CanvasPoint2D::new(*x as Float, *y as Float);
+            assert_eq!(
+                vp.world_point_from_pixels(*x, *y),
+                vp.as_world_point(&canvas_p)
+            );
+        }
+    }
+
+    #[test]
+    fn test_snap_to_grid() {
+        let drawer = Drawer2D::detached();
+        let snap = |x: Float, y: Float, spacing: Float| {
+            let p = drawer.snap_to_grid(&Point2D::new(x, y), spacing);
+            (p.x, p.y)
+        };
+
+        // Just above and just below a grid line
+        assert_eq!(snap(2.01, 1.99, 1.), (2., 2.));
+        assert_eq!(snap(2.49, 2.51, 1.), (2., 3.));
+        assert_eq!(snap(0.74, 0.76, 0.5), (0.5, 1.));
+
+        // Symmetric for negative coordinates
+        assert_eq!(snap(-2.01, -1.99, 1.), (-2., -2.));
+        assert_eq!(snap(-2.49, -2.51, 1.), (-2., -3.));
+        assert_eq!(snap(-0.74, -0.76, 0.5), (-0.5, -1.));
+        assert_eq!(snap(2.5, -2.5, 1.), (3., -3.));
+
+        // Nonsense spacings
+        for spacing in [0., -1., Float::NAN].iter() {
+            assert_eq!(snap(1.3, -0.8, *spacing), (1.3, -0.8));
+        }
+    }
+
+    #[test]
+    fn test_resize() {
+        let original = Viewport {
+            center: Point2D::new(1., 2.),
+            width: 10.,
+            canvas_width: 200.,
+            canvas_height: 100.,
+        };
+        let area = |vp: &Viewport| {
+            let (height, width) = vp.size();
+            height * width
+        };
+
+        for (height, width) in [(100., 200.), (300., 200.), (50., 400.), (123., 77.)].iter() {
+            // Same width
+            let mut vp = original;
+            vp.resize(*height, *width, false);
+            assert_eq!(vp.center, original.center);
+            assert_eq!(vp.width, original.width);
+            assert_eq!((vp.canvas_height, vp.canvas_width), (*height, *width));
+
+            // Same area
+            let mut vp = original;
+            vp.resize(*height, *width, true);
+            assert_eq!(vp.center, original.center);
+            assert!((area(&vp) - area(&original)).abs() < 1e-3);
+            assert_eq!((vp.canvas_height, vp.canvas_width), (*height, *width));
+        }
+
+        // A canvas without size cannot keep any area
+        let mut vp = original;
+        vp.resize(0., 0., true);
+        assert_eq!(vp.width, original.width);
+        assert_eq!(vp.center, original.center);
+    }
+
+    #[test]
+    fn test_line_cap_and_join() {
+        assert_eq!(one_of("round", &LINE_CAPS), Ok("round".to_string()));
+        assert!(one_of("Round", &LINE_CAPS).is_err());
+        assert!(one_of("", &LINE_JOINS).is_err());
+
+        let mut drawer = Drawer2D::detached();
+        assert_eq!(drawer.line_cap, "butt");
+        assert_eq!(drawer.line_join, "miter");
+
+        assert!(drawer.set_line_cap("square").is_ok());
+        assert!(drawer.set_line_join("bevel").is_ok());
+        assert_eq!(drawer.line_cap, "square");
+        assert_eq!(drawer.line_join, "bevel");
+
+        // Invalid ones keep the current values
+        assert!(drawer.set_line_cap("bevel").is_err());
+        assert!(drawer.set_line_join("square").is_err());
+        assert_eq!(drawer.line_cap, "square");
+        assert_eq!(drawer.line_join, "bevel");
+    }
+
+    #[test]
+    fn test_camera() {
+        let viewport = |drawer: &Drawer2D| Viewport {
+            center: drawer.center,
+            width: drawer.width,
+            canvas_width: 300.,
+            canvas_height: 200.,
+        };
+        let points = [
+            Point2D::new(0., 0.),
+            Point2D::new(1.5, -2.),
+            Point2D::new(-40., 13.),
+        ];
+
+        let mut drawer = Drawer2D::detached();
+        drawer.set_center(3., -1.);
+        drawer.zoom(0.37);
+        let camera = drawer.camera();
+        let before: Vec<(Float, Float)> = points
+            .iter()
+            .map(|p| viewport(&drawer).as_canvas_point(p).0)
+            .map(|c| (c.x, c.y))
+            .collect();
+
+        // Somewhere else, and back
+        let mut other = Drawer2D::detached();
+        assert!(other.set_camera(camera).is_ok());
+        assert_eq!(other.camera(), camera);
+        drawer.reset_view();
+        assert!(drawer.set_camera(other.camera()).is_ok());
+        let after: Vec<(Float, Float)> = points
+            .iter()
+            .map(|p| viewport(&drawer).as_canvas_point(p).0)
+            .map(|c| (c.x, c.y))
+            .collect();
+        assert_eq!(before, after);
+
+        // Invalid widths keep the viewport
+        for width in [0., -1., Float::NAN, Float::INFINITY].iter() {
+            let bad = Camera2D::new(Point2D::new(100., 100.), *width);
+            assert!(drawer.set_camera(bad).is_err());
+            assert_eq!(drawer.camera(), camera);
+        }
+    }
 }
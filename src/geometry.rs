@@ -0,0 +1,277 @@
+use crate::Float;
+
+use wasm_bindgen::prelude::*;
+
+use crate::drawer2d::points_from_flat;
+use crate::point2d::Point2D;
+
+/// Calculates the area of the polygon whose vertices are `points`, in order,
+/// which is positive if they go counterclockwise and negative otherwise.
+///
+/// The polygon is closed (i.e., the last point connects to the first one),
+/// and it is expected not to cross itself. Less than three points
+/// have no area.
+pub fn polygon_signed_area(points: &[Point2D]) -> Float {
+    if points.len() < 3 {
+        return 0.;
+    }
+
+    // Shoelace formula
+    let mut twice_area = 0.;
+    for (i, a) in points.iter().enumerate() {
+        let b = &points[(i + 1) % points.len()];
+        twice_area += a.x * b.y - b.x * a.y;
+    }
+    twice_area / 2.
+}
+
+/// Calculates the area of the polygon whose vertices are `points`,
+/// in order, regardless of their direction (see `polygon_signed_area()`)
+pub fn polygon_area(points: &[Point2D]) -> Float {
+    polygon_signed_area(points).abs()
+}
+
+/// Calculates the area of a polygon like `polygon_area()`, taking its
+/// vertices as a flat list of coordinates (i.e., `[x0, y0, x1, y1, ...]`)
+#[wasm_bindgen]
+pub fn polygon_area_flat(coordinates: Vec<Float>) -> Float {
+    polygon_area(&points_from_flat(&coordinates))
+}
+
+/// Calculates the signed area of a polygon like `polygon_signed_area()`,
+/// taking its vertices as a flat list of coordinates
+#[wasm_bindgen]
+pub fn polygon_signed_area_flat(coordinates: Vec<Float>) -> Float {
+    polygon_signed_area(&points_from_flat(&coordinates))
+}
+
+/// Checks whether `p` is on the segment going from `a` to `b`
+fn is_on_segment(p: &Point2D, a: &Point2D, b: &Point2D) -> bool {
+    let cross = (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x);
+    cross == 0.
+        && p.x >= a.x.min(b.x)
+        && p.x <= a.x.max(b.x)
+        && p.y >= a.y.min(b.y)
+        && p.y <= a.y.max(b.y)
+}
+
+/// Checks whether `p` is inside the polygon whose vertices are `polygon`, in
+/// order, using the even-odd rule: a ray going from `p` towards the right
+/// crosses the edges of the polygon an odd number of times when `p` is inside.
+///
+/// Points exactly on an edge (or a vertex) are inside. Polygons of less than
+/// three vertices contain nothing.
+pub fn point_in_polygon(p: &Point2D, polygon: &[Point2D]) -> bool {
+    if polygon.len() < 3 {
+        return false;
+    }
+
+    let mut inside = false;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = &polygon[(i + 1) % polygon.len()];
+        if is_on_segment(p, a, b) {
+            return true;
+        }
+
+        // Edges going across the horizontal line through p, counting
+        // their lower vertex but not the upper one (so the rays through a
+        // vertex are counted once)
+        if (a.y > p.y) != (b.y > p.y) {
+            let x = a.x + (p.y - a.y) * (b.x - a.x) / (b.y - a.y);
+            if p.x < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Checks whether the point (`x`, `y`) is inside a polygon like `point_in_polygon()`,
+/// taking its vertices as a flat list of coordinates (i.e., `[x0, y0, x1, y1, ...]`)
+#[wasm_bindgen]
+pub fn point_in_polygon_flat(x: Float, y: Float, coordinates: Vec<Float>) -> bool {
+    point_in_polygon(&Point2D::new(x, y), &points_from_flat(&coordinates))
+}
+
+/// Calculates the distance from `p` to the closest point of the segment
+/// going from `a` to `b` (which might be one of its ends)
+pub(crate) fn distance_to_segment(p: &Point2D, a: &Point2D, b: &Point2D) -> Float {
+    let ab = b.sub(a);
+    let length2 = ab.x * ab.x + ab.y * ab.y;
+    if length2 == 0. {
+        return p.distance_to(a);
+    }
+    let ap = p.sub(a);
+    let t = ((ap.x * ab.x + ap.y * ab.y) / length2).clamp(0., 1.);
+    p.distance_to(&Point2D::lerp(a, b, t))
+}
+
+/// Simplifies the polyline going through `points` using the Ramer-Douglas-Peucker
+/// algorithm, returning the indexes of the points kept, in order.
+///
+/// The first and last points are always kept, and every point removed is within
+/// `epsilon` of the simplified polyline. An `epsilon` that is not positive
+/// keeps every point.
+pub fn simplify_polyline(points: &[Point2D], epsilon: Float) -> Vec<usize> {
+    let n = points.len();
+    if n < 3 || epsilon.is_nan() || epsilon <= 0. {
+        return (0..n).collect();
+    }
+
+    let mut keep = vec![false; n];
+    keep[0] = true;
+    keep[n - 1] = true;
+
+    // The spans yet to be simplified (instead of recursing, which
+    // could overflow the stack with long traces)
+    let mut pending = vec![(0, n - 1)];
+    while let Some((first, last)) = pending.pop() {
+        let (a, b) = (&points[first], &points[last]);
+        let mut farthest = None;
+        let mut max_distance = epsilon;
+        for (i, p) in points.iter().enumerate().take(last).skip(first + 1) {
+            let d = distance_to_segment(p, a, b);
+            if d > max_distance {
+                max_distance = d;
+                farthest = Some(i);
+            }
+        }
+        if let Some(i) = farthest {
+            keep[i] = true;
+            pending.push((first, i));
+            pending.push((i, last));
+        }
+    }
+
+    (0..n).filter(|i| keep[*i]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon(coordinates: &[(Float, Float)]) -> Vec<Point2D> {
+        coordinates
+            .iter()
+            .map(|(x, y)| Point2D::new(*x, *y))
+            .collect()
+    }
+
+    #[test]
+    fn test_polygon_area() {
+        let ccw_square = polygon(&[(0., 0.), (1., 0.), (1., 1.), (0., 1.)]);
+        assert_eq!(polygon_area(&ccw_square), 1.);
+        assert_eq!(polygon_signed_area(&ccw_square), 1.);
+
+        let cw_square = polygon(&[(0., 0.), (0., 1.), (1., 1.), (1., 0.)]);
+        assert_eq!(polygon_area(&cw_square), 1.);
+        assert_eq!(polygon_signed_area(&cw_square), -1.);
+
+        // Anywhere, starting anywhere
+        let triangle = polygon(&[(7., -2.), (3., 1.), (3., -2.)]);
+        assert_eq!(polygon_signed_area(&triangle), 6.);
+        let triangle = polygon(&[(3., -2.), (7., -2.), (3., 1.)]);
+        assert_eq!(polygon_signed_area(&triangle), 6.);
+
+        // An L shape
+        let l_shape = polygon(&[(0., 0.), (2., 0.), (2., 1.), (1., 1.), (1., 3.), (0., 3.)]);
+        assert_eq!(polygon_area(&l_shape), 4.);
+
+        // Degenerate
+        assert_eq!(polygon_area(&[]), 0.);
+        assert_eq!(polygon_area(&polygon(&[(1., 1.), (2., 3.)])), 0.);
+        assert_eq!(polygon_area(&polygon(&[(0., 0.), (1., 1.), (2., 2.)])), 0.);
+
+        // Flat
+        assert_eq!(polygon_area_flat(vec![0., 0., 0., 1., 1., 1., 1., 0.]), 1.);
+        assert_eq!(
+            polygon_signed_area_flat(vec![0., 0., 0., 1., 1., 1., 1., 0.]),
+            -1.
+        );
+        assert_eq!(polygon_area_flat(vec![0., 0., 1., 0., 1.]), 0.);
+    }
+
+    #[test]
+    fn test_point_in_polygon() {
+        let square = polygon(&[(0., 0.), (2., 0.), (2., 2.), (0., 2.)]);
+        let inside = |x: Float, y: Float| point_in_polygon(&Point2D::new(x, y), &square);
+
+        // Clearly inside
+        assert!(inside(1., 1.));
+        assert!(inside(0.1, 1.9));
+
+        // Clearly outside
+        assert!(!inside(3., 1.));
+        assert!(!inside(-1., 1.));
+        assert!(!inside(1., -0.5));
+        assert!(!inside(1., 2.5));
+
+        // On the vertices and the edges
+        for (x, y) in [(0., 0.), (2., 0.), (2., 2.), (0., 2.), (1., 0.), (2., 1.5)].iter() {
+            assert!(inside(*x, *y));
+        }
+
+        // Rays through a vertex
+        assert!(!inside(-1., 0.));
+        assert!(!inside(-1., 2.));
+        let diamond = polygon(&[(0., -1.), (1., 0.), (0., 1.), (-1., 0.)]);
+        assert!(point_in_polygon(&Point2D::new(0.5, 0.), &diamond));
+        assert!(!point_in_polygon(&Point2D::new(-2., 0.), &diamond));
+
+        // Concave
+        let l_shape = polygon(&[(0., 0.), (2., 0.), (2., 1.), (1., 1.), (1., 3.), (0., 3.)]);
+        assert!(point_in_polygon(&Point2D::new(0.5, 2.5), &l_shape));
+        assert!(!point_in_polygon(&Point2D::new(1.5, 2.5), &l_shape));
+
+        // Degenerate
+        let segment = polygon(&[(0., 0.), (2., 0.)]);
+        assert!(!point_in_polygon(&Point2D::new(1., 0.), &segment));
+
+        // Flat
+        let flat = vec![0., 0., 2., 0., 2., 2., 0., 2.];
+        assert!(point_in_polygon_flat(1., 1., flat.clone()));
+        assert!(!point_in_polygon_flat(3., 1., flat));
+    }
+
+    #[test]
+    fn test_distance_to_segment() {
+        let a = Point2D::new(0., 0.);
+        let b = Point2D::new(2., 0.);
+        assert_eq!(distance_to_segment(&Point2D::new(1., 1.), &a, &b), 1.);
+        assert_eq!(distance_to_segment(&Point2D::new(-3., 4.), &a, &b), 5.);
+        assert_eq!(distance_to_segment(&Point2D::new(5., -4.), &a, &b), 5.);
+        assert_eq!(distance_to_segment(&Point2D::new(3., 4.), &a, &a), 5.);
+    }
+
+    #[test]
+    fn test_simplify_polyline() {
+        // A straight line
+        let line: Vec<Point2D> = (0..50)
+            .map(|i| Point2D::new(i as Float, 0.5 * i as Float))
+            .collect();
+        assert_eq!(simplify_polyline(&line, 0.01), vec![0, 49]);
+
+        // An L shape, slightly noisy
+        let l_shape = polygon(&[
+            (0., 0.),
+            (1., 0.01),
+            (2., -0.01),
+            (3., 0.),
+            (3.01, 1.),
+            (2.99, 2.),
+            (3., 3.),
+        ]);
+        assert_eq!(simplify_polyline(&l_shape, 0.1), vec![0, 3, 6]);
+
+        // Small epsilons keep the noise
+        assert_eq!(simplify_polyline(&l_shape, 0.001).len(), 7);
+
+        // Nothing to simplify
+        let all: Vec<usize> = (0..7).collect();
+        assert_eq!(simplify_polyline(&l_shape, 0.), all);
+        assert_eq!(simplify_polyline(&l_shape, -1.), all);
+        assert_eq!(simplify_polyline(&l_shape, Float::NAN), all);
+        assert_eq!(simplify_polyline(&l_shape[..2], 10.), vec![0, 1]);
+        assert!(simplify_polyline(&[], 10.).is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::Float;
+
+use crate::point2d::Point2D;
+
+use std::collections::VecDeque;
+
+/// A change made to the points of a PointCloud2D, with enough
+/// information to undo it and to do it again.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Operation {
+    /// A point was pushed at the end, maybe with an ID
+    Push { point: Point2D, id: Option<u64> },
+
+    /// A point was removed, along with its ID and its connections
+    /// (with the indexes it had before the removal)
+    Remove {
+        index: usize,
+        point: Point2D,
+        id: Option<u64>,
+        edges: Vec<(usize, usize)>,
+    },
+
+    /// A point moved from one position to another
+    Update {
+        index: usize,
+        from: Point2D,
+        to: Point2D,
+    },
+
+    /// All the points moved by the same offset
+    TranslateAll { dx: Float, dy: Float },
+}
+
+/// The operations done on a PointCloud2D that can be undone, and
+/// those undone that can be done again.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct History {
+    /// How many operations are kept. Nothing is recorded when this is zero.
+    depth: usize,
+
+    /// The operations that can be undone, the latest last
+    done: VecDeque<Operation>,
+
+    /// The operations that can be redone, the latest undone last
+    undone: Vec<Operation>,
+}
+
+impl History {
+    /// Changes how many operations are kept, forgetting the
+    /// oldest ones if there are too many
+    pub(crate) fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        while self.done.len() > depth {
+            self.done.pop_front();
+        }
+        self.undone.truncate(depth);
+    }
+
+    /// Records a new operation (if recording), which means that
+    /// the ones undone cannot be redone anymore
+    pub(crate) fn record(&mut self, operation: Operation) {
+        if self.depth == 0 {
+            return;
+        }
+        self.undone.clear();
+        self.done.push_back(operation);
+        if self.done.len() > self.depth {
+            self.done.pop_front();
+        }
+    }
+
+    /// Forgets all the operations
+    pub(crate) fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+    }
+
+    /// Takes the latest operation done, to be undone
+    pub(crate) fn pop_done(&mut self) -> Option<Operation> {
+        self.done.pop_back()
+    }
+
+    /// Keeps an operation that was undone, so it can be redone
+    pub(crate) fn push_undone(&mut self, operation: Operation) {
+        self.undone.push(operation);
+    }
+
+    /// Takes the latest operation undone, to be redone
+    pub(crate) fn pop_undone(&mut self) -> Option<Operation> {
+        self.undone.pop()
+    }
+
+    /// Keeps an operation that was redone, so it can be undone again
+    /// (unlike `record()`, this keeps the rest of the undone ones)
+    pub(crate) fn push_done(&mut self, operation: Operation) {
+        self.done.push_back(operation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let op = |i: usize| Operation::Push {
+            point: Point2D::new(i as Float, 0.),
+            id: None,
+        };
+
+        // Not recording
+        let mut history = History::default();
+        history.record(op(0));
+        assert!(history.pop_done().is_none());
+
+        // Only the latest ones are kept
+        history.set_depth(3);
+        for i in 0..5 {
+            history.record(op(i));
+        }
+        assert_eq!(history.pop_done(), Some(op(4)));
+        history.push_undone(op(4));
+        assert_eq!(history.pop_done(), Some(op(3)));
+        history.push_undone(op(3));
+
+        // Redoing keeps the rest of the undone ones
+        assert_eq!(history.pop_undone(), Some(op(3)));
+        history.push_done(op(3));
+        assert_eq!(history.undone, vec![op(4)]);
+
+        // A new operation forgets them
+        history.record(op(5));
+        assert!(history.pop_undone().is_none());
+        assert_eq!(history.done, vec![op(2), op(3), op(5)]);
+
+        // Shrinking forgets the oldest ones
+        history.set_depth(1);
+        assert_eq!(history.done, vec![op(5)]);
+        history.clear();
+        assert!(history.pop_done().is_none());
+    }
+}
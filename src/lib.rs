@@ -1,5 +1,9 @@
 
 
+// When `Float` is `f64`, the `.into()` calls used to feed the (always `f64`)
+// canvas API are no-ops... they are needed for the `"float"` feature, though.
+#![cfg_attr(not(feature = "float"), allow(clippy::useless_conversion))]
+
 /// The kind of Floating point number used in the
 /// library... the `"float"` feature means it becomes `f32`
 /// and `f64` is used otherwise.
@@ -19,9 +23,13 @@ const PI : Float = std::f64::consts::PI;
 
 
 pub mod drawer2d;
+pub mod geometry;
 pub mod point2d;
 pub mod pointcloud2d;
+pub mod quadtree;
 pub mod tool_trait;
 pub mod toolbox;
 
+mod history;
+mod spatial_hash;
 mod utils;
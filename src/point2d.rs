@@ -1,11 +1,12 @@
 use crate::Float;
 
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
 /// Very simple structure: a 2D Point, with x and
 /// y components
 #[wasm_bindgen]
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Point2D {
     pub x: Float,
     pub y: Float,
@@ -34,6 +35,52 @@ impl Point2D {
     pub fn new(x: Float, y: Float) -> Self {
         Self { x, y }
     }
+
+    /// Calculates the distance to another point
+    ///
+    /// Use `squared_distance_to()` when only comparing distances, as it is cheaper
+    pub fn distance_to(&self, other: &Point2D) -> Float {
+        self.squared_distance_to(other).sqrt()
+    }
+
+    /// Checks whether both components are within `tol` of those of another point
+    pub fn approx_eq(&self, other: &Point2D, tol: Float) -> bool {
+        (self.x - other.x).abs() <= tol && (self.y - other.y).abs() <= tol
+    }
+
+    /// Adds the components of another point (e.g., a displacement)
+    pub fn add(&self, other: &Point2D) -> Point2D {
+        Self::new(self.x + other.x, self.y + other.y)
+    }
+
+    /// Subtracts the components of another point (e.g., to get
+    /// the direction from `other` to this one)
+    pub fn sub(&self, other: &Point2D) -> Point2D {
+        Self::new(self.x - other.x, self.y - other.y)
+    }
+
+    /// Multiplies both components by `factor`
+    pub fn scale(&self, factor: Float) -> Point2D {
+        Self::new(self.x * factor, self.y * factor)
+    }
+
+    /// Interpolates linearly between `a` (when `t` is 0) and `b` (when `t` is 1),
+    /// extrapolating for values of `t` outside of `[0, 1]`
+    pub fn lerp(a: &Point2D, b: &Point2D, t: Float) -> Point2D {
+        // Weighting both ends, rather than doing a + (b - a) * t, gives
+        // exactly `a` and `b` at the ends
+        Self::new(a.x * (1. - t) + b.x * t, a.y * (1. - t) + b.y * t)
+    }
+
+    /// Rotates the point counterclockwise by `radians` around `pivot`
+    pub fn rotated_around(&self, pivot: &Point2D, radians: Float) -> Point2D {
+        let (sin, cos) = radians.sin_cos();
+        let d = self.sub(pivot);
+        Self::new(
+            pivot.x + d.x * cos - d.y * sin,
+            pivot.y + d.x * sin + d.y * cos,
+        )
+    }
 }
 
 #[wasm_bindgen]
@@ -66,4 +113,100 @@ mod tests {
         let b = Point2D { x: 2., y: 0. };
         assert_eq!(a.squared_distance_to(&b), 4.0);
     }
+
+    #[test]
+    fn test_distance() {
+        let a = Point2D { x: 0., y: 0. };
+        let b = Point2D { x: 0., y: 0. };
+        assert_eq!(a.distance_to(&b), 0.0);
+
+        let b = Point2D { x: 0., y: 1. };
+        assert_eq!(a.distance_to(&b), 1.0);
+
+        let b = Point2D { x: 0., y: 2. };
+        assert_eq!(a.distance_to(&b), 2.0);
+
+        let b = Point2D { x: 1., y: 0. };
+        assert_eq!(a.distance_to(&b), 1.0);
+
+        let b = Point2D { x: 2., y: 0. };
+        assert_eq!(a.distance_to(&b), 2.0);
+
+        let b = Point2D { x: 3., y: -4. };
+        assert_eq!(a.distance_to(&b), 5.0);
+        assert_eq!(b.distance_to(&a), 5.0);
+    }
+
+    #[test]
+    fn test_vector_ops() {
+        let a = Point2D::new(1., 2.);
+        let b = Point2D::new(-3., 0.5);
+        assert_eq!(a.add(&b), Point2D::new(-2., 2.5));
+        assert_eq!(a.sub(&b), Point2D::new(4., 1.5));
+        assert_eq!(b.sub(&a), Point2D::new(-4., -1.5));
+        assert_eq!(a.scale(2.), Point2D::new(2., 4.));
+        assert_eq!(a.scale(0.), Point2D::new(0., 0.));
+
+        // Midpoint
+        assert_eq!(a.add(&b).scale(0.5), Point2D::new(-1., 1.25));
+    }
+
+    #[test]
+    fn test_rotated_around() {
+        use std::f64::consts::PI;
+        let pi = PI as Float;
+
+        let origin = Point2D::new(0., 0.);
+        let pivot = Point2D::new(2., 1.);
+        let p = Point2D::new(1., 0.);
+        let cases = [
+            (origin, pi / 2., (0., 1.)),
+            (origin, pi, (-1., 0.)),
+            (origin, -pi / 2., (0., -1.)),
+            (origin, 2. * pi, (1., 0.)),
+            (pivot, pi / 2., (3., 0.)),
+            (pivot, 2. * pi, (1., 0.)),
+        ];
+        for (around, radians, expected) in cases.iter() {
+            let rotated = p.rotated_around(around, *radians);
+            let expected = Point2D::new(expected.0, expected.1);
+            assert!(rotated.distance_to(&expected) < 1e-5);
+        }
+
+        // The pivot does not move
+        assert_eq!(pivot.rotated_around(&pivot, 1.), pivot);
+    }
+
+    #[test]
+    fn test_lerp() {
+        let a = Point2D::new(0.1, -3.7);
+        let b = Point2D::new(2.3, 1e3);
+
+        // Exactly at the ends
+        assert_eq!(Point2D::lerp(&a, &b, 0.), a);
+        assert_eq!(Point2D::lerp(&a, &b, 1.), b);
+
+        // Midpoint
+        let a = Point2D::new(1., 2.);
+        let b = Point2D::new(3., -2.);
+        assert_eq!(Point2D::lerp(&a, &b, 0.5), Point2D::new(2., 0.));
+
+        // Beyond the ends
+        assert_eq!(Point2D::lerp(&a, &b, 2.), Point2D::new(5., -6.));
+        assert_eq!(Point2D::lerp(&a, &b, -1.), Point2D::new(-1., 6.));
+    }
+
+    #[test]
+    fn test_approx_eq() {
+        let a = Point2D::new(0., 0.);
+        let b = Point2D::new(1e-9, 0.); // a nanometer away
+        assert!(a.approx_eq(&b, 1e-3));
+        assert!(!a.approx_eq(&b, 1e-12));
+
+        let c = Point2D::new(0., -1e-9);
+        assert!(a.approx_eq(&c, 1e-3));
+        assert!(!a.approx_eq(&c, 1e-12));
+
+        assert!(a.approx_eq(&a, 0.));
+    }
 }
@@ -2,8 +2,267 @@ use crate::Float;
 
 use wasm_bindgen::prelude::*;
 
-use crate::drawer2d::Drawer2D;
-use crate::point2d::Point2D;
+use crate::drawer2d::{snap_to_grid, Drawer2D, Viewport};
+use crate::geometry::{distance_to_segment, point_in_polygon};
+use crate::history::{History, Operation};
+use crate::point2d::{CanvasPoint2D, Point2D};
+use crate::quadtree::QuadNode;
+use crate::spatial_hash::SpatialHash;
+
+use serde::{Deserialize, Serialize};
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+/// The largest number of cells `occupancy_grid()` allocates (i.e., 16 MB)
+const MAX_GRID_CELLS: usize = 1 << 24;
+
+/// How close (in meters) a point needs to be for `test_world_point()` to match it
+const MAX_DISTANCE: Float = 0.25;
+
+/// What `to_json()` saves of a PointCloud2D. The rest (e.g., the
+/// sorting) is rebuilt by `from_json()`.
+#[derive(Serialize, Deserialize)]
+struct SavedPointCloud2D<'a> {
+    points: Cow<'a, [Point2D]>,
+    is_sorted: bool,
+
+    /// The connections, in any order and maybe repeated (e.g., when
+    /// written by other tools). Older files have none.
+    #[serde(default)]
+    edges: Cow<'a, [(usize, usize)]>,
+
+    /// The IDs of the points (see `push_with_id()`). Older files have
+    /// none, so their points have no IDs either.
+    #[serde(default)]
+    ids: Cow<'a, [Option<u64>]>,
+}
+
+/// Compares two coordinates using a total ordering, so that sorting
+/// and searching are deterministic for every value.
+///
+/// The exception is `-0.0`, which is equal to `+0.0` (as it is numerically),
+/// so that the queries on sorted clouds find the same points as on unsorted
+/// ones (e.g., a point at `-0.0` is within a rectangle starting at `0.0`).
+///
+/// This also means that NaN coordinates never cause a panic: a (positive) NaN
+/// goes after every other value, and a negative one before them all. Such
+/// points are kept, but they are never within any distance or rectangle.
+fn compare_coordinates(a: Float, b: Float) -> Ordering {
+    let unsigned_zero = |v: Float| if v == 0. { 0. } else { v };
+    unsigned_zero(a).total_cmp(&unsigned_zero(b))
+}
+
+/// Projects `p` perpendicularly onto the (infinite) line going through `a`
+/// and `b`, which might land beyond them. If `a` and `b` are coincident,
+/// the projection is `a`.
+fn project_onto_line(p: &Point2D, a: &Point2D, b: &Point2D) -> Point2D {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length2 = dx * dx + dy * dy;
+    if length2 == 0. {
+        return *a;
+    }
+    let t = ((p.x - a.x) * dx + (p.y - a.y) * dy) / length2;
+    Point2D::new(a.x + t * dx, a.y + t * dy)
+}
+
+/// Finds the point where the segment going from `a` to `b` crosses the one
+/// going from `c` to `d` (ends included), if any.
+///
+/// Parallel segments never cross, even if they are collinear and overlap
+/// (as they have no single crossing point).
+fn segment_intersection(a: &Point2D, b: &Point2D, c: &Point2D, d: &Point2D) -> Option<Point2D> {
+    let (abx, aby) = (b.x - a.x, b.y - a.y);
+    let (cdx, cdy) = (d.x - c.x, d.y - c.y);
+    let denominator = abx * cdy - aby * cdx;
+    if denominator == 0. {
+        return None;
+    }
+
+    // Where along each of them they cross
+    let (acx, acy) = (c.x - a.x, c.y - a.y);
+    let t = (acx * cdy - acy * cdx) / denominator;
+    let u = (acx * aby - acy * abx) / denominator;
+    if (0. ..=1.).contains(&t) && (0. ..=1.).contains(&u) {
+        Some(Point2D::new(a.x + t * abx, a.y + t * aby))
+    } else {
+        None
+    }
+}
+
+/// How the connections between points are drawn
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeStyle {
+    /// A straight line between the points
+    Straight,
+
+    /// A right-angle connector, going horizontally from the first point
+    /// and then vertically to the second one (see `elbow()`)
+    Orthogonal,
+}
+
+/// Finds the corner of the orthogonal connector going from `a` to `b`
+/// (i.e., horizontally first, and then vertically)
+fn elbow(a: &Point2D, b: &Point2D) -> Point2D {
+    Point2D::new(b.x, a.y)
+}
+
+/// Checks whether the segment going from `a` to `b` crosses the `[min, max]`
+/// rectangle (borders included), even if both ends are out of it
+fn segment_crosses_rect(a: &Point2D, b: &Point2D, min: &Point2D, max: &Point2D) -> bool {
+    // Their bounding boxes overlap...
+    if a.x.max(b.x) < min.x || a.x.min(b.x) > max.x || a.y.max(b.y) < min.y || a.y.min(b.y) > max.y
+    {
+        return false;
+    }
+
+    // ... and the rectangle is not entirely on one side of the line
+    let corners = [
+        *min,
+        Point2D::new(max.x, min.y),
+        *max,
+        Point2D::new(min.x, max.y),
+    ];
+    let side = |c: &Point2D| (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+    !(corners.iter().all(|c| side(c) > 0.) || corners.iter().all(|c| side(c) < 0.))
+}
+
+/// Calculates the average position of some `points`, or `None` if there are none
+fn average<'a>(points: impl Iterator<Item = &'a Point2D>) -> Option<Point2D> {
+    // Accumulate in f64, even if Float is f32
+    let (mut x, mut y, mut n) = (0f64, 0f64, 0usize);
+    for p in points {
+        x += f64::from(p.x);
+        y += f64::from(p.y);
+        n += 1;
+    }
+    if n == 0 {
+        return None;
+    }
+    let n = n as f64;
+    Some(Point2D::new((x / n) as Float, (y / n) as Float))
+}
+
+/// Merges the sorted indexes `a` and `b` (whose indexes are shifted up
+/// by `offset`), comparing the coordinates given by `coordinate`.
+///
+/// Ties go to `a`, so this is the same as inserting the indexes in
+/// `b` after those in `a`, one by one.
+fn merge_sorted(
+    a: &[usize],
+    b: &[usize],
+    offset: usize,
+    coordinate: impl Fn(usize) -> Float,
+) -> Vec<usize> {
+    let mut ret = Vec::with_capacity(a.len() + b.len());
+    let mut b = b.iter().map(|i| i + offset).peekable();
+    for i in a.iter() {
+        let current = coordinate(*i);
+        while let Some(j) = b.next_if(|j| compare_coordinates(coordinate(*j), current).is_lt()) {
+            ret.push(j);
+        }
+        ret.push(*i);
+    }
+    ret.extend(b);
+    ret
+}
+
+/// Calculates the opacity of a flash that started at `start_ms` and lasts
+/// `duration_ms`, at time `now_ms`. It goes from 1 (i.e., just started)
+/// to 0 (i.e., finished).
+fn flash_opacity(start_ms: f64, duration_ms: f64, now_ms: f64) -> f64 {
+    if duration_ms <= 0. {
+        return 0.;
+    }
+    let t = ((now_ms - start_ms) / duration_ms).clamp(0., 1.);
+    1. - t
+}
+
+/// Interpolates the uniform Catmull-Rom spline segment going from `p1` (when
+/// `t` is 0) to `p2` (when `t` is 1), with `p0` and `p3` as the points
+/// before and after them
+fn catmull_rom(p0: &Point2D, p1: &Point2D, p2: &Point2D, p3: &Point2D, t: Float) -> Point2D {
+    let (t2, t3) = (t * t, t * t * t);
+    let at = |c0: Float, c1: Float, c2: Float, c3: Float| {
+        0.5 * (2. * c1
+            + (c2 - c0) * t
+            + (2. * c0 - 5. * c1 + 4. * c2 - c3) * t2
+            + (3. * c1 - c0 - 3. * c2 + c3) * t3)
+    };
+    Point2D::new(at(p0.x, p1.x, p2.x, p3.x), at(p0.y, p1.y, p2.y, p3.y))
+}
+
+/// Calculates the angle (in radians, from 0 to `π`) at `p` between
+/// the directions towards `a` and `b`
+fn angle_at(p: &Point2D, a: &Point2D, b: &Point2D) -> Float {
+    let (u, w) = (a.sub(p), b.sub(p));
+    (u.x * w.y - u.y * w.x).atan2(u.x * w.x + u.y * w.y).abs()
+}
+
+/// How the points are drawn
+#[derive(Clone, Debug, PartialEq)]
+struct PointStyle {
+    /// Radius of the dots, in pixels
+    radius: f64,
+
+    /// CSS colour (or style) of the inside of the dots
+    fill_style: String,
+
+    /// CSS colour (or style) of the outline of the dots
+    stroke_style: String,
+
+    /// Width of the outline of the dots, in pixels
+    line_width: f64,
+}
+
+impl PointStyle {
+    /// The style of regular points: green dots
+    fn regular() -> Self {
+        Self {
+            radius: 5.,
+            fill_style: "green".to_string(),
+            stroke_style: "#003300".to_string(),
+            line_width: 3.,
+        }
+    }
+
+    /// The style of highlighted points: larger red dots
+    fn highlighted() -> Self {
+        Self {
+            radius: 8.,
+            fill_style: "red".to_string(),
+            stroke_style: "#330000".to_string(),
+            line_width: 3.,
+        }
+    }
+
+    /// Draws a dot in the canvas
+    fn draw_dot(&self, context: &web_sys::CanvasRenderingContext2d, p: &CanvasPoint2D) {
+        context.begin_path();
+        context
+            .arc(
+                p.x.into(),
+                p.y.into(),
+                self.radius,
+                0.,
+                2.0 * std::f64::consts::PI,
+            )
+            .unwrap();
+
+        context.set_fill_style_str(&self.fill_style);
+        context.fill();
+
+        context.set_line_width(self.line_width);
+        context.set_stroke_style_str(&self.stroke_style);
+        context.stroke();
+    }
+}
 
 /// A Point2D collection that allows organizing them
 /// and connecting them.
@@ -24,8 +283,42 @@ pub struct PointCloud2D {
     /// The indexes of the points, sorted in the Y axis
     sorted_y: Vec<usize>,
 
+    /// Optional stable IDs of the points, aligned with `points`
+    ids: Vec<Option<u64>>,
+
     /// Do we care about sorting points?
     is_sorted: bool,
+
+    /// The connections between points, as pairs of indexes with
+    /// the smallest one first. There are no duplicates.
+    edges: Vec<(usize, usize)>,
+
+    /// How the connections are drawn
+    edge_style: EdgeStyle,
+
+    /// The point being flashed, if any, as (index, start, duration), with
+    /// the times in milliseconds
+    flash: Option<(usize, f64, f64)>,
+
+    /// How the points are drawn
+    style: PointStyle,
+
+    /// How the highlighted points are drawn
+    highlight_style: PointStyle,
+
+    /// How far a point needs to go for `update_point()` to move it
+    update_tolerance: Float,
+
+    /// The size of the cells of the spatial hash, if one is used
+    /// for finding the points close to another one
+    spatial_hash_cell_size: Option<Float>,
+
+    /// The spatial hash of the points, built when needed and
+    /// dropped whenever they change
+    spatial_hash: RefCell<Option<SpatialHash>>,
+
+    /// The changes to the points that can be undone and redone
+    history: History,
 }
 
 impl PointCloud2D {
@@ -37,7 +330,17 @@ impl PointCloud2D {
             positions_y: Vec::with_capacity(n),
             sorted_x: Vec::with_capacity(n),
             sorted_y: Vec::with_capacity(n),
+            ids: Vec::with_capacity(n),
             is_sorted: true,
+            edges: Vec::new(),
+            edge_style: EdgeStyle::Straight,
+            flash: None,
+            style: PointStyle::regular(),
+            highlight_style: PointStyle::highlighted(),
+            update_tolerance: Float::EPSILON,
+            spatial_hash_cell_size: None,
+            spatial_hash: RefCell::new(None),
+            history: History::default(),
         }
     }
 
@@ -49,8 +352,124 @@ impl PointCloud2D {
             positions_y: Vec::with_capacity(n),
             sorted_x: Vec::with_capacity(n),
             sorted_y: Vec::with_capacity(n),
+            ids: Vec::with_capacity(n),
             is_sorted: false,
+            edges: Vec::new(),
+            edge_style: EdgeStyle::Straight,
+            flash: None,
+            style: PointStyle::regular(),
+            highlight_style: PointStyle::highlighted(),
+            update_tolerance: Float::EPSILON,
+            spatial_hash_cell_size: None,
+            spatial_hash: RefCell::new(None),
+            history: History::default(),
+        }
+    }
+
+    /// Builds `sorted_x`, `sorted_y`, `positions_x` and `positions_y`
+    /// from scratch.
+    ///
+    /// Points with equal coordinates keep their index order, just like
+    /// when pushing them.
+    fn rebuild_sorting(&mut self) {
+        let points = &self.points;
+        let mut sorted_x: Vec<usize> = (0..points.len()).collect();
+        sorted_x.sort_by(|a, b| compare_coordinates(points[*a].x, points[*b].x));
+        let mut sorted_y: Vec<usize> = (0..points.len()).collect();
+        sorted_y.sort_by(|a, b| compare_coordinates(points[*a].y, points[*b].y));
+
+        self.positions_x = vec![0; points.len()];
+        for (position, index) in sorted_x.iter().enumerate() {
+            self.positions_x[*index] = position;
+        }
+        self.positions_y = vec![0; points.len()];
+        for (position, index) in sorted_y.iter().enumerate() {
+            self.positions_y[*index] = position;
+        }
+        self.sorted_x = sorted_x;
+        self.sorted_y = sorted_y;
+    }
+
+    /// Creates a sorted PointCloud2D containing `points`, in the same
+    /// order (i.e., the same as pushing them one by one).
+    ///
+    /// This sorts them all at once, which is much faster than pushing.
+    pub fn from_points(points: Vec<Point2D>) -> Self {
+        let n = points.len();
+        let mut ret = Self {
+            points,
+            ids: vec![None; n],
+            ..Self::with_capacity(n)
+        };
+        ret.rebuild_sorting();
+
+        #[cfg(debug_assertions)]
+        ret.check_consistency();
+
+        ret
+    }
+
+    /// Splits the cloud in two halves at the median X: the points in the
+    /// first half of `sorted_x` go to the left cloud, and the rest to the right
+    /// one (which gets the extra point when the count is odd).
+    ///
+    /// Both clouds are sorted, and points keep their relative order and IDs.
+    /// Connections within each half are kept, while those spanning both
+    /// halves are dropped.
+    pub fn split_at_median_x(mut self) -> (PointCloud2D, PointCloud2D) {
+        self.sort();
+        let n = self.points.len();
+
+        // Which half each point goes to, and its index there
+        let mut is_left = vec![false; n];
+        for i in self.sorted_x[..n / 2].iter() {
+            is_left[*i] = true;
         }
+        let mut new_index = vec![0; n];
+        let (mut n_left, mut n_right) = (0, 0);
+        for i in 0..n {
+            if is_left[i] {
+                new_index[i] = n_left;
+                n_left += 1;
+            } else {
+                new_index[i] = n_right;
+                n_right += 1;
+            }
+        }
+
+        let build = |left: bool| {
+            let points = (0..n)
+                .filter(|i| is_left[*i] == left)
+                .map(|i| self.points[i])
+                .collect();
+            let mut half = PointCloud2D::from_points(points);
+            half.ids = (0..n)
+                .filter(|i| is_left[*i] == left)
+                .map(|i| self.ids[i])
+                .collect();
+            half.edges = self
+                .edges
+                .iter()
+                .filter(|(a, b)| is_left[*a] == left && is_left[*b] == left)
+                .map(|(a, b)| (new_index[*a], new_index[*b]))
+                .collect();
+            half
+        };
+        (build(true), build(false))
+    }
+
+    /// Builds a quadtree over the bounds of the cloud, subdividing them until
+    /// each leaf holds at most `max_points_per_leaf` points (or until they
+    /// cannot be reasonably split, when many points are coincident).
+    ///
+    /// The tree refers to the points by index, so it needs to be rebuilt
+    /// after modifying the cloud.
+    pub fn build_quadtree(&self, max_points_per_leaf: usize) -> QuadNode {
+        let (min, max) = self
+            .bounds()
+            .unwrap_or((Point2D::new(0., 0.), Point2D::new(0., 0.)));
+        let indexes = (0..self.points.len()).collect();
+        QuadNode::build(&self.points, indexes, min, max, max_points_per_leaf)
     }
 
     /// Borrows the points
@@ -58,6 +477,12 @@ impl PointCloud2D {
         &self.points
     }
 
+    /// Borrows the connections between points, as pairs of
+    /// indexes with the smallest one first
+    pub fn connections(&self) -> &[(usize, usize)] {
+        &self.edges
+    }
+
     /// Finds the position that new point would have in the
     /// sorted_x.
     ///
@@ -68,16 +493,11 @@ impl PointCloud2D {
             return Err("Cannont find_position_x in unsorted PointCloud2D".to_string());
         }
 
-        let found = self.sorted_x.binary_search_by(|i| {
-            self.points[*i]
-                .x
-                .partial_cmp(&new_x)
-                .expect("could not compare!")
-        });
-        match found {
-            Ok(i) => Ok(i + 1), // It was there... return the index of the following element
-            Err(i) => Ok(i),    // It was not there... return the index
-        }
+        // After all the points that are not greater, so that points in
+        // the same position keep the order in which they were added
+        Ok(self.sorted_x.partition_point(|i| {
+            compare_coordinates(self.points[*i].x, new_x) != Ordering::Greater
+        }))
     }
 
     /// Finds the position that new point would have in the
@@ -90,761 +510,5378 @@ impl PointCloud2D {
             return Err("Cannont find_position_y in unsorted PointCloud2D".to_string());
         }
 
-        let found = self.sorted_y.binary_search_by(|i| {
-            self.points[*i]
-                .y
-                .partial_cmp(&new_y)
-                .expect("could not compare!")
-        });
-        match found {
-            Ok(i) => Ok(i + 1), // It was there... return the index of the following element
-            Err(i) => Ok(i),    // It was not there... return the index
+        // After all the points that are not greater, so that points in
+        // the same position keep the order in which they were added
+        Ok(self.sorted_y.partition_point(|i| {
+            compare_coordinates(self.points[*i].y, new_y) != Ordering::Greater
+        }))
+    }
+
+    /// Finds the positions in `edges` of the connections that cross the visible
+    /// World rectangle of the `viewport` (even if both their ends are out of it),
+    /// following their route when drawn as `EdgeStyle::Orthogonal`.
+    ///
+    /// Like in `visible_points()`, the rectangle is one pixel larger on each side.
+    fn visible_edges(&self, viewport: &Viewport) -> Vec<usize> {
+        let margin = 1. / viewport.pixels_per_meter();
+        let (min, max) = viewport.world_rect();
+        let min = Point2D::new(min.x - margin, min.y - margin);
+        let max = Point2D::new(max.x + margin, max.y + margin);
+
+        (0..self.edges.len())
+            .filter(|i| {
+                let (a, b) = self.edges[*i];
+                let (a, b) = (&self.points[a], &self.points[b]);
+                match self.edge_style {
+                    EdgeStyle::Straight => segment_crosses_rect(a, b, &min, &max),
+                    EdgeStyle::Orthogonal => {
+                        let corner = elbow(a, b);
+                        segment_crosses_rect(a, &corner, &min, &max)
+                            || segment_crosses_rect(&corner, b, &min, &max)
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Adds the connection between the points `a` and `b` to the current path
+    /// of the `context`, as set by `set_edge_style()`
+    fn trace_edge(
+        &self,
+        context: &web_sys::CanvasRenderingContext2d,
+        viewport: &Viewport,
+        a: &Point2D,
+        b: &Point2D,
+    ) {
+        let (canvas_a, _) = viewport.as_canvas_point(a);
+        let (canvas_b, _) = viewport.as_canvas_point(b);
+        context.move_to(canvas_a.x.into(), canvas_a.y.into());
+        if self.edge_style == EdgeStyle::Orthogonal {
+            let (corner, _) = viewport.as_canvas_point(&elbow(a, b));
+            context.line_to(corner.x.into(), corner.y.into());
         }
+        context.line_to(canvas_b.x.into(), canvas_b.y.into());
     }
 
-    /// Checks whether the structure is coherent
-    #[cfg(debug_assertions)]
-    fn check_consistency(&self) {
+    /// Finds the range of positions in `sorted_x` of the points
+    /// whose X component is within `[min, max]`
+    fn window_x(&self, min: Float, max: Float) -> Range<usize> {
+        let start = self
+            .sorted_x
+            .partition_point(|i| compare_coordinates(self.points[*i].x, min) == Ordering::Less);
+        let end = self
+            .sorted_x
+            .partition_point(|i| compare_coordinates(self.points[*i].x, max) != Ordering::Greater);
+        start..end.max(start)
+    }
+
+    /// Finds the range of positions in `sorted_y` of the points
+    /// whose Y component is within `[min, max]`
+    fn window_y(&self, min: Float, max: Float) -> Range<usize> {
+        let start = self
+            .sorted_y
+            .partition_point(|i| compare_coordinates(self.points[*i].y, min) == Ordering::Less);
+        let end = self
+            .sorted_y
+            .partition_point(|i| compare_coordinates(self.points[*i].y, max) != Ordering::Greater);
+        start..end.max(start)
+    }
+
+    /// Iterates the indexes of the points within the `[min, max]` rectangle
+    ///
+    /// When sorted, only the points within the narrowest of the X or Y
+    /// windows are checked.
+    fn iter_in_rect<'a>(
+        &'a self,
+        min: &'a Point2D,
+        max: &'a Point2D,
+    ) -> Box<dyn Iterator<Item = usize> + 'a> {
+        let is_inside = move |i: &usize| {
+            let p = &self.points[*i];
+            p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y
+        };
+
         if !self.is_sorted {
-            // nothing to check
-            return;
+            return Box::new((0..self.points.len()).filter(is_inside));
         }
 
-        // Lengths of the structure
-        debug_assert_eq!(self.points.len(), self.positions_x.len());
-        debug_assert_eq!(self.positions_x.len(), self.positions_y.len());
-        debug_assert_eq!(self.positions_y.len(), self.sorted_x.len());
-        debug_assert_eq!(self.sorted_x.len(), self.sorted_y.len());
+        let window_x = self.window_x(min.x, max.x);
+        let window_y = self.window_y(min.y, max.y);
+        let (window, sorted) = if window_x.len() <= window_y.len() {
+            (window_x, &self.sorted_x)
+        } else {
+            (window_y, &self.sorted_y)
+        };
+        Box::new(sorted[window].iter().copied().filter(is_inside))
+    }
 
-        // Ensure that all positions are there
-        for i in 0..self.points.len() {
-            assert!(self.positions_x.contains(&i));
-            assert!(self.positions_y.contains(&i));
-            assert!(self.sorted_x.contains(&i));
-            assert!(self.sorted_y.contains(&i));
+    /// Finds the points that are visible in the `viewport` (i.e., those that
+    /// `Viewport::as_canvas_point()` says are visible), sorted by index.
+    ///
+    /// When sorted, only the points within the visible World rectangle are
+    /// checked. That rectangle is one pixel larger on each side, so that
+    /// rounding does not leave out points right on the border of the canvas.
+    fn visible_points(&self, viewport: &Viewport) -> Vec<usize> {
+        if !self.is_sorted {
+            return (0..self.points.len())
+                .filter(|i| viewport.as_canvas_point(&self.points[*i]).1)
+                .collect();
         }
 
-        // indexes and positions in X direction
-        for i in 0..self.sorted_x.len() {
-            let index = self.sorted_x[i];
-            let current = self.points[index];
-            debug_assert_eq!(self.positions_x[index], i);
+        let margin = 1. / viewport.pixels_per_meter();
+        let (min, max) = viewport.world_rect();
+        let min = Point2D::new(min.x - margin, min.y - margin);
+        let max = Point2D::new(max.x + margin, max.y + margin);
+        let mut ret: Vec<usize> = self
+            .iter_in_rect(&min, &max)
+            .filter(|i| viewport.as_canvas_point(&self.points[*i]).1)
+            .collect();
+        // Drawn in the same order as the points (e.g., overlapping
+        // ones look the same as when drawing them all)
+        ret.sort_unstable();
+        ret
+    }
 
-            // If not first, check previous
-            if i >= 1 {
-                let prev_index = self.sorted_x[i - 1];
-                let prev = self.points[prev_index];
-                if prev.x > current.x {
-                    panic!("not true: prev.x [index:{}, position:{}, x:{:.6}] <= current.x [index:{}, position:{}, x:{:.6}]",prev_index, i-1,prev.x, index, i ,current.x);
-                }
-            }
+    /// Counts the points within the `[min, max]` rectangle
+    fn count_in_rect(&self, min: &Point2D, max: &Point2D) -> usize {
+        self.iter_in_rect(min, max).count()
+    }
 
-            // if not last, check next
-            if i + 1 < self.sorted_x.len() {
-                let next_index = self.sorted_x[i + 1];
-                let next = self.points[next_index];
-                if next.x < current.x {
-                    panic!("not true: next.x [index:{}, position:{}, x:{:.6}] >= current.x [index:{}, position:{}, x:{:.6}]",next_index, i+1, next.x, index, i, current.x);
-                }
-            }
+    /// Finds the points that might be within `max_distance` of `p`.
+    ///
+    /// The way this works is as follows:
+    /// 1. Find the points that might be close enough (i.e., within the p +- max_distance square)
+    /// 2. Check which direction contains less points (i.e., X or Y)
+    ///
+    /// Returns the range of candidate positions and the sorted
+    /// indexes (i.e., `sorted_x` or `sorted_y`) those positions refer to.
+    fn candidate_window(&self, p: &Point2D, max_distance: Float) -> (Range<usize>, &[usize]) {
+        // 1. Find the points that might be close enough (i.e., within the p +- max_distance square)
+        // Points outside of this rectangle cannot be "close enough"
+        let window_x = self.window_x(p.x - max_distance, p.x + max_distance);
+        let window_y = self.window_y(p.y - max_distance, p.y + max_distance);
+
+        // 2. Check which direction contains less points (i.e., X or Y)
+        if window_x.len() <= window_y.len() {
+            // there are less points to test in the X axis... iterate them all
+            (window_x, &self.sorted_x)
+        } else {
+            // there are less points to test in the Y axis
+            (window_y, &self.sorted_y)
         }
+    }
 
-        // indexes and positions in Y direction
-        for i in 0..self.sorted_y.len() {
-            let index = self.sorted_y[i];
-            let current = self.points[index];
-            debug_assert_eq!(self.positions_y[index], i);
+    /// Iterates the indexes of the points that might be within `max_distance`
+    /// of `p` (and some others), or none if `max_distance` is negative or NaN.
+    ///
+    /// Sorted clouds only check the points around `p`, as do those with a
+    /// spatial hash, while unsorted ones check them all.
+    fn candidates(&self, p: &Point2D, max_distance: Float) -> Box<dyn Iterator<Item = usize> + '_> {
+        if max_distance.is_nan() || max_distance < 0. {
+            return Box::new(std::iter::empty());
+        }
 
-            // If not first, check previous
-            if i >= 1 {
-                let prev_index = self.sorted_y[i - 1];
-                let prev = self.points[prev_index];
-                if prev.y > current.y {
-                    panic!("not true: prev.y [index:{}] <= current.y [index:{}] | next.y = {}, current.y = {}",prev_index, index, prev.y, current.y);
-                }
+        // 1. and 2. Find the candidates, in the direction that contains less points...
+        // or around p, if there is a spatial hash
+        match self.spatial_hash_cell_size {
+            Some(cell_size) => {
+                let mut hash = self.spatial_hash.borrow_mut();
+                let hash = hash.get_or_insert_with(|| SpatialHash::new(&self.points, cell_size));
+                Box::new(hash.candidates(p, max_distance).into_iter())
             }
-
-            // if not last, check next
-            if i + 1 < self.sorted_y.len() {
-                let next_index = self.sorted_y[i + 1];
-                let next = self.points[next_index];
-                if next.y < current.y {
-                    panic!("not true: next.y [index:{}] >= current.y [index:{}] | next.y = {}, current.y = {}",next_index, index, next.y, current.y);
-                }
+            None if !self.is_sorted => Box::new(0..self.points.len()),
+            None => {
+                let (candidate_point_positions, sorted) = self.candidate_window(p, max_distance);
+                Box::new(candidate_point_positions.map(move |position| sorted[position]))
             }
         }
     }
-}
 
-#[wasm_bindgen]
-impl PointCloud2D {
-    /// Creates a new empty PointCloud2D
-    #[wasm_bindgen(constructor)]
-    pub fn new() -> Self {
-        Self {
-            points: Vec::new(),
-            positions_x: Vec::new(),
-            positions_y: Vec::new(),
-            sorted_x: Vec::new(),
-            sorted_y: Vec::new(),
-            is_sorted: true,
-        }
+    /// Finds the closest point closer than `max_distance` to `p` (or coincident
+    /// with it), returning its index and its squared distance to `p`.
+    ///
+    /// Only the `candidates()` are checked.
+    fn nearest_within(&self, p: &Point2D, max_distance: Float) -> Option<(usize, Float)> {
+        self.nearest_of(p, self.candidates(p, max_distance), max_distance)
     }
 
-    /// Creates a new empty PointCloud2D
-    #[wasm_bindgen]
-    pub fn new_unsorted() -> Self {
-        Self {
-            points: Vec::new(),
-            positions_x: Vec::new(),
-            positions_y: Vec::new(),
-            sorted_x: Vec::new(),
-            sorted_y: Vec::new(),
-            is_sorted: false,
+    /// Finds, among the `candidates`, the closest point closer than `max_distance`
+    /// to `p` (or coincident with it), returning its index and its squared
+    /// distance to `p`.
+    fn nearest_of(
+        &self,
+        p: &Point2D,
+        candidates: impl Iterator<Item = usize>,
+        max_distance: Float,
+    ) -> Option<(usize, Float)> {
+        if max_distance.is_nan() || max_distance < 0. {
+            return None;
         }
-    }
+        let max_squared_distance = max_distance * max_distance;
 
-    /// Cleans the canvas and then redraws
-    pub fn redraw(&self, drawer: &Drawer2D) {
-        drawer.clear();
-        self.draw(drawer)
-    }
+        // initialize return
+        let mut ret: Option<(usize, Float)> = None;
+        let mut min_squared_distance = max_squared_distance;
 
-    /// Draws the Cloud
-    pub fn draw(&self, drawer: &Drawer2D) {
-        const RADIUS: Float = 5.;
-        let context = drawer.context();
+        // 3. Iterate the candidate points, checking the distance. If smallest so far, mark for return.
+        // Coincident points always match, so that a `max_distance` of zero can find them.
+        for other_index in candidates {
+            // Get the point
+            let other_p = &self.points[other_index];
+            // Check distance... would this be really more efficient if searched in squares as opposed to circles?
+            // That is, instead of calculating the ACTUAL square distance, to calculate the
+            // vertical/horizontal distance?
+            let sq_d = p.squared_distance_to(other_p);
+            if sq_d < min_squared_distance || (sq_d == 0. && ret.is_none()) {
+                ret = Some((other_index, sq_d));
+                min_squared_distance = sq_d;
+            }
+        }
 
-        for p in &self.points {
-            let (canvas_p, is_visible) = drawer.as_canvas_point(p);
-            if is_visible {
-                context.begin_path();
-                context
-                    .arc(
-                        canvas_p.x.into(),
-                        canvas_p.y.into(),
-                        RADIUS.into(),
-                        0.,
-                        2.0 * std::f64::consts::PI,
-                    )
-                    .unwrap();
+        // return
+        ret
+    }
 
-                let fill_style = wasm_bindgen::JsValue::from_str("green");
-                context.set_fill_style(&fill_style);
-                context.fill();
+    /// Like `test_world_point()`, but also returning the distance (in meters)
+    /// from `p` to the matched point.
+    pub fn nearest_world_point(&self, p: &Point2D) -> Option<(usize, Float)> {
+        self.nearest_within(p, MAX_DISTANCE)
+            .map(|(i, sq_d)| (i, sq_d.sqrt()))
+    }
 
-                context.set_line_width(3.);
-                let stroke_style = wasm_bindgen::JsValue::from_str("#003300");
-                context.set_stroke_style(&stroke_style);
-                context.stroke();
+    /// Finds the segment (i.e., a pair of point indexes) closest to `p`, within
+    /// `max_distance` (inclusive), returning its position in `segments`.
+    ///
+    /// The distance is measured to the closest point of each segment, so it is
+    /// the distance to one of its ends when `p` is beyond them. Segments referring
+    /// to points that do not exist are ignored, and ties go to the first segment.
+    pub fn nearest_segment(
+        &self,
+        p: &Point2D,
+        segments: &[(usize, usize)],
+        max_distance: Float,
+    ) -> Option<usize> {
+        let mut ret: Option<(usize, Float)> = None;
+        for (i, (a, b)) in segments.iter().enumerate() {
+            let (a, b) = match (self.points.get(*a), self.points.get(*b)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => continue,
+            };
+            let d = distance_to_segment(p, a, b);
+            if d.is_nan() || d > max_distance {
+                continue;
+            }
+            if ret.is_none_or(|(_, min_distance)| d < min_distance) {
+                ret = Some((i, d));
             }
         }
+        ret.map(|(i, _)| i)
     }
 
-    /// Checks if the PointCloud2D is empty
-    pub fn is_empty(&self) -> bool {
-        #[cfg(debug_assertions)]
-        self.check_consistency();
+    /// Drops the spatial hash (if any), as the points have changed
+    fn invalidate_spatial_hash(&mut self) {
+        *self.spatial_hash.get_mut() = None;
+    }
 
-        self.points.is_empty()
+    /// Moves a point to `new_p`, without recording it in the history
+    fn move_point(&mut self, point_index: usize, new_p: Point2D) {
+        let old_p = self.points[point_index];
+        if old_p.x != new_p.x {
+            self.move_point_x(point_index, new_p.x);
+        }
+        if old_p.y != new_p.y {
+            self.move_point_y(point_index, new_p.y);
+        }
     }
 
-    /// Adds a point to the cloud, identifying its position
-    /// and updating the whole structure
-    pub fn push(&mut self, p: Point2D) {
-        // Get the index of the new point
-        let new_index = self.points.len();
-
-        // Push the point
-        self.points.push(p);
-
-        if self.is_sorted{
-
-            // Insert in X
-            let index_x = match self.find_point_position_x(p.x){
-                Ok(i)=>i,
-                Err(e)=>panic!("{}",e)
-            };
-            for e in self.positions_x.iter_mut() {
-                if *e >= index_x {
-                    *e += 1
+    /// Puts back a point removed with `remove_point()` (i.e., the opposite of it),
+    /// along with its ID and its `edges`
+    fn restore_point(
+        &mut self,
+        index: usize,
+        p: Point2D,
+        id: Option<u64>,
+        edges: &[(usize, usize)],
+    ) {
+        self.invalidate_spatial_hash();
+        if self.is_sorted {
+            // The points after it shift back up
+            self.points.insert(index, p);
+            self.ids.insert(index, id);
+            let shift = |i: &mut usize| {
+                if *i >= index {
+                    *i += 1;
                 }
-            }
-            self.positions_x.push(index_x);
-            self.sorted_x.insert(index_x, new_index);
-    
-            // Insert in Y
-            let index_y = match self.find_point_position_y(p.y){
-                Ok(i)=>i,
-                Err(e)=>panic!("{}",e)
             };
-            for e in self.positions_y.iter_mut() {
-                if *e >= index_y {
-                    *e += 1
-                }
+            for (a, b) in self.edges.iter_mut() {
+                shift(a);
+                shift(b);
             }
-            self.positions_y.push(index_y);
-            self.sorted_y.insert(index_y, new_index);
-        }
-
-
-        #[cfg(debug_assertions)]
-        self.check_consistency();
-    }
-
-    /// Updates the Y element of a point in the cloud
-    pub fn update_point_y(&mut self, point_index: usize, new_y: Float) {
-        // We only care about positions when this is sorted
-        if self.is_sorted {
-
-            let old_y_position = self.positions_y[point_index];
-            let mut new_y_position = match self.find_point_position_y(new_y){
-                Ok(i)=>i,
-                Err(e)=> panic!("{}",e)
-            };
-    
-            if old_y_position > new_y_position {
-                // moving down
-    
-                // update positions_y
-                for e in self.positions_y.iter_mut() {
-                    if *e == old_y_position {
-                        *e = new_y_position
-                    } else if *e >= new_y_position && *e < old_y_position {
-                        *e += 1
-                    }
+            if let Some((i, _, _)) = self.flash.as_mut() {
+                shift(i);
+            }
+            self.rebuild_sorting();
+        } else {
+            // The point that took its place goes back to the end
+            let last_index = self.points.len();
+            self.points.push(p);
+            self.points.swap(index, last_index);
+            self.ids.push(id);
+            self.ids.swap(index, last_index);
+            for (a, b) in self.edges.iter_mut() {
+                if *a == index {
+                    *a = last_index;
                 }
-    
-                // update sorted_y
-                for i in (new_y_position + 1..old_y_position + 1).rev() {
-                    self.sorted_y[i] = self.sorted_y[i - 1];
+                if *b == index {
+                    *b = last_index;
                 }
-                self.sorted_y[new_y_position] = point_index;
-            } else if old_y_position < new_y_position {
-                // Moving up
-    
-                // update, because the new position was found
-                // including the point that is being moved.
-                new_y_position -= 1;
-    
-                // update positions_y
-                for e in self.positions_y.iter_mut() {
-                    if *e == old_y_position {
-                        *e = new_y_position
-                    } else if *e <= new_y_position && *e > old_y_position {
-                        *e -= 1
-                    }
+                if *a > *b {
+                    std::mem::swap(a, b);
                 }
-                // update sorted_y
-                for i in old_y_position..new_y_position {
-                    self.sorted_y[i] = self.sorted_y[i + 1];
+            }
+            if let Some((i, _, _)) = self.flash.as_mut() {
+                if *i == index {
+                    *i = last_index;
                 }
-                self.sorted_y[new_y_position] = point_index;
             }
-        }// end of is_sorted?
-        
-
-        // Update point
-        self.points[point_index].y = new_y;
+        }
+        self.edges.extend_from_slice(edges);
 
-        /* VERIFY */
         #[cfg(debug_assertions)]
         self.check_consistency();
     }
 
-    /// Updates the X element of a point in the cloud
-    pub fn update_point_x(&mut self, point_index: usize, new_x: Float) {
-        
-        
-        if self.is_sorted{
-
-            let old_x_position = self.positions_x[point_index];
-            let mut new_x_position = match self.find_point_position_y(new_x){
-                Ok(i)=>i,
-                Err(e)=> panic!("{}",e)
-            };
-    
-            if old_x_position > new_x_position {
-                // moving left
-                // update positions_x... iterate backwards
-                for e in self.positions_x.iter_mut() {
-                    if *e == old_x_position {
-                        *e = new_x_position
-                    } else if *e >= new_x_position && *e < old_x_position {
-                        *e += 1
-                    }
-                }
-    
-                // update sorted_x
-                for i in (new_x_position + 1..old_x_position + 1).rev() {
-                    self.sorted_x[i] = self.sorted_x[i - 1];
-                }
-                self.sorted_x[new_x_position] = point_index;
-            } else if old_x_position < new_x_position {
-                // Moving right
-    
-                // update, because the new position was found
-                // including the point that is being moved.
-                new_x_position -= 1;
-    
-                // update positions_x
-                for e in self.positions_x.iter_mut() {
-                    if *e == old_x_position {
-                        *e = new_x_position
-                    } else if *e <= new_x_position && *e > old_x_position {
-                        *e -= 1
-                    }
-                }
-    
-                // update sorted_x
-                for i in old_x_position..new_x_position {
-                    self.sorted_x[i] = self.sorted_x[i + 1];
-                }
-                self.sorted_x[new_x_position] = point_index;
+    /// Undoes an operation, without recording it in the history
+    fn revert(&mut self, operation: &Operation) {
+        match operation {
+            Operation::Push { .. } => {
+                self.remove_point(self.points.len() - 1);
             }
+            Operation::Remove {
+                index,
+                point,
+                id,
+                edges,
+            } => self.restore_point(*index, *point, *id, edges),
+            Operation::Update { index, from, .. } => self.move_point(*index, *from),
+            Operation::TranslateAll { dx, dy } => self.translate_all(-dx, -dy),
         }
+    }
 
+    /// Does an operation again, without recording it in the history
+    fn reapply(&mut self, operation: &Operation) {
+        match operation {
+            Operation::Push { point, id } => {
+                self.append(*point);
+                let last = self.ids.len() - 1;
+                self.ids[last] = *id;
+            }
+            Operation::Remove { index, .. } => {
+                self.remove_point(*index);
+            }
+            Operation::Update { index, to, .. } => self.move_point(*index, *to),
+            Operation::TranslateAll { dx, dy } => self.translate_all(*dx, *dy),
+        }
+    }
 
-        // Update point
-        self.points[point_index].x = new_x;
+    /// Highlights the points in `indices` (see `highlight_point()`),
+    /// silently skipping the indices that are out of range.
+    pub fn highlight_points(&self, drawer: &Drawer2D, indices: &[usize]) {
+        for p in self.selected_points(indices) {
+            self.draw_highlighted(drawer, p);
+        }
+    }
 
-        /* VERIFY */
-        #[cfg(debug_assertions)]
-        self.check_consistency();
+    /// Iterates the points in `indices`, skipping those that are out of range
+    fn selected_points<'a>(&'a self, indices: &'a [usize]) -> impl Iterator<Item = &'a Point2D> {
+        indices.iter().filter_map(move |i| self.points.get(*i))
     }
 
-    /// Updates the X and Y position of points in point_index
-    pub fn update_point(&mut self, point_index: usize, new_p: Point2D) {
-        let px = self.points[point_index].x;
-        let py = self.points[point_index].y;
-        if (px - new_p.x).abs()>Float::EPSILON{
-            self.update_point_x(point_index, new_p.x);
-        }
-        if (py - new_p.y).abs()>Float::EPSILON{
-            self.update_point_y(point_index, new_p.y);
+    /// Draws a point in the highlight style, if it is visible
+    fn draw_highlighted(&self, drawer: &Drawer2D, p: &Point2D) {
+        let (p, is_visible) = drawer.as_canvas_point(p);
+        if !is_visible {
+            return;
         }
-    }
 
-    /// Moves a point 
-    pub fn translate_point(&mut self, point_index: usize, x_movement: Float, y_movement:Float){
-        let px = self.points[point_index].x;
-        let py = self.points[point_index].y;
-        self.update_point(point_index, Point2D::new(px+x_movement, py+y_movement));
-    } 
+        self.highlight_style.draw_dot(drawer.context(), &p);
+    }
 
-    /// Checks whether a point P is very close to
-    /// another point in the Cloud
+    /// Finds the (min, max) corners of the box containing all the points,
+    /// or `None` if there are none.
     ///
-    /// The way this works is as follows:
-    /// 1. Find the points that might be close enough (i.e., within the p +- MAX_DISTANCE square)
-    /// 2. Check which direction contains less points (i.e., X or Y)
-    /// 3. Iterate the candidate points, checking the distance. If smallest so far, mark for return
-    pub fn test_world_point(&self, p: &Point2D) -> Option<usize> {
-        const MAX_DISTANCE: Float = 0.25;
-        const MAX_DISTANCE_SQ: Float = MAX_DISTANCE * MAX_DISTANCE;
+    /// This takes constant time when sorted, and a scan over all the points
+    /// when unsorted.
+    pub fn bounds(&self) -> Option<(Point2D, Point2D)> {
+        if self.is_sorted {
+            let min_x = self.points[*self.sorted_x.first()?].x;
+            let max_x = self.points[*self.sorted_x.last()?].x;
+            let min_y = self.points[*self.sorted_y.first()?].y;
+            let max_y = self.points[*self.sorted_y.last()?].y;
+            return Some((Point2D::new(min_x, min_y), Point2D::new(max_x, max_y)));
+        }
 
-        // 1. Find the points that might be close enough (i.e., within the p +- MAX_DISTANCE square)
-        // Points outside of this rectangle cannot be "close enough"
-        let min_index_x = self.find_point_position_x(p.x - MAX_DISTANCE).unwrap();
-        let max_index_x = self.find_point_position_x(p.x + MAX_DISTANCE).unwrap();
-        let d_index_x = max_index_x - min_index_x;
+        let first = *self.points.first()?;
+        let mut min = first;
+        let mut max = first;
+        for p in self.points.iter() {
+            min.x = min.x.min(p.x);
+            min.y = min.y.min(p.y);
+            max.x = max.x.max(p.x);
+            max.y = max.y.max(p.y);
+        }
+        Some((min, max))
+    }
 
-        let min_index_y = self.find_point_position_y(p.y - MAX_DISTANCE).unwrap();
-        let max_index_y = self.find_point_position_y(p.y + MAX_DISTANCE).unwrap();
-        let d_index_y = max_index_y - min_index_y;
+    /// Calculates the convex hull of the cloud using Andrew's monotone
+    /// chain, returning the indexes of its vertices in counter-clockwise order.
+    ///
+    /// Points lying on the edges of the hull are not included. Clouds with
+    /// fewer than three points return all their indexes.
+    pub fn convex_hull(&self) -> Vec<usize> {
+        let n = self.points.len();
+        if n < 3 {
+            return (0..n).collect();
+        }
 
-        // 2. Check which direction contains less points (i.e., X or Y)
-        let (candidate_point_positions, sorted) = if d_index_x <= d_index_y {
-            // there are less points to test in the X axis... iterate them all
-            (min_index_x..max_index_x, &self.sorted_x)
+        // Sort the points lexicographically... sorted_x is (nearly) there already
+        let mut sorted: Vec<usize> = if self.is_sorted {
+            self.sorted_x.clone()
         } else {
-            // there are less points to test in the Y axis
-            (min_index_y..max_index_y, &self.sorted_y)
+            (0..n).collect()
         };
+        sorted.sort_by(|a, b| {
+            let (a, b) = (&self.points[*a], &self.points[*b]);
+            compare_coordinates(a.x, b.x).then(compare_coordinates(a.y, b.y))
+        });
 
-        // initialize return
-        let mut ret: Option<usize> = None;
-        let mut min_squared_distance = MAX_DISTANCE_SQ;
+        let cross = |o: usize, a: usize, b: usize| -> Float {
+            let (o, a, b) = (&self.points[o], &self.points[a], &self.points[b]);
+            (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x)
+        };
 
-        // 3. Iterate the candidate points, checking the distance. If smallest so far, mark for return
-        for other_position in candidate_point_positions {
-            // Get the point
-            let other_index = sorted[other_position];
-            let other_p = &self.points[other_index];
-            // Check distance... would this be really more efficient if searched in squares as opposed to circles?
-            // That is, instead of calculating the ACTUAL square distance, to calculate the
-            // vertical/horizontal distance?
-            let sq_d = p.squared_distance_to(other_p);
-            if sq_d < min_squared_distance {
-                ret = Some(other_index);
-                min_squared_distance = sq_d;
+        let mut hull: Vec<usize> = Vec::with_capacity(2 * n);
+
+        // Lower hull
+        for i in sorted.iter() {
+            while hull.len() >= 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], *i) <= 0. {
+                hull.pop();
             }
+            hull.push(*i);
         }
 
-        // return
-        ret
+        // Upper hull
+        let lower_len = hull.len() + 1;
+        for i in sorted.iter().rev().skip(1) {
+            while hull.len() >= lower_len
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], *i) <= 0.
+            {
+                hull.pop();
+            }
+            hull.push(*i);
+        }
+
+        // The last one is the first one
+        hull.pop();
+        hull
     }
 
-    /// Highlights a point by showing it on a different colour    
-    pub fn highlight_point(&self, drawer: &Drawer2D, i: usize) {
-        let (p, is_visible) = drawer.as_canvas_point(&self.points[i]);
-        if !is_visible {
-            return;
+    /// Calculates the smallest-area (possibly rotated) rectangle enclosing all
+    /// the points, returning its four corners in counter-clockwise order.
+    ///
+    /// It uses the rotating calipers method over the convex hull: the
+    /// best rectangle has one side aligned with an edge of the hull.
+    /// Returns `None` when there are not three non-collinear points.
+    pub fn min_area_rect(&self) -> Option<(Point2D, Point2D, Point2D, Point2D)> {
+        let hull: Vec<Point2D> = self.convex_hull().iter().map(|i| self.points[*i]).collect();
+        let n = hull.len();
+        if n < 3 {
+            return None;
         }
 
-        const RADIUS: Float = 8.;
+        let dot = |p: &Point2D, dx: Float, dy: Float| p.x * dx + p.y * dy;
 
-        drawer.context().begin_path();
-        drawer
-            .context()
-            .arc(p.x.into(), p.y.into(), RADIUS.into(), 0., 2.0 * std::f64::consts::PI)
-            .unwrap();
+        // The calipers: positions of the furthest point to the right, top and left of each edge.
+        let (mut right, mut top, mut left) = (0, 0, 0);
+
+        let mut best: Option<(Float, (Point2D, Point2D, Point2D, Point2D))> = None;
+        for i in 0..n {
+            let a = hull[i];
+            let b = hull[(i + 1) % n];
+            let len = a.distance_to(&b);
+            // Unit direction along the edge, and its (inwards) normal
+            let (ux, uy) = ((b.x - a.x) / len, (b.y - a.y) / len);
+            let (vx, vy) = (-uy, ux);
+
+            if i == 0 {
+                right = 1;
+            }
+            while dot(&hull[(right + 1) % n], ux, uy) > dot(&hull[right], ux, uy) {
+                right = (right + 1) % n;
+            }
+            if i == 0 {
+                top = right;
+            }
+            while dot(&hull[(top + 1) % n], vx, vy) > dot(&hull[top], vx, vy) {
+                top = (top + 1) % n;
+            }
+            if i == 0 {
+                left = top;
+            }
+            while dot(&hull[(left + 1) % n], ux, uy) < dot(&hull[left], ux, uy) {
+                left = (left + 1) % n;
+            }
+
+            let min_u = dot(&hull[left], ux, uy) - dot(&a, ux, uy);
+            let max_u = dot(&hull[right], ux, uy) - dot(&a, ux, uy);
+            let max_v = dot(&hull[top], vx, vy) - dot(&a, vx, vy);
+            let area = (max_u - min_u) * max_v;
 
-        let fill_style = wasm_bindgen::JsValue::from_str("red");
-        drawer.context().set_fill_style(&fill_style);
-        drawer.context().fill();
+            if best.is_none_or(|(best_area, _)| area < best_area) {
+                let corner =
+                    |u: Float, v: Float| Point2D::new(a.x + u * ux + v * vx, a.y + u * uy + v * vy);
+                best = Some((
+                    area,
+                    (
+                        corner(min_u, 0.),
+                        corner(max_u, 0.),
+                        corner(max_u, max_v),
+                        corner(min_u, max_v),
+                    ),
+                ));
+            }
+        }
 
-        drawer.context().set_line_width(3.);
-        let stroke_style = wasm_bindgen::JsValue::from_str("#330000");
-        drawer.context().set_stroke_style(&stroke_style);
-        drawer.context().stroke();
+        best.map(|(_, corners)| corners)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Finds the ends of a copy of the connection in position `edge_index` of
+    /// `connections()`, moved `distance` meters perpendicular to it (e.g., for
+    /// drawing walls as double lines). Positive distances go to its left, as
+    /// seen going from its first point to its second one.
+    ///
+    /// Returns `None` if there is no such connection, or if its points are
+    /// coincident (so it has no direction).
+    pub fn parallel_edge_points(
+        &self,
+        edge_index: usize,
+        distance: Float,
+    ) -> Option<(Point2D, Point2D)> {
+        let (a, b) = self.edges.get(edge_index)?;
+        let (a, b) = (&self.points[*a], &self.points[*b]);
+        let length = a.distance_to(b);
+        if length == 0. {
+            return None;
+        }
 
-    #[test]
-    fn test_find_point_position_x() {
-        /******************************** */
-        /* Empty case... should return 0. */
-        /******************************** */
-        let cloud = PointCloud2D::new();
-        cloud.check_consistency();
-        assert_eq!(cloud.find_point_position_x(1.).unwrap(), 0);
+        // The direction, turned a quarter counterclockwise
+        let d = b.sub(a).scale(distance / length);
+        let offset = Point2D::new(-d.y, d.x);
+        Some((a.add(&offset), b.add(&offset)))
+    }
 
-        /******************************** */
-        /* 1 point case. */
-        /******************************** */
+    /// Rasterizes the cloud into a grid of square cells of `cell_size`, marking
+    /// those containing points or crossed by connections.
+    ///
+    /// Returns the cells (row by row, going up from the origin), the width and
+    /// height of the grid in cells, and the world position of the corner of
+    /// cell (0, 0). Empty clouds and non-positive cell sizes produce an empty grid,
+    /// as do grids that would have more than `MAX_GRID_CELLS` cells (e.g., with
+    /// a tiny `cell_size`, or a point very far from the rest).
+    pub fn occupancy_grid(&self, cell_size: Float) -> (Vec<bool>, usize, usize, Point2D) {
+        let empty = (Vec::new(), 0, 0, Point2D::new(0., 0.));
+        let (min, max) = match self.bounds() {
+            Some(b) if cell_size > 0. => b,
+            _ => return empty,
+        };
 
-        // X axis is | ----- O ------
-        //                 (0,0)
-        let cloud = PointCloud2D {
-            points: vec![Point2D { x: 0.0, y: 0.0 }],
-            positions_x: vec![0],
-            positions_y: vec![0],
-            sorted_x: vec![0],
-            sorted_y: vec![0],
-            is_sorted:true,
+        // The number of cells along an extent of the bounds, if not too many
+        let cells = |extent: Float| {
+            let n = (extent / cell_size).floor();
+            if n < MAX_GRID_CELLS as Float {
+                Some(n as usize + 1)
+            } else {
+                None
+            }
         };
-        cloud.check_consistency();
+        let (width, height) = match (cells(max.x - min.x), cells(max.y - min.y)) {
+            (Some(w), Some(h)) if w.checked_mul(h).filter(|n| *n <= MAX_GRID_CELLS).is_some() => {
+                (w, h)
+            }
+            _ => return empty,
+        };
+        let mut grid = vec![false; width * height];
 
-        // Testing case:
-        // X axis is   - P ---------- O ------
-        //            (-1,0)        (0,0)
+        // In cell units
+        let to_cell = |p: &Point2D| ((p.x - min.x) / cell_size, (p.y - min.y) / cell_size);
+        let mut mark = |col: i64, row: i64| {
+            if col >= 0 && row >= 0 && (col as usize) < width && (row as usize) < height {
+                grid[row as usize * width + col as usize] = true;
+            }
+        };
+
+        for p in self.points.iter() {
+            let (x, y) = to_cell(p);
+            mark(x.floor() as i64, y.floor() as i64);
+        }
+
+        // Walk the cells crossed by each connection
+        for (a, b) in self.edges.iter() {
+            let (x0, y0) = to_cell(&self.points[*a]);
+            let (x1, y1) = to_cell(&self.points[*b]);
+            let (mut col, mut row) = (x0.floor() as i64, y0.floor() as i64);
+            let (end_col, end_row) = (x1.floor() as i64, y1.floor() as i64);
+            let (dx, dy) = (x1 - x0, y1 - y0);
+            let step_col = if dx > 0. { 1 } else { -1 };
+            let step_row = if dy > 0. { 1 } else { -1 };
+
+            // Distances along the segment (from 0 to 1) to the next cell border,
+            // and between borders
+            let border_t = |start: Float, cell: i64, step: i64, d: Float| {
+                if d == 0. {
+                    Float::INFINITY
+                } else {
+                    let border = if step > 0 { cell + 1 } else { cell } as Float;
+                    (border - start) / d
+                }
+            };
+            let mut t_col = border_t(x0, col, step_col, dx);
+            let mut t_row = border_t(y0, row, step_row, dy);
+            // (infinite if the segment does not move in that axis)
+            let delta_col = 1. / dx.abs();
+            let delta_row = 1. / dy.abs();
+
+            mark(col, row);
+            let n_steps = (end_col - col).abs() + (end_row - row).abs();
+            for _ in 0..n_steps {
+                if t_col < t_row {
+                    col += step_col;
+                    t_col += delta_col;
+                } else {
+                    row += step_row;
+                    t_row += delta_row;
+                }
+                mark(col, row);
+            }
+        }
+
+        (grid, width, height, min)
+    }
+
+    /// Lists the (name, length, capacity, bytes) of each of the internal vectors,
+    /// with the bytes being the approximate heap footprint (i.e., capacity
+    /// times the size of the elements)
+    fn vector_stats(&self) -> [(&'static str, usize, usize, usize); 7] {
+        fn stats<T>(name: &'static str, v: &Vec<T>) -> (&'static str, usize, usize, usize) {
+            (
+                name,
+                v.len(),
+                v.capacity(),
+                v.capacity() * std::mem::size_of::<T>(),
+            )
+        }
+        [
+            stats("points", &self.points),
+            stats("positions_x", &self.positions_x),
+            stats("positions_y", &self.positions_y),
+            stats("sorted_x", &self.sorted_x),
+            stats("sorted_y", &self.sorted_y),
+            stats("ids", &self.ids),
+            stats("edges", &self.edges),
+        ]
+    }
+
+    /// Finds the indexes of the points around the closed loop formed by the
+    /// connections, if they form exactly one (i.e., all connected points have
+    /// two connections, and they are all reachable from each other).
+    ///
+    /// The loop starts at its lowest index and goes towards the lowest of
+    /// its neighbours, so connecting points in order follows that order.
+    fn edge_loop(&self) -> Option<Vec<usize>> {
+        if self.edges.len() < 3 {
+            return None;
+        }
+
+        let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); self.points.len()];
+        for (a, b) in self.edges.iter() {
+            neighbours[*a].push(*b);
+            neighbours[*b].push(*a);
+        }
+        if neighbours.iter().any(|n| !n.is_empty() && n.len() != 2) {
+            return None;
+        }
+
+        let start = neighbours.iter().position(|n| !n.is_empty())?;
+        let mut ret = Vec::with_capacity(self.edges.len());
+        let mut prev = start;
+        let mut current = *neighbours[start].iter().min()?;
+        ret.push(start);
+        while current != start {
+            ret.push(current);
+            let next = if neighbours[current][0] == prev {
+                neighbours[current][1]
+            } else {
+                neighbours[current][0]
+            };
+            prev = current;
+            current = next;
+        }
+
+        // Other connections are in a different loop
+        if ret.len() == self.edges.len() {
+            Some(ret)
+        } else {
+            None
+        }
+    }
+
+    /// Finds the indexes of the points along the single chain formed by the
+    /// connections, and whether it is closed (see `edge_loop()`).
+    ///
+    /// Open chains go from their end with the lowest index to the other one.
+    /// Returns `None` if there are no connections, if any point has more than
+    /// two, or if they form more than one chain.
+    fn edge_chain(&self) -> Option<(Vec<usize>, bool)> {
+        let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); self.points.len()];
+        for (a, b) in self.edges.iter() {
+            neighbours[*a].push(*b);
+            neighbours[*b].push(*a);
+        }
+        if neighbours.iter().any(|n| n.len() > 2) {
+            return None;
+        }
+
+        let start = match neighbours.iter().position(|n| n.len() == 1) {
+            Some(start) => start,
+            None => return self.edge_loop().map(|ring| (ring, true)),
+        };
+        let mut ret = Vec::with_capacity(self.edges.len() + 1);
+        let mut prev = start;
+        let mut current = neighbours[start][0];
+        ret.push(start);
+        ret.push(current);
+        while neighbours[current].len() == 2 {
+            let next = if neighbours[current][0] == prev {
+                neighbours[current][1]
+            } else {
+                neighbours[current][0]
+            };
+            prev = current;
+            current = next;
+            ret.push(current);
+        }
+
+        // Other connections are in a different chain
+        if ret.len() == self.edges.len() + 1 {
+            Some((ret, false))
+        } else {
+            None
+        }
+    }
+
+    /// Checks whether the structure is coherent
+    #[cfg(debug_assertions)]
+    fn check_consistency(&self) {
+        // IDs and edges are kept regardless of sorting
+        debug_assert_eq!(self.points.len(), self.ids.len());
+        for (a, b) in self.edges.iter() {
+            debug_assert!(a < b);
+            debug_assert!(*b < self.points.len());
+        }
+
+        if !self.is_sorted {
+            // nothing to check
+            return;
+        }
+
+        // Lengths of the structure
+        debug_assert_eq!(self.points.len(), self.positions_x.len());
+        debug_assert_eq!(self.positions_x.len(), self.positions_y.len());
+        debug_assert_eq!(self.positions_y.len(), self.sorted_x.len());
+        debug_assert_eq!(self.sorted_x.len(), self.sorted_y.len());
+
+        // Ensure that all positions are there
+        for i in 0..self.points.len() {
+            assert!(self.positions_x.contains(&i));
+            assert!(self.positions_y.contains(&i));
+            assert!(self.sorted_x.contains(&i));
+            assert!(self.sorted_y.contains(&i));
+        }
+
+        // indexes and positions in X direction
+        for i in 0..self.sorted_x.len() {
+            let index = self.sorted_x[i];
+            let current = self.points[index];
+            debug_assert_eq!(self.positions_x[index], i);
+
+            // If not first, check previous
+            if i >= 1 {
+                let prev_index = self.sorted_x[i - 1];
+                let prev = self.points[prev_index];
+                if prev.x > current.x {
+                    panic!("not true: prev.x [index:{}, position:{}, x:{:.6}] <= current.x [index:{}, position:{}, x:{:.6}]",prev_index, i-1,prev.x, index, i ,current.x);
+                }
+            }
+
+            // if not last, check next
+            if i + 1 < self.sorted_x.len() {
+                let next_index = self.sorted_x[i + 1];
+                let next = self.points[next_index];
+                if next.x < current.x {
+                    panic!("not true: next.x [index:{}, position:{}, x:{:.6}] >= current.x [index:{}, position:{}, x:{:.6}]",next_index, i+1, next.x, index, i, current.x);
+                }
+            }
+        }
+
+        // indexes and positions in Y direction
+        for i in 0..self.sorted_y.len() {
+            let index = self.sorted_y[i];
+            let current = self.points[index];
+            debug_assert_eq!(self.positions_y[index], i);
+
+            // If not first, check previous
+            if i >= 1 {
+                let prev_index = self.sorted_y[i - 1];
+                let prev = self.points[prev_index];
+                if prev.y > current.y {
+                    panic!("not true: prev.y [index:{}] <= current.y [index:{}] | next.y = {}, current.y = {}",prev_index, index, prev.y, current.y);
+                }
+            }
+
+            // if not last, check next
+            if i + 1 < self.sorted_y.len() {
+                let next_index = self.sorted_y[i + 1];
+                let next = self.points[next_index];
+                if next.y < current.y {
+                    panic!("not true: next.y [index:{}] >= current.y [index:{}] | next.y = {}, current.y = {}",next_index, index, next.y, current.y);
+                }
+            }
+        }
+    }
+}
+
+impl Default for PointCloud2D {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl PointCloud2D {
+    /// Creates a new empty PointCloud2D
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self {
+            points: Vec::new(),
+            positions_x: Vec::new(),
+            positions_y: Vec::new(),
+            sorted_x: Vec::new(),
+            sorted_y: Vec::new(),
+            ids: Vec::new(),
+            is_sorted: true,
+            edges: Vec::new(),
+            edge_style: EdgeStyle::Straight,
+            flash: None,
+            style: PointStyle::regular(),
+            highlight_style: PointStyle::highlighted(),
+            update_tolerance: Float::EPSILON,
+            spatial_hash_cell_size: None,
+            spatial_hash: RefCell::new(None),
+            history: History::default(),
+        }
+    }
+
+    /// Creates a new empty PointCloud2D
+    #[wasm_bindgen]
+    pub fn new_unsorted() -> Self {
+        Self {
+            points: Vec::new(),
+            positions_x: Vec::new(),
+            positions_y: Vec::new(),
+            sorted_x: Vec::new(),
+            sorted_y: Vec::new(),
+            ids: Vec::new(),
+            is_sorted: false,
+            edges: Vec::new(),
+            edge_style: EdgeStyle::Straight,
+            flash: None,
+            style: PointStyle::regular(),
+            highlight_style: PointStyle::highlighted(),
+            update_tolerance: Float::EPSILON,
+            spatial_hash_cell_size: None,
+            spatial_hash: RefCell::new(None),
+            history: History::default(),
+        }
+    }
+
+    /// Cleans the canvas and then redraws
+    pub fn redraw(&self, drawer: &Drawer2D) {
+        drawer.clear();
+        self.draw(drawer)
+    }
+
+    /// Draws the Cloud
+    pub fn draw(&self, drawer: &Drawer2D) {
+        let context = drawer.context();
+
+        let viewport = drawer.viewport();
+
+        // Connections go below the points
+        let visible_edges = self.visible_edges(&viewport);
+        if !visible_edges.is_empty() {
+            context.begin_path();
+            for i in visible_edges {
+                let (a, b) = self.edges[i];
+                self.trace_edge(context, &viewport, &self.points[a], &self.points[b]);
+            }
+            context.set_line_width(2.);
+            context.set_stroke_style_str(&self.style.stroke_style);
+            context.stroke();
+        }
+
+        for i in self.visible_points(&viewport) {
+            let (canvas_p, _) = viewport.as_canvas_point(&self.points[i]);
+            self.style.draw_dot(context, &canvas_p);
+        }
+    }
+
+    /// Draws the connections between points as lines of a certain `color`
+    /// and `width` (in pixels), without drawing the points themselves.
+    ///
+    /// Only the connections crossing the canvas are drawn.
+    pub fn draw_edges_only(&self, drawer: &Drawer2D, width: f64, color: &str) {
+        let viewport = drawer.viewport();
+        let visible = self.visible_edges(&viewport);
+        if visible.is_empty() {
+            return;
+        }
+
+        let context = drawer.context();
+        context.begin_path();
+        for i in visible {
+            let (a, b) = self.edges[i];
+            self.trace_edge(context, &viewport, &self.points[a], &self.points[b]);
+        }
+        context.set_line_width(width);
+        context.set_stroke_style_str(color);
+        context.stroke();
+    }
+
+    /// Sets how the connections between points are drawn: as straight lines
+    /// (the default) or as right-angle connectors.
+    pub fn set_edge_style(&mut self, style: EdgeStyle) {
+        self.edge_style = style;
+    }
+
+    /// Sets how the points are drawn: the `radius` of the dots and the
+    /// `line_width` of their outline (in pixels), and the CSS `fill` and
+    /// `stroke` styles of their inside and outline.
+    ///
+    /// By default, points are drawn as green dots of 5 pixels.
+    pub fn set_point_style(&mut self, radius: f64, fill: &str, stroke: &str, line_width: f64) {
+        self.style = PointStyle {
+            radius,
+            fill_style: fill.to_string(),
+            stroke_style: stroke.to_string(),
+            line_width,
+        };
+    }
+
+    /// Sets how the highlighted points are drawn, just like `set_point_style()`.
+    ///
+    /// By default, highlighted points are drawn as red dots of 8 pixels.
+    pub fn set_highlight_style(&mut self, radius: f64, fill: &str, stroke: &str, line_width: f64) {
+        self.highlight_style = PointStyle {
+            radius,
+            fill_style: fill.to_string(),
+            stroke_style: stroke.to_string(),
+            line_width,
+        };
+    }
+
+    /// Checks if the PointCloud2D is empty
+    pub fn is_empty(&self) -> bool {
+        #[cfg(debug_assertions)]
+        self.check_consistency();
+
+        self.points.is_empty()
+    }
+
+    /// Counts the points in the PointCloud2D
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Gets a copy of the point with index `index`, or `None` (i.e.,
+    /// `undefined`, in JavaScript) if it is out of range
+    pub fn get_point(&self, index: usize) -> Option<Point2D> {
+        self.points.get(index).copied()
+    }
+
+    /// Counts the points in the PointCloud2D, just like `len()`
+    pub fn points_count(&self) -> usize {
+        self.len()
+    }
+
+    /// Copies the X coordinates of all the points, in the same order as the
+    /// points (i.e., the `i`-th value belongs to the point with index `i`).
+    ///
+    /// In JavaScript, this is a typed array.
+    pub fn point_xs(&self) -> Vec<Float> {
+        self.points.iter().map(|p| p.x).collect()
+    }
+
+    /// Copies the Y coordinates of all the points, like `point_xs()` does
+    /// with the X ones.
+    pub fn point_ys(&self) -> Vec<Float> {
+        self.points.iter().map(|p| p.y).collect()
+    }
+
+    /// Reports the length and capacity of each of the internal vectors, and
+    /// the approximate heap memory they use, in bytes
+    pub fn memory_stats(&self) -> String {
+        let stats = self.vector_stats();
+        let mut ret = String::new();
+        for (name, len, capacity, bytes) in stats.iter() {
+            ret.push_str(&format!(
+                "{}: len {}, capacity {}, {} bytes\n",
+                name, len, capacity, bytes
+            ));
+        }
+        let total: usize = stats.iter().map(|s| s.3).sum();
+        ret.push_str(&format!("total: {} bytes", total));
+        ret
+    }
+
+    /// Writes the points (with their IDs and connections, and whether the
+    /// cloud is sorted) as a JSON string, to be loaded with `from_json()`.
+    ///
+    /// Coordinates that are not finite cannot be written in JSON, so
+    /// they are written as `null`... which `from_json()` rejects.
+    pub fn to_json(&self) -> String {
+        let saved = SavedPointCloud2D {
+            points: Cow::Borrowed(&self.points),
+            is_sorted: self.is_sorted,
+            edges: Cow::Borrowed(&self.edges),
+            ids: Cow::Borrowed(&self.ids),
+        };
+        serde_json::to_string(&saved).unwrap()
+    }
+
+    /// Loads a cloud written by `to_json()`, sorting it again if needed.
+    ///
+    /// The connections are cleaned up with `normalize_edges()`, so they can
+    /// come in any order. Returns an error if the string is not a valid cloud
+    /// (e.g., if a connection refers to a point that does not exist).
+    pub fn from_json(s: &str) -> Result<PointCloud2D, String> {
+        let saved: SavedPointCloud2D = serde_json::from_str(s).map_err(|e| e.to_string())?;
+        let points = saved.points.into_owned();
+        let n = points.len();
+        if let Some((a, b)) = saved.edges.iter().find(|(a, b)| *a >= n || *b >= n) {
+            return Err(format!(
+                "Cannot connect points {} and {}... the cloud has {} points",
+                a, b, n
+            ));
+        }
+        if !saved.ids.is_empty() && saved.ids.len() != n {
+            return Err(format!(
+                "Cannot load {} IDs... the cloud has {} points",
+                saved.ids.len(),
+                n
+            ));
+        }
+
+        let mut ret = if saved.is_sorted {
+            Self::from_points(points)
+        } else {
+            Self {
+                points,
+                ids: vec![None; n],
+                ..Self::new_unsorted()
+            }
+        };
+        if !saved.ids.is_empty() {
+            ret.ids = saved.ids.into_owned();
+        }
+        ret.edges = saved.edges.into_owned();
+        ret.normalize_edges();
+
+        #[cfg(debug_assertions)]
+        ret.check_consistency();
+
+        Ok(ret)
+    }
+
+    /// Calculates the distance between the points with indexes `a` and `b`.
+    ///
+    /// Returns `None` if any of the indexes is out of range
+    pub fn distance_between(&self, a: usize, b: usize) -> Option<Float> {
+        let a = self.points.get(a)?;
+        let b = self.points.get(b)?;
+        Some(a.distance_to(b))
+    }
+
+    /// Counts the points that are visible in the Drawer2D
+    pub fn visible_count(&self, drawer: &Drawer2D) -> usize {
+        let (min, max) = drawer.visible_world_rect();
+        self.count_in_rect(&min, &max)
+    }
+
+    /// Exports the cloud as an SVG document of `width_px` by `height_px` pixels,
+    /// with the points fitted into it and their connections below them (as
+    /// `<line>`s, or as `<polyline>`s when drawn as `EdgeStyle::Orthogonal`).
+    ///
+    /// An empty cloud produces a valid, empty, SVG document.
+    pub fn to_svg(&self, width_px: u32, height_px: u32) -> String {
+        const MARGIN: Float = 8.;
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+            w = width_px,
+            h = height_px
+        );
+
+        if let Some((min, max)) = self.bounds() {
+            let canvas_width = width_px as Float;
+            let canvas_height = height_px as Float;
+
+            // Fit the bounds in the canvas, keeping the aspect ratio
+            let available_width = (canvas_width - 2. * MARGIN).max(0.);
+            let available_height = (canvas_height - 2. * MARGIN).max(0.);
+            let span_x = max.x - min.x;
+            let span_y = max.y - min.y;
+            let scale = match (span_x > 0., span_y > 0.) {
+                (true, true) => (available_width / span_x).min(available_height / span_y),
+                (true, false) => available_width / span_x,
+                (false, true) => available_height / span_y,
+                (false, false) => 1.,
+            };
+            let mid_x = (min.x + max.x) / 2.;
+            let mid_y = (min.y + max.y) / 2.;
+
+            // The Y axis goes down in the SVG
+            let to_svg = |p: &Point2D| {
+                (
+                    canvas_width / 2. + (p.x - mid_x) * scale,
+                    canvas_height / 2. - (p.y - mid_y) * scale,
+                )
+            };
+
+            // Same style as `draw()`, with the connections below the points
+            if !self.edges.is_empty() {
+                svg.push_str(&format!(
+                    "<g fill=\"none\" stroke=\"{}\" stroke-width=\"2\">\n",
+                    self.style.stroke_style
+                ));
+                for (a, b) in self.edges.iter() {
+                    let (a, b) = (&self.points[*a], &self.points[*b]);
+                    let (x1, y1) = to_svg(a);
+                    let (x2, y2) = to_svg(b);
+                    match self.edge_style {
+                        EdgeStyle::Straight => svg.push_str(&format!(
+                            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"/>\n",
+                            x1, y1, x2, y2
+                        )),
+                        EdgeStyle::Orthogonal => {
+                            let (xc, yc) = to_svg(&elbow(a, b));
+                            svg.push_str(&format!(
+                                "<polyline points=\"{},{} {},{} {},{}\"/>\n",
+                                x1, y1, xc, yc, x2, y2
+                            ))
+                        }
+                    }
+                }
+                svg.push_str("</g>\n");
+            }
+
+            svg.push_str(&format!(
+                "<g fill=\"{}\" stroke=\"{}\" stroke-width=\"{}\">\n",
+                self.style.fill_style, self.style.stroke_style, self.style.line_width
+            ));
+            for p in self.points.iter() {
+                let (x, y) = to_svg(p);
+                svg.push_str(&format!(
+                    "<circle cx=\"{}\" cy=\"{}\" r=\"{}\"/>\n",
+                    x, y, self.style.radius
+                ));
+            }
+            svg.push_str("</g>\n");
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Finds the points within the rectangle going from `min` to `max`, borders included.
+    ///
+    /// Inverted (i.e., `min` greater than `max`) or zero-area rectangles contain no points.
+    /// Sorted clouds only check the points within the narrowest of the X or Y windows,
+    /// while unsorted ones check them all.
+    pub fn points_in_rect(&self, min: &Point2D, max: &Point2D) -> Vec<usize> {
+        if min.x >= max.x || min.y >= max.y {
+            return Vec::new();
+        }
+        self.iter_in_rect(min, max).collect()
+    }
+
+    /// Finds the points whose X component is within `tolerance` of `x`
+    /// (e.g., the points in a column)
+    pub fn points_at_x(&self, x: Float, tolerance: Float) -> Vec<usize> {
+        if !self.is_sorted {
+            return (0..self.points.len())
+                .filter(|i| (self.points[*i].x - x).abs() <= tolerance)
+                .collect();
+        }
+        self.sorted_x[self.window_x(x - tolerance, x + tolerance)].to_vec()
+    }
+
+    /// Finds the points whose Y component is within `tolerance` of `y`
+    /// (e.g., the points in a row)
+    pub fn points_at_y(&self, y: Float, tolerance: Float) -> Vec<usize> {
+        if !self.is_sorted {
+            return (0..self.points.len())
+                .filter(|i| (self.points[*i].y - y).abs() <= tolerance)
+                .collect();
+        }
+        self.sorted_y[self.window_y(y - tolerance, y + tolerance)].to_vec()
+    }
+
+    /// Returns copies of the vertices of the convex hull, in
+    /// counter-clockwise order
+    pub fn convex_hull_points(&self) -> Vec<Point2D> {
+        self.convex_hull().iter().map(|i| self.points[*i]).collect()
+    }
+
+    /// Calculates a fingerprint of the geometry (i.e., the coordinates of the
+    /// points, in order, and the connections between them) that can be used
+    /// for detecting changes.
+    ///
+    /// Reordering the points changes the hash, even if the geometry is the
+    /// same, but the order in which they were connected does not. The hash
+    /// is not guaranteed to be the same across builds.
+    pub fn geometry_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.points.len().hash(&mut hasher);
+        for p in self.points.iter() {
+            p.x.to_bits().hash(&mut hasher);
+            p.y.to_bits().hash(&mut hasher);
+        }
+        let mut edges = self.edges.clone();
+        edges.sort_unstable();
+        edges.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Calculates the centroid (i.e., the average position) of the
+    /// points, or `None` if there are none
+    pub fn centroid(&self) -> Option<Point2D> {
+        average(self.points.iter())
+    }
+
+    /// Returns the width/height ratio of the box containing all the points.
+    ///
+    /// Returns `None` for an empty cloud and infinity when the
+    /// box has no height (e.g., all the points are in a horizontal line)
+    pub fn bounds_aspect_ratio(&self) -> Option<Float> {
+        let (min, max) = self.bounds()?;
+        let width = max.x - min.x;
+        let height = max.y - min.y;
+        if height == 0.0 {
+            return Some(Float::INFINITY);
+        }
+        Some(width / height)
+    }
+
+    /// Makes an unsorted PointCloud2D sorted, so that the queries that rely
+    /// on sorting can be used. Points keep their indexes.
+    ///
+    /// Does nothing if the PointCloud2D is already sorted.
+    pub fn sort(&mut self) {
+        if self.is_sorted {
+            return;
+        }
+        self.rebuild_sorting();
+        self.is_sorted = true;
+
+        #[cfg(debug_assertions)]
+        self.check_consistency();
+    }
+
+    /// Returns the indexes of the points sorted by their angle around `center`
+    /// (i.e., `atan2(y - center.y, x - center.x)`), which goes counterclockwise
+    /// starting just after the negative X direction.
+    ///
+    /// Points coincident with the center go first, and points with the same
+    /// angle keep their index order.
+    pub fn ordered_by_angle(&self, center: &Point2D) -> Vec<usize> {
+        let angle = |i: usize| {
+            let p = &self.points[i];
+            if p.x == center.x && p.y == center.y {
+                None
+            } else {
+                Some((p.y - center.y).atan2(p.x - center.x))
+            }
+        };
+        let mut ret: Vec<usize> = (0..self.points.len()).collect();
+        ret.sort_by(|a, b| match (angle(*a), angle(*b)) {
+            (Some(a), Some(b)) => compare_coordinates(a, b),
+            (a, b) => a.is_some().cmp(&b.is_some()),
+        });
+        ret
+    }
+
+    /// Returns the indexes of the points, sorted in the X axis.
+    ///
+    /// This is empty when the PointCloud2D is unsorted
+    pub fn sort_order_x(&self) -> Vec<usize> {
+        self.sorted_x.clone()
+    }
+
+    /// Returns the indexes of the points, sorted in the Y axis.
+    ///
+    /// This is empty when the PointCloud2D is unsorted
+    pub fn sort_order_y(&self) -> Vec<usize> {
+        self.sorted_y.clone()
+    }
+
+    /// Adds a point to the cloud, identifying its position
+    /// and updating the whole structure
+    pub fn push(&mut self, p: Point2D) {
+        self.history.record(Operation::Push { point: p, id: None });
+        self.append(p);
+    }
+
+    /// Adds a point to the cloud like `push()`, unless there is already a point
+    /// closer than `tol` to it (e.g., after a double click). Returns the
+    /// index of that point, if there is one, or the index of the new point.
+    ///
+    /// Points with NaN coordinates are not pushed, as they could never be
+    /// found this way, and return `None`.
+    pub fn push_unique(&mut self, p: Point2D, tol: Float) -> Option<usize> {
+        if p.x.is_nan() || p.y.is_nan() {
+            return None;
+        }
+
+        let existing = self.test_world_point_within(&p, tol);
+        if existing.is_some() {
+            return existing;
+        }
+
+        self.push(p);
+        Some(self.points.len() - 1)
+    }
+
+    /// Adds a point at the end, without recording it in the history
+    fn append(&mut self, p: Point2D) {
+        // Get the index of the new point
+        let new_index = self.points.len();
+
+        // Push the point
+        self.points.push(p);
+        self.ids.push(None);
+        self.invalidate_spatial_hash();
+
+        if self.is_sorted{
+
+            // Insert in X
+            let index_x = match self.find_point_position_x(p.x){
+                Ok(i)=>i,
+                Err(e)=>panic!("{}",e)
+            };
+            for e in self.positions_x.iter_mut() {
+                if *e >= index_x {
+                    *e += 1
+                }
+            }
+            self.positions_x.push(index_x);
+            self.sorted_x.insert(index_x, new_index);
+    
+            // Insert in Y
+            let index_y = match self.find_point_position_y(p.y){
+                Ok(i)=>i,
+                Err(e)=>panic!("{}",e)
+            };
+            for e in self.positions_y.iter_mut() {
+                if *e >= index_y {
+                    *e += 1
+                }
+            }
+            self.positions_y.push(index_y);
+            self.sorted_y.insert(index_y, new_index);
+        }
+
+
+        #[cfg(debug_assertions)]
+        self.check_consistency();
+    }
+
+    /// Adds a point to the cloud, just like `push()`, but
+    /// giving it a stable ID that can be used to find it
+    /// later, even if its index changes.
+    ///
+    /// IDs are expected to be unique.
+    pub fn push_with_id(&mut self, p: Point2D, id: u64) {
+        self.history.record(Operation::Push {
+            point: p,
+            id: Some(id),
+        });
+        self.append(p);
+        let last = self.ids.len() - 1;
+        self.ids[last] = Some(id);
+    }
+
+    /// Finds the index of the point with a certain ID, if any
+    pub fn index_of_id(&self, id: u64) -> Option<usize> {
+        self.ids.iter().position(|i| *i == Some(id))
+    }
+
+    /// Connects the points with indexes `a` and `b`.
+    ///
+    /// Returns `false`, doing nothing, if any index is out of range,
+    /// if `a == b` (i.e., self-loops are not allowed) or if the points
+    /// were already connected.
+    pub fn connect(&mut self, a: usize, b: usize) -> bool {
+        if a == b || a >= self.points.len() || b >= self.points.len() {
+            return false;
+        }
+        let edge = (a.min(b), a.max(b));
+        if self.edges.contains(&edge) {
+            return false;
+        }
+        self.edges.push(edge);
+        true
+    }
+
+    /// Disconnects the points with indexes `a` and `b`, returning
+    /// `false` if they were not connected.
+    pub fn disconnect(&mut self, a: usize, b: usize) -> bool {
+        let edge = (a.min(b), a.max(b));
+        match self.edges.iter().position(|e| *e == edge) {
+            Some(i) => {
+                self.edges.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Checks whether the points with indexes `a` and `b` are connected
+    pub fn are_connected(&self, a: usize, b: usize) -> bool {
+        self.edges.contains(&(a.min(b), a.max(b)))
+    }
+
+    /// Cleans up the connections, returning how many were dropped: each one
+    /// is written with its smallest index first, and self-loops and duplicates
+    /// (even if written the other way around) are dropped.
+    ///
+    /// The connections kept stay in the same order. `connect()` keeps them
+    /// clean already, so this is only needed for those loaded from elsewhere.
+    pub fn normalize_edges(&mut self) -> usize {
+        let before = self.edges.len();
+        let mut seen = HashSet::with_capacity(before);
+        self.edges = self
+            .edges
+            .iter()
+            .map(|(a, b)| (*a.min(b), *a.max(b)))
+            .filter(|(a, b)| a != b && seen.insert((*a, *b)))
+            .collect();
+        before - self.edges.len()
+    }
+
+    /// Finds the point at a fraction `t` along the connection in position
+    /// `edge_index` of `connections()`, going from its first point (when `t`
+    /// is 0) to its second one (when `t` is 1), like `Point2D::lerp()`.
+    ///
+    /// Returns `None` if there is no such connection.
+    pub fn point_on_edge(&self, edge_index: usize, t: Float) -> Option<Point2D> {
+        let (a, b) = self.edges.get(edge_index)?;
+        Some(Point2D::lerp(&self.points[*a], &self.points[*b], t))
+    }
+
+    /// Projects `p` perpendicularly onto the (infinite) line going through the
+    /// points of the connection in position `edge_index` of `connections()`, so
+    /// that it can be snapped to the extension of that connection.
+    ///
+    /// Unlike `nearest_segment()`, this is not limited to the ends of the
+    /// connection. If its points are coincident, this returns that point, and
+    /// if there is no such connection, `p` itself.
+    pub fn snap_to_edge_line(&self, p: &Point2D, edge_index: usize) -> Point2D {
+        match self.edges.get(edge_index) {
+            Some((a, b)) => project_onto_line(p, &self.points[*a], &self.points[*b]),
+            None => *p,
+        }
+    }
+
+    /// Walks along the connections, emitting a point every `spacing` meters
+    /// (e.g., to animate something travelling along them).
+    ///
+    /// The connections need to form a single chain, either open or closed (see
+    /// `total_turning()`), or nothing is returned. Open chains are walked from
+    /// their end with the lowest index, and their other end is only sampled if
+    /// their length is a multiple of `spacing`. Closed chains are walked from
+    /// their lowest index, which is not sampled again at the end of the loop.
+    ///
+    /// Nothing is returned either if `spacing` is not positive.
+    pub fn sample_path(&self, spacing: Float) -> Vec<Point2D> {
+        let mut ret = Vec::new();
+        if spacing.is_nan() || spacing <= 0. {
+            return ret;
+        }
+        let (mut chain, is_closed) = match self.edge_chain() {
+            Some(chain) => chain,
+            None => return ret,
+        };
+        if is_closed {
+            chain.push(chain[0]);
+        }
+        let path: Vec<&Point2D> = chain.iter().map(|i| &self.points[*i]).collect();
+        let length: Float = path.windows(2).map(|w| w[0].distance_to(w[1])).sum();
+
+        // Allow for some rounding, so that lengths that are multiples
+        // of the spacing get (or skip) their last sample
+        let steps = length / spacing;
+        let rounded = steps.round();
+        let is_multiple = (steps - rounded).abs() < 1e-4;
+        let n = match (is_closed, is_multiple) {
+            (true, true) => rounded,
+            (true, false) => steps.ceil(),
+            (false, true) => rounded + 1.,
+            (false, false) => steps.floor() + 1.,
+        } as usize;
+
+        // Distance walked up to the start of the current segment
+        let mut walked = 0.;
+        let mut segment = 0;
+        for k in 0..n {
+            let d = (k as Float * spacing).min(length);
+            loop {
+                let segment_length = path[segment].distance_to(path[segment + 1]);
+                if d <= walked + segment_length || segment + 2 == path.len() {
+                    let t = if segment_length > 0. {
+                        (d - walked) / segment_length
+                    } else {
+                        0.
+                    };
+                    ret.push(Point2D::lerp(path[segment], path[segment + 1], t.min(1.)));
+                    break;
+                }
+                walked += segment_length;
+                segment += 1;
+            }
+        }
+        ret
+    }
+
+    /// Finds the point where the connections in positions `e1` and `e2` of
+    /// `connections()` cross (ends included), if they do.
+    ///
+    /// Returns `None` if any of them does not exist, or if they are parallel...
+    /// even when collinear and overlapping, as there is no single crossing point.
+    pub fn edge_intersection(&self, e1: usize, e2: usize) -> Option<Point2D> {
+        let (a, b) = self.edges.get(e1)?;
+        let (c, d) = self.edges.get(e2)?;
+        segment_intersection(
+            &self.points[*a],
+            &self.points[*b],
+            &self.points[*c],
+            &self.points[*d],
+        )
+    }
+
+    /// Samples a smooth (Catmull-Rom) spline going through the points along
+    /// the connections, with `samples_per_segment` points from each of them to
+    /// the next one (the first of which is the point itself).
+    ///
+    /// The connections need to form a single chain, either open or closed
+    /// (see `sample_path()`, which walks them the same way), or nothing is
+    /// returned. Closed chains give a closed spline, which does not repeat
+    /// its first point at the end. Open chains end with their last point, and
+    /// their ends are handled by reflecting their neighbours across them
+    /// (so the spline starts and ends going towards those neighbours).
+    pub fn spline_points(&self, samples_per_segment: usize) -> Vec<Point2D> {
+        let (chain, is_closed) = match self.edge_chain() {
+            Some(chain) => chain,
+            None => return Vec::new(),
+        };
+        let samples = samples_per_segment.max(1);
+        let vertices: Vec<Point2D> = chain.iter().map(|i| self.points[*i]).collect();
+        let n = vertices.len();
+
+        // The vertices around the segment starting at `i`
+        let vertex = |i: isize| -> Point2D {
+            if is_closed {
+                return vertices[i.rem_euclid(n as isize) as usize];
+            }
+            if i < 0 {
+                // Reflect the second one across the first one
+                vertices[0].scale(2.).sub(&vertices[1])
+            } else if i as usize >= n {
+                vertices[n - 1].scale(2.).sub(&vertices[n - 2])
+            } else {
+                vertices[i as usize]
+            }
+        };
+
+        let segments = if is_closed { n } else { n - 1 };
+        let mut ret = Vec::with_capacity(segments * samples + 1);
+        for i in 0..segments as isize {
+            let (p0, p1, p2, p3) = (vertex(i - 1), vertex(i), vertex(i + 1), vertex(i + 2));
+            ret.push(p1);
+            for k in 1..samples {
+                let t = k as Float / samples as Float;
+                ret.push(catmull_rom(&p0, &p1, &p2, &p3, t));
+            }
+        }
+        if !is_closed {
+            ret.push(vertices[n - 1]);
+        }
+        ret
+    }
+
+    /// Finds the position in `connections()` of the longest connection,
+    /// or `None` if there are none. Ties go to the first one.
+    pub fn longest_edge(&self) -> Option<usize> {
+        self.extreme_edge(|length, best| length > best)
+    }
+
+    /// Finds the position in `connections()` of the shortest connection,
+    /// or `None` if there are none. Ties go to the first one.
+    pub fn shortest_edge(&self) -> Option<usize> {
+        self.extreme_edge(|length, best| length < best)
+    }
+
+    /// Finds the position in `connections()` of the connection whose length
+    /// is `better()` than all others, skipping those of NaN length
+    fn extreme_edge(&self, better: impl Fn(Float, Float) -> bool) -> Option<usize> {
+        let mut ret: Option<(usize, Float)> = None;
+        for (i, (a, b)) in self.edges.iter().enumerate() {
+            // Squared lengths compare the same
+            let length = self.points[*a].squared_distance_to(&self.points[*b]);
+            if length.is_nan() {
+                continue;
+            }
+            if ret.is_none_or(|(_, best)| better(length, best)) {
+                ret = Some((i, length));
+            }
+        }
+        ret.map(|(i, _)| i)
+    }
+
+    /// Calculates the sum of the signed exterior angles (in radians) along the
+    /// closed loop formed by the connections, which is `2π` for a simple polygon
+    /// going counterclockwise and `-2π` for one going clockwise.
+    ///
+    /// The loop is followed from its lowest index towards the lowest of its
+    /// neighbours. Returns `None` if the connections are not a single closed loop.
+    pub fn total_turning(&self) -> Option<Float> {
+        let ring = self.edge_loop()?;
+        let n = ring.len();
+        let mut total = 0.;
+        for i in 0..n {
+            let prev = self.points[ring[(i + n - 1) % n]];
+            let current = self.points[ring[i]];
+            let next = self.points[ring[(i + 1) % n]];
+
+            let (ax, ay) = (current.x - prev.x, current.y - prev.y);
+            let (bx, by) = (next.x - current.x, next.y - current.y);
+            total += (ax * by - ay * bx).atan2(ax * bx + ay * by);
+        }
+        Some(total)
+    }
+
+    /// Checks whether `p` is inside the polygon formed by the closed loop of
+    /// connections, like `point_in_polygon()` does (i.e., points on its edges
+    /// are inside). This is meant for simple polygons, like rooms.
+    ///
+    /// Returns `false` if the connections are not a single closed loop.
+    pub fn point_in_loop(&self, p: &Point2D) -> bool {
+        match self.edge_loop() {
+            Some(ring) => {
+                let polygon: Vec<Point2D> = ring.iter().map(|i| self.points[*i]).collect();
+                point_in_polygon(p, &polygon)
+            }
+            None => false,
+        }
+    }
+
+    /// Calculates the angle (in radians, from 0 to `π`) between the two
+    /// connections of the point with index `point_index`, as seen from it
+    /// (e.g., `π / 2` at a right-angle corner, and `π` when they go straight).
+    ///
+    /// Returns `None` unless the point has exactly two connections.
+    pub fn vertex_angle(&self, point_index: usize) -> Option<Float> {
+        let mut neighbours = self.edges.iter().filter_map(|(a, b)| {
+            if *a == point_index {
+                Some(*b)
+            } else if *b == point_index {
+                Some(*a)
+            } else {
+                None
+            }
+        });
+        let (a, b) = (neighbours.next()?, neighbours.next()?);
+        if neighbours.next().is_some() {
+            return None;
+        }
+        Some(angle_at(
+            &self.points[point_index],
+            &self.points[a],
+            &self.points[b],
+        ))
+    }
+
+    /// Merges the pairs of connections meeting at points connected to nothing
+    /// else that (almost) go in a straight line, returning how many were merged.
+    ///
+    /// A pair is merged when the angle between them is within
+    /// `angle_tolerance_deg` degrees of 180. The point in the middle is
+    /// removed, and the points at the other ends get connected instead (unless
+    /// they already were, in which case that connection is kept as it is).
+    /// Like `remove_points_in_rect()`, the remaining points keep their
+    /// relative order, and this cannot be undone.
+    pub fn merge_collinear_edges(&mut self, angle_tolerance_deg: Float) -> usize {
+        let tolerance = angle_tolerance_deg.to_radians();
+        let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); self.points.len()];
+        for (a, b) in self.edges.iter() {
+            neighbours[*a].push(*b);
+            neighbours[*b].push(*a);
+        }
+
+        // Merging as we go, so that straight chains merge all the way
+        let mut edges = self.edges.clone();
+        let mut removed = Vec::new();
+        for p in 0..self.points.len() {
+            if neighbours[p].len() != 2 {
+                continue;
+            }
+            let (a, b) = (neighbours[p][0], neighbours[p][1]);
+            let angle = angle_at(&self.points[p], &self.points[a], &self.points[b]);
+            if std::f64::consts::PI as Float - angle > tolerance {
+                continue;
+            }
+
+            let edge = |i: usize, j: usize| (i.min(j), i.max(j));
+            edges.retain(|e| *e != edge(p, a) && *e != edge(p, b));
+            if neighbours[a].contains(&b) {
+                neighbours[a].retain(|n| *n != p);
+                neighbours[b].retain(|n| *n != p);
+            } else {
+                edges.push(edge(a, b));
+                for (i, j) in [(a, b), (b, a)].iter() {
+                    for n in neighbours[*i].iter_mut() {
+                        if *n == p {
+                            *n = *j;
+                        }
+                    }
+                }
+            }
+            neighbours[p].clear();
+            removed.push(p);
+        }
+
+        self.edges = edges;
+        self.remove_indexes(&removed)
+    }
+
+    /// Finds the indexes of the points that are not connected to any other one
+    pub fn isolated_points(&self) -> Vec<usize> {
+        let mut is_connected = vec![false; self.points.len()];
+        for (a, b) in self.edges.iter() {
+            is_connected[*a] = true;
+            is_connected[*b] = true;
+        }
+        (0..self.points.len())
+            .filter(|i| !is_connected[*i])
+            .collect()
+    }
+
+    /// Removes all the points that are not connected to any other one (see
+    /// `isolated_points()`) at once, returning how many were removed.
+    ///
+    /// Like `remove_points_in_rect()`, the remaining points keep their relative
+    /// order, and this cannot be undone.
+    pub fn remove_isolated(&mut self) -> usize {
+        let isolated = self.isolated_points();
+        self.remove_indexes(&isolated)
+    }
+
+    /// Removes the points in `removed` at once (see `remove_points_in_rect()`),
+    /// returning how many were removed
+    fn remove_indexes(&mut self, removed: &[usize]) -> usize {
+        if removed.is_empty() {
+            return 0;
+        }
+        self.history.clear();
+        self.invalidate_spatial_hash();
+
+        // The new index of each point, if it is kept
+        let n = self.points.len();
+        let mut is_removed = vec![false; n];
+        for i in removed.iter() {
+            is_removed[*i] = true;
+        }
+        let mut new_index: Vec<Option<usize>> = vec![None; n];
+        let mut kept = 0;
+        for i in 0..n {
+            if !is_removed[i] {
+                new_index[i] = Some(kept);
+                kept += 1;
+            }
+        }
+
+        let mut i = 0;
+        self.points.retain(|_| {
+            i += 1;
+            !is_removed[i - 1]
+        });
+        let mut i = 0;
+        self.ids.retain(|_| {
+            i += 1;
+            !is_removed[i - 1]
+        });
+        self.flash = self
+            .flash
+            .and_then(|(i, start, duration)| new_index[i].map(|i| (i, start, duration)));
+        // The new indexes keep the order, so the smallest one is still first
+        self.edges = self
+            .edges
+            .iter()
+            .filter_map(|(a, b)| Some((new_index[*a]?, new_index[*b]?)))
+            .collect();
+
+        if self.is_sorted {
+            self.rebuild_sorting();
+        }
+
+        #[cfg(debug_assertions)]
+        self.check_consistency();
+
+        removed.len()
+    }
+
+    /// Rotates the points in `indices` counterclockwise by `radians` around
+    /// their centroid, leaving the rest where they are. Indexes that are out of
+    /// range or repeated are skipped.
+    ///
+    /// The cloud does not keep track of what is selected, so the selection is
+    /// given as the indexes of its points, like in `highlight_selection()`.
+    ///
+    /// This sorts the points again only once (rather than once per point),
+    /// but it cannot be undone, so it clears the history (see `undo()`).
+    pub fn rotate_selection(&mut self, indices: Vec<usize>, radians: Float) {
+        let mut indices = indices;
+        indices.retain(|i| *i < self.points.len());
+        indices.sort_unstable();
+        indices.dedup();
+        let pivot = match average(self.selected_points(&indices)) {
+            Some(p) => p,
+            None => return,
+        };
+
+        self.history.clear();
+        self.invalidate_spatial_hash();
+        for i in indices {
+            self.points[i] = self.points[i].rotated_around(&pivot, radians);
+        }
+        if self.is_sorted {
+            self.rebuild_sorting();
+        }
+
+        #[cfg(debug_assertions)]
+        self.check_consistency();
+    }
+
+    /// Removes the point with index `point_index` from the cloud, returning it.
+    ///
+    /// In sorted clouds, the indexes of the points after it are shifted down
+    /// by one. In unsorted clouds, the last point takes its place (i.e., its
+    /// index becomes `point_index`). Returns `None` if the index is out of range.
+    ///
+    /// The connections of the removed point are dropped, and the rest are
+    /// updated to the new indexes.
+    pub fn remove_point(&mut self, point_index: usize) -> Option<Point2D> {
+        if point_index >= self.points.len() {
+            return None;
+        }
+        self.invalidate_spatial_hash();
+        self.history.record(Operation::Remove {
+            index: point_index,
+            point: self.points[point_index],
+            id: self.ids[point_index],
+            edges: self
+                .edges
+                .iter()
+                .filter(|(a, b)| *a == point_index || *b == point_index)
+                .copied()
+                .collect(),
+        });
+
+        if !self.is_sorted {
+            let last_index = self.points.len() - 1;
+            let p = self.points.swap_remove(point_index);
+            self.ids.swap_remove(point_index);
+            self.flash = match self.flash {
+                Some((i, _, _)) if i == point_index => None,
+                Some((i, start, duration)) if i == last_index => {
+                    Some((point_index, start, duration))
+                }
+                f => f,
+            };
+            self.edges
+                .retain(|(a, b)| *a != point_index && *b != point_index);
+            for (a, b) in self.edges.iter_mut() {
+                if *b == last_index {
+                    *b = point_index;
+                }
+                if *a > *b {
+                    std::mem::swap(a, b);
+                }
+            }
+            return Some(p);
+        }
+
+        let p = self.points.remove(point_index);
+        self.ids.remove(point_index);
+        self.flash = match self.flash {
+            Some((i, _, _)) if i == point_index => None,
+            Some((i, start, duration)) if i > point_index => Some((i - 1, start, duration)),
+            f => f,
+        };
+        self.edges
+            .retain(|(a, b)| *a != point_index && *b != point_index);
+        for (a, b) in self.edges.iter_mut() {
+            if *a > point_index {
+                *a -= 1
+            }
+            if *b > point_index {
+                *b -= 1
+            }
+        }
+
+        // Remove from X
+        let position_x = self.positions_x.remove(point_index);
+        self.sorted_x.remove(position_x);
+        for e in self.positions_x.iter_mut() {
+            if *e > position_x {
+                *e -= 1
+            }
+        }
+        for e in self.sorted_x.iter_mut() {
+            if *e > point_index {
+                *e -= 1
+            }
+        }
+
+        // Remove from Y
+        let position_y = self.positions_y.remove(point_index);
+        self.sorted_y.remove(position_y);
+        for e in self.positions_y.iter_mut() {
+            if *e > position_y {
+                *e -= 1
+            }
+        }
+        for e in self.sorted_y.iter_mut() {
+            if *e > point_index {
+                *e -= 1
+            }
+        }
+
+        #[cfg(debug_assertions)]
+        self.check_consistency();
+
+        Some(p)
+    }
+
+    /// Removes all the points within the rectangle going from `min` to `max`
+    /// (borders included, like `points_in_rect()`) at once, returning how many
+    /// were removed.
+    ///
+    /// The remaining points keep their relative order, with their indexes
+    /// shifted down to fill the gaps (in both sorted and unsorted clouds).
+    /// Their IDs and connections come along, while the connections of the
+    /// removed points are dropped. Removing points this way cannot be undone,
+    /// so it clears the history (see `undo()`).
+    pub fn remove_points_in_rect(&mut self, min: &Point2D, max: &Point2D) -> usize {
+        let removed = self.points_in_rect(min, max);
+        self.remove_indexes(&removed)
+    }
+
+    /// Updates the Y element of a point in the cloud
+    pub fn update_point_y(&mut self, point_index: usize, new_y: Float) {
+        let from = self.points[point_index];
+        self.history.record(Operation::Update {
+            index: point_index,
+            from,
+            to: Point2D::new(from.x, new_y),
+        });
+        self.move_point_y(point_index, new_y);
+    }
+
+    /// Moves a point in the Y axis, without recording it in the history
+    fn move_point_y(&mut self, point_index: usize, new_y: Float) {
+        // We only care about positions when this is sorted
+        if self.is_sorted {
+
+            let old_y_position = self.positions_y[point_index];
+            let mut new_y_position = match self.find_point_position_y(new_y){
+                Ok(i)=>i,
+                Err(e)=> panic!("{}",e)
+            };
+    
+            if old_y_position > new_y_position {
+                // moving down
+    
+                // update positions_y
+                for e in self.positions_y.iter_mut() {
+                    if *e == old_y_position {
+                        *e = new_y_position
+                    } else if *e >= new_y_position && *e < old_y_position {
+                        *e += 1
+                    }
+                }
+    
+                // update sorted_y
+                for i in (new_y_position + 1..old_y_position + 1).rev() {
+                    self.sorted_y[i] = self.sorted_y[i - 1];
+                }
+                self.sorted_y[new_y_position] = point_index;
+            } else if old_y_position < new_y_position {
+                // Moving up
+    
+                // update, because the new position was found
+                // including the point that is being moved.
+                new_y_position -= 1;
+    
+                // update positions_y
+                for e in self.positions_y.iter_mut() {
+                    if *e == old_y_position {
+                        *e = new_y_position
+                    } else if *e <= new_y_position && *e > old_y_position {
+                        *e -= 1
+                    }
+                }
+                // update sorted_y
+                for i in old_y_position..new_y_position {
+                    self.sorted_y[i] = self.sorted_y[i + 1];
+                }
+                self.sorted_y[new_y_position] = point_index;
+            }
+        }// end of is_sorted?
+        
+
+        // Update point
+        self.points[point_index].y = new_y;
+        self.invalidate_spatial_hash();
+
+        /* VERIFY */
+        #[cfg(debug_assertions)]
+        self.check_consistency();
+    }
+
+    /// Updates the X element of a point in the cloud
+    pub fn update_point_x(&mut self, point_index: usize, new_x: Float) {
+        let from = self.points[point_index];
+        self.history.record(Operation::Update {
+            index: point_index,
+            from,
+            to: Point2D::new(new_x, from.y),
+        });
+        self.move_point_x(point_index, new_x);
+    }
+
+    /// Moves a point in the X axis, without recording it in the history
+    fn move_point_x(&mut self, point_index: usize, new_x: Float) {
+        
+        
+        if self.is_sorted{
+
+            let old_x_position = self.positions_x[point_index];
+            let mut new_x_position = match self.find_point_position_x(new_x){
+                Ok(i)=>i,
+                Err(e)=> panic!("{}",e)
+            };
+    
+            if old_x_position > new_x_position {
+                // moving left
+                // update positions_x... iterate backwards
+                for e in self.positions_x.iter_mut() {
+                    if *e == old_x_position {
+                        *e = new_x_position
+                    } else if *e >= new_x_position && *e < old_x_position {
+                        *e += 1
+                    }
+                }
+    
+                // update sorted_x
+                for i in (new_x_position + 1..old_x_position + 1).rev() {
+                    self.sorted_x[i] = self.sorted_x[i - 1];
+                }
+                self.sorted_x[new_x_position] = point_index;
+            } else if old_x_position < new_x_position {
+                // Moving right
+    
+                // update, because the new position was found
+                // including the point that is being moved.
+                new_x_position -= 1;
+    
+                // update positions_x
+                for e in self.positions_x.iter_mut() {
+                    if *e == old_x_position {
+                        *e = new_x_position
+                    } else if *e <= new_x_position && *e > old_x_position {
+                        *e -= 1
+                    }
+                }
+    
+                // update sorted_x
+                for i in old_x_position..new_x_position {
+                    self.sorted_x[i] = self.sorted_x[i + 1];
+                }
+                self.sorted_x[new_x_position] = point_index;
+            }
+        }
+
+
+        // Update point
+        self.points[point_index].x = new_x;
+        self.invalidate_spatial_hash();
+
+        /* VERIFY */
+        #[cfg(debug_assertions)]
+        self.check_consistency();
+    }
+
+    /// Updates the X and Y position of points in point_index
+    ///
+    /// Nothing is done if the point moves less than the update
+    /// tolerance (see `set_update_tolerance()`) in both axes.
+    pub fn update_point(&mut self, point_index: usize, new_p: Point2D) {
+        let old_p = self.points[point_index];
+        if old_p.approx_eq(&new_p, self.update_tolerance) {
+            return;
+        }
+        self.history.record(Operation::Update {
+            index: point_index,
+            from: old_p,
+            to: new_p,
+        });
+        self.move_point(point_index, new_p);
+    }
+
+    /// Sets how far (in meters, in each axis) a point needs to go for `update_point()`
+    /// to actually move it, which avoids updates caused by sub-pixel jitter.
+    ///
+    /// This is `Float::EPSILON` by default.
+    pub fn set_update_tolerance(&mut self, tolerance: Float) {
+        self.update_tolerance = tolerance;
+    }
+
+    /// Sets how many changes to the points (i.e., `push()`, `remove_point()`,
+    /// the updates and the translations) are kept so they can be undone with
+    /// `undo()`, forgetting the oldest ones beyond that.
+    ///
+    /// This is zero by default, which means that nothing can be undone.
+    pub fn set_history_depth(&mut self, depth: usize) {
+        self.history.set_depth(depth);
+    }
+
+    /// Undoes the latest change to the points, returning whether
+    /// there was anything to undo.
+    ///
+    /// Undoing a removal brings back the point (in the same index), its ID
+    /// and its connections. The connections are not recorded otherwise, so
+    /// undoing a push drops those of the point, and redoing it does not bring
+    /// them back. Undoing `translate_all()` moves the points back
+    /// by the same offset, which might not be exactly where they were
+    /// because of rounding.
+    pub fn undo(&mut self) -> bool {
+        let operation = match self.history.pop_done() {
+            Some(op) => op,
+            None => return false,
+        };
+        let history = std::mem::take(&mut self.history);
+        self.revert(&operation);
+        self.history = history;
+        self.history.push_undone(operation);
+        true
+    }
+
+    /// Does the latest undone change again, returning whether there was
+    /// anything to redo. Changes made after undoing cannot be redone.
+    pub fn redo(&mut self) -> bool {
+        let operation = match self.history.pop_undone() {
+            Some(op) => op,
+            None => return false,
+        };
+        let history = std::mem::take(&mut self.history);
+        self.reapply(&operation);
+        self.history = history;
+        self.history.push_done(operation);
+        true
+    }
+
+    /// Makes `test_world_point()` (and `test_world_point_within()`) find the
+    /// candidate points through a spatial hash: a grid of square cells of
+    /// `cell_size` (in meters) bucketing the points, which is checked around
+    /// the cell containing the cursor.
+    ///
+    /// This is faster than the default search on large clouds whose points are
+    /// packed along an axis, and works best when `cell_size` is close to the
+    /// searched distance. The hash is built on the first search, and again
+    /// after the points change. A `cell_size` that is not positive goes back
+    /// to the default search.
+    pub fn use_spatial_hash(&mut self, cell_size: Float) {
+        self.invalidate_spatial_hash();
+        self.spatial_hash_cell_size = if cell_size > 0. && cell_size.is_finite() {
+            Some(cell_size)
+        } else {
+            None
+        };
+    }
+
+    /// Moves a point 
+    pub fn translate_point(&mut self, point_index: usize, x_movement: Float, y_movement:Float){
+        let px = self.points[point_index].x;
+        let py = self.points[point_index].y;
+        self.update_point(point_index, Point2D::new(px+x_movement, py+y_movement));
+    } 
+
+    /// Moves all the points by the same offset.
+    ///
+    /// This does not need to sort anything again, as moving all the points
+    /// the same way keeps them in the same order.
+    pub fn translate_all(&mut self, dx: Float, dy: Float) {
+        self.history.record(Operation::TranslateAll { dx, dy });
+        for p in self.points.iter_mut() {
+            p.x += dx;
+            p.y += dy;
+        }
+        self.invalidate_spatial_hash();
+
+        #[cfg(debug_assertions)]
+        self.check_consistency();
+    }
+
+    /// Removes all the points (and their IDs and connections), keeping
+    /// the allocated memory for the new ones and whether the cloud is sorted.
+    ///
+    /// This cannot be undone, so it clears the history (see `undo()`).
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.positions_x.clear();
+        self.positions_y.clear();
+        self.sorted_x.clear();
+        self.sorted_y.clear();
+        self.ids.clear();
+        self.edges.clear();
+        self.flash = None;
+        self.invalidate_spatial_hash();
+        self.history.clear();
+    }
+
+    /// Adds the points of `other` after the ones in this cloud, which is the
+    /// same as pushing them one by one (but faster). Their IDs and connections
+    /// come along too, the latter updated to the new indexes.
+    ///
+    /// The result is unsorted if any of the clouds is unsorted. This
+    /// cannot be undone, so it clears the history (see `undo()`).
+    pub fn merge(&mut self, other: &PointCloud2D) {
+        self.history.clear();
+        let offset = self.points.len();
+        self.points.extend_from_slice(&other.points);
+        self.ids.extend_from_slice(&other.ids);
+        self.edges
+            .extend(other.edges.iter().map(|(a, b)| (a + offset, b + offset)));
+        self.invalidate_spatial_hash();
+
+        if self.is_sorted && other.is_sorted {
+            let points = &self.points;
+            self.sorted_x = merge_sorted(&self.sorted_x, &other.sorted_x, offset, |i| points[i].x);
+            self.sorted_y = merge_sorted(&self.sorted_y, &other.sorted_y, offset, |i| points[i].y);
+            self.positions_x = vec![0; points.len()];
+            for (position, index) in self.sorted_x.iter().enumerate() {
+                self.positions_x[*index] = position;
+            }
+            self.positions_y = vec![0; points.len()];
+            for (position, index) in self.sorted_y.iter().enumerate() {
+                self.positions_y[*index] = position;
+            }
+        } else {
+            self.is_sorted = false;
+            self.sorted_x.clear();
+            self.sorted_y.clear();
+            self.positions_x.clear();
+            self.positions_y.clear();
+        }
+
+        #[cfg(debug_assertions)]
+        self.check_consistency();
+    }
+
+    /// Moves the point with index `point_index` to the projection of `cursor`
+    /// onto the (infinite) line going through `line_a` and `line_b`, so that
+    /// dragging it follows that line.
+    ///
+    /// If `line_a` and `line_b` are coincident, the point goes to `line_a`.
+    pub fn project_drag_onto_line(
+        &mut self,
+        point_index: usize,
+        line_a: &Point2D,
+        line_b: &Point2D,
+        cursor: &Point2D,
+    ) {
+        let new_p = project_onto_line(cursor, line_a, line_b);
+        self.update_point(point_index, new_p);
+    }
+
+    /// Checks whether a point P is very close to
+    /// another point in the Cloud
+    ///
+    /// The way this works is as follows:
+    /// 1. Find the points that might be close enough (i.e., within the p +- MAX_DISTANCE square)
+    /// 2. Check which direction contains less points (i.e., X or Y)
+    /// 3. Iterate the candidate points, checking the distance. If smallest so far, mark for return
+    pub fn test_world_point(&self, p: &Point2D) -> Option<usize> {
+        self.nearest_world_point(p).map(|(i, _)| i)
+    }
+
+    /// Like `test_world_point()`, but checking whether P is closer than
+    /// `max_distance` to another point in the Cloud.
+    ///
+    /// A `max_distance` of zero only matches points in the exact same position as P.
+    pub fn test_world_point_within(&self, p: &Point2D, max_distance: Float) -> Option<usize> {
+        self.nearest_within(p, max_distance).map(|(i, _)| i)
+    }
+
+    /// Snaps `p` (e.g., the cursor) to the closest point in the Cloud within
+    /// `point_radius`, if any, or to the closest node of a grid with a
+    /// certain `grid_spacing` otherwise (see `Drawer2D::snap_to_grid()`).
+    ///
+    /// A `point_radius` or `grid_spacing` that is not positive disables
+    /// that kind of snapping, so disabling both returns `p` unchanged.
+    pub fn snap(&self, p: &Point2D, point_radius: Float, grid_spacing: Float) -> Point2D {
+        if point_radius > 0. {
+            if let Some(i) = self.test_world_point_within(p, point_radius) {
+                return self.points[i];
+            }
+        }
+        snap_to_grid(p, grid_spacing)
+    }
+
+    /// Like `test_world_point()`, but with a configurable distance and
+    /// checking at most `max_candidates` points.
+    ///
+    /// This caps the cost of the search in very dense regions, but makes it an
+    /// approximation: once the cap is reached, the closest point found so far is
+    /// returned, which might not be the actual closest one. The candidates are
+    /// found like in `test_world_point()` (e.g., unsorted clouds without a spatial
+    /// hash check their points in order, up to the cap).
+    pub fn test_world_point_capped(
+        &self,
+        p: &Point2D,
+        max_distance: Float,
+        max_candidates: usize,
+    ) -> Option<usize> {
+        let candidates = self.candidates(p, max_distance).take(max_candidates);
+        self.nearest_of(p, candidates, max_distance).map(|(i, _)| i)
+    }
+
+    /// Finds the closest point to `p`, within `max_distance`, that lies
+    /// on the left side of the directed line going from `line_a` to `line_b`.
+    ///
+    /// The side is given by the sign of the cross product between `line_b - line_a`
+    /// and `point - line_a`; points exactly on the line are not on the left side.
+    /// The candidates are found like in `test_world_point()`, so this works on
+    /// sorted and unsorted clouds, and uses the spatial hash if there is one.
+    pub fn nearest_in_halfplane(
+        &self,
+        p: &Point2D,
+        line_a: &Point2D,
+        line_b: &Point2D,
+        max_distance: Float,
+    ) -> Option<usize> {
+        let dx = line_b.x - line_a.x;
+        let dy = line_b.y - line_a.y;
+
+        // Filter by side
+        let candidates = self.candidates(p, max_distance).filter(|i| {
+            let other_p = &self.points[*i];
+            let cross = dx * (other_p.y - line_a.y) - dy * (other_p.x - line_a.x);
+            cross > 0.0
+        });
+        self.nearest_of(p, candidates, max_distance).map(|(i, _)| i)
+    }
+
+    /// Calculates, for each point, the distance to its closest neighbour.
+    ///
+    /// A point without neighbours (i.e., the only point in the cloud) has
+    /// an infinite distance, so a single-point cloud returns `[INFINITY]`
+    ///
+    /// When sorted, the points are swept along the X axis, stopping
+    /// as soon as the horizontal distance alone is larger than the
+    /// closest distance found so far.
+    pub fn nearest_neighbor_distances(&self) -> Vec<Float> {
+        let n = self.points.len();
+        let mut ret = Vec::with_capacity(n);
+
+        if !self.is_sorted {
+            // Brute force
+            for (i, p) in self.points.iter().enumerate() {
+                let mut min_sq = Float::INFINITY;
+                for (j, other) in self.points.iter().enumerate() {
+                    if i != j {
+                        min_sq = min_sq.min(p.squared_distance_to(other));
+                    }
+                }
+                ret.push(min_sq.sqrt());
+            }
+            return ret;
+        }
+
+        for (i, p) in self.points.iter().enumerate() {
+            let position = self.positions_x[i];
+            let mut min_sq = Float::INFINITY;
+
+            // Go right
+            for other_index in self.sorted_x.iter().skip(position + 1) {
+                let other = &self.points[*other_index];
+                let dx = other.x - p.x;
+                if dx * dx >= min_sq {
+                    break;
+                }
+                min_sq = min_sq.min(p.squared_distance_to(other));
+            }
+
+            // Go left
+            for other_index in self.sorted_x[..position].iter().rev() {
+                let other = &self.points[*other_index];
+                let dx = p.x - other.x;
+                if dx * dx >= min_sq {
+                    break;
+                }
+                min_sq = min_sq.min(p.squared_distance_to(other));
+            }
+
+            ret.push(min_sq.sqrt());
+        }
+
+        ret
+    }
+
+    /// Flashes the point with index `index`, starting at time `now_ms` and lasting `duration_ms`.
+    ///
+    /// The flash is drawn by `draw_flash()`; only one point flashes at a time.
+    pub fn flash_point(&mut self, index: usize, now_ms: f64, duration_ms: f64) {
+        self.flash = Some((index, now_ms, duration_ms));
+    }
+
+    /// Draws the flashing point (if any), fading from the highlight
+    /// colour at the beginning of the flash towards the colour of the
+    /// point underneath it at the end.
+    pub fn draw_flash(&self, drawer: &Drawer2D, now_ms: f64) {
+        let (index, start, duration) = match self.flash {
+            Some(f) => f,
+            None => return,
+        };
+        let opacity = flash_opacity(start, duration, now_ms);
+        if opacity <= 0. || index >= self.points.len() {
+            return;
+        }
+
+        drawer.context().set_global_alpha(opacity);
+        self.highlight_point(drawer, index);
+        drawer.context().set_global_alpha(1.);
+    }
+
+    /// Highlights a point by showing it on a different colour    
+    pub fn highlight_point(&self, drawer: &Drawer2D, i: usize) {
+        self.draw_highlighted(drawer, &self.points[i]);
+    }
+
+    /// Highlights all the points in `indices`, like `highlight_points()`
+    /// does. This takes a `Vec` so it can receive a `Uint32Array` from
+    /// JavaScript in a single call.
+    pub fn highlight_selection(&self, drawer: &Drawer2D, indices: Vec<usize>) {
+        self.highlight_points(drawer, &indices);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Random;
+
+    #[test]
+    fn test_find_point_position_x() {
+        /******************************** */
+        /* Empty case... should return 0. */
+        /******************************** */
+        let cloud = PointCloud2D::new();
+        cloud.check_consistency();
+        assert_eq!(cloud.find_point_position_x(1.).unwrap(), 0);
+
+        /******************************** */
+        /* 1 point case. */
+        /******************************** */
+
+        // X axis is | ----- O ------
+        //                 (0,0)
+        let cloud = PointCloud2D {
+            points: vec![Point2D { x: 0.0, y: 0.0 }],
+            positions_x: vec![0],
+            positions_y: vec![0],
+            sorted_x: vec![0],
+            sorted_y: vec![0],
+            ids: vec![None; 1],
+            is_sorted:true,
+            ..PointCloud2D::new()
+        };
+        cloud.check_consistency();
+
+        // Testing case:
+        // X axis is   - P ---------- O ------
+        //            (-1,0)        (0,0)
+        assert_eq!(cloud.find_point_position_x(-1.).unwrap(), 0);
+
+        // Testing case:
+        // X axis is   ---- O ------ P
+        //                (0,0)    (1,0)
+        assert_eq!(cloud.find_point_position_x(1.).unwrap(), 1);
+
+        // Testing case:
+        // X axis is   ---- OP ------
+        //                (0,0)
+        //                (0,0)
+        assert_eq!(cloud.find_point_position_x(0.).unwrap(), 1);
+
+        /******************************** */
+        /*  2 point cases */
+        /******************************** */
+        // X axis is | ----- O --------- O --
+        //                 (0,0)        (1,0)
+        let cloud = PointCloud2D {
+            points: vec![Point2D { x: 0.0, y: 0.0 }, Point2D { x: 1., y: 0. }],
+            positions_x: vec![0, 1],
+            positions_y: vec![0, 1],
+            sorted_x: vec![0, 1],
+            sorted_y: vec![0, 1],
+            ids: vec![None; 2],
+            is_sorted:true,
+            ..PointCloud2D::new()
+        };
+        cloud.check_consistency();
+
+        // Test case |   P --------- O --------- O --
+        //             (-1,0)      (0,0)        (1,0)
         assert_eq!(cloud.find_point_position_x(-1.).unwrap(), 0);
 
-        // Testing case:
-        // X axis is   ---- O ------ P
-        //                (0,0)    (1,0)
-        assert_eq!(cloud.find_point_position_x(1.).unwrap(), 1);
+        // Test case |   -- OP --------- O --
+        //                (0,0)        (1,0)
+        //                (0,0)
+        assert_eq!(cloud.find_point_position_x(0.).unwrap(), 1);
+
+        // Test case |   ----- O ----- P --------- O --
+        //                   (0,0)    (0.5,0)    (1,0)
+        assert_eq!(cloud.find_point_position_x(0.5).unwrap(), 1);
+
+        // Test case |   -- O --------- OP--
+        //                (0,0)        (1,0)
+        //                             (1,0)
+        assert_eq!(cloud.find_point_position_x(1.).unwrap(), 2);
+
+        // Test case |   -- O --------- O ------- P
+        //                (0,0)        (1,0)     (2,0)
+        assert_eq!(cloud.find_point_position_x(2.).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_find_point_position_y() {
+        /******************************** */
+        /* Empty case... should return 0. */
+        /******************************** */
+        let cloud = PointCloud2D::new();
+        cloud.check_consistency();
+        assert_eq!(cloud.find_point_position_y(1.).unwrap(), 0);
+
+        /******************************** */
+        /* 1 point case. */
+        /******************************** */
+
+        // Y axis is | ----- O ------
+        //                 (0,0)
+        let cloud = PointCloud2D {
+            points: vec![Point2D { x: 0.0, y: 0.0 }],
+            positions_x: vec![0],
+            positions_y: vec![0],
+            sorted_x: vec![0],
+            sorted_y: vec![0],
+            ids: vec![None; 1],
+            is_sorted:true,
+            ..PointCloud2D::new()
+        };
+        cloud.check_consistency();
+
+        // Testing case:
+        // Y axis is   - P ---------- O ------
+        //            (0,-1)        (0,0)
+        assert_eq!(cloud.find_point_position_y(-1.).unwrap(), 0);
+
+        // Testing case:
+        // Y axis is   ---- O ------ P
+        //                (0,0)    (0,1)
+        assert_eq!(cloud.find_point_position_y(1.).unwrap(), 1);
+
+        // Testing case:
+        // Y axis is   ---- OP ------
+        //                (0,0)
+        //                (0,0)
+        assert_eq!(cloud.find_point_position_y(0.).unwrap(), 1);
+
+        /******************************** */
+        /*  2 point cases */
+        /******************************** */
+        // X axis is | ----- O --------- O --
+        //                 (0,0)        (0,1)
+        let cloud = PointCloud2D {
+            points: vec![Point2D { x: 0.0, y: 0.0 }, Point2D { x: 0., y: 1. }],
+            positions_x: vec![0, 1],
+            positions_y: vec![0, 1],
+            sorted_x: vec![0, 1],
+            sorted_y: vec![0, 1],
+            ids: vec![None; 2],
+            is_sorted:true,
+            ..PointCloud2D::new()
+        };
+        cloud.check_consistency();
+
+        // Test case |   P --------- O --------- O --
+        //             (0,-1)      (0,0)        (0,1)
+        assert_eq!(cloud.find_point_position_y(-1.).unwrap(), 0);
+
+        // Test case |   -- OP --------- O --
+        //                (0,0)        (0,1)
+        //                (0,0)
+        assert_eq!(cloud.find_point_position_y(0.).unwrap(), 1);
+
+        // Test case |   ----- O ----- P --------- O --
+        //                   (0,0)  (0,0.5)      (0,1)
+        assert_eq!(cloud.find_point_position_y(0.5).unwrap(), 1);
+
+        // Test case |   -- O --------- OP--
+        //                (0,0)        (0,1)
+        //                             (0,1)
+        assert_eq!(cloud.find_point_position_y(1.).unwrap(), 2);
+
+        // Test case |   -- O --------- O ------- P
+        //                (0,0)       (0,1)     (0,2)
+        assert_eq!(cloud.find_point_position_y(2.).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_push() {
+        /******************************** */
+        /* Empty case... should return 0. */
+        /******************************** */
+        let mut cloud = PointCloud2D::new();
+        cloud.check_consistency();
+        let p = Point2D { x: 0., y: 0. };
+        cloud.push(p);
+        assert_eq!(cloud.sorted_x, vec![0]);
+        assert_eq!(cloud.sorted_y, vec![0]);
+        assert_eq!(cloud.points, vec![p]);
+        cloud.check_consistency();
+
+        /******************************** */
+        /* 1 point case. */
+        /******************************** */
+
+        // X axis is | ----- O ------
+        //                 (0,0)
+        let mut cloud = PointCloud2D {
+            points: vec![Point2D { x: 0.0, y: 0.0 }],
+            positions_x: vec![0],
+            positions_y: vec![0],
+            sorted_x: vec![0],
+            sorted_y: vec![0],
+            ids: vec![None; 1],
+            is_sorted:true,
+            ..PointCloud2D::new()
+        };
+        cloud.check_consistency();
+
+        // Testing case:
+        // X axis is   - P ---------- O ------
+        //            (-1,0)        (0,0)
+        let p = Point2D { x: -1.0, y: 0.0 };
+        cloud.push(p);
+        assert_eq!(cloud.sorted_x, vec![1, 0]);
+        assert_eq!(cloud.sorted_y, vec![0, 1]);
+        assert_eq!(cloud.points, vec![Point2D { x: 0.0, y: 0.0 }, p]);
+        cloud.check_consistency();
+    }
+
+    #[test]
+    fn test_update_point() {
+        let a = Point2D { x: 0.0, y: 0.0 };
+        let b = Point2D { x: 1.0, y: 0.0 };
+        let c = Point2D { x: 2.0, y: 0.0 };
+
+        /******************************** */
+        /*  3 points cases */
+        /******************************** */
+        // X axis is | ----- O --------- O -------- O
+        //                 A(0,0)        B(1,0)     C(2,0)
+        let mut cloud = PointCloud2D {
+            points: vec![a, b, c],
+            positions_x: vec![0, 1, 2],
+            positions_y: vec![0, 1, 2],
+            sorted_x: vec![0, 1, 2],
+            sorted_y: vec![0, 1, 2],
+            ids: vec![None; 3],
+            is_sorted:true,
+            ..PointCloud2D::new()
+        };
+        cloud.check_consistency();
+
+        // Move A to the left (nothing should happen)
+        let new_a = Point2D { x: -1., y: 0. };
+        cloud.update_point(0, new_a);
+        assert_eq!(cloud.positions_x, vec![0, 1, 2]);
+        assert_eq!(cloud.sorted_x, vec![0, 1, 2]);
+        assert_eq!(cloud.points[0], new_a);        
+        // Does not change (we did not even touched sorted_y and positions_y)
+        assert_eq!(cloud.positions_y, vec![0,1,2]);
+
+        // Move A to the very right... new order is [b,c,a]
+        let new_a = Point2D { x: 12., y: 0. };
+        cloud.update_point(0, new_a);
+        assert_eq!(cloud.positions_x, vec![2, 0, 1]);
+        assert_eq!(cloud.sorted_x, vec![1, 2, 0]);        
+        assert_eq!(cloud.sorted_y, vec![0, 1, 2]);
+        assert_eq!(cloud.positions_y, vec![0, 1, 2]);
+        assert_eq!(cloud.points[0], new_a);
+    }
+
+    #[test]
+    fn test_test_point() {
+        let n_points = 20;
+
+        /* ************** */
+        /* ALL HORIZONTAL */
+        /* ************** */
+        let mut cloud = PointCloud2D::new();
+        for i in 0..n_points {
+            cloud.push(Point2D::new(i as Float, 0.0));
+        }
+        // These are out of the clould altogether
+        let p = Point2D::new(-10.0, 0.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        let p = Point2D::new(100.0, 0.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        let p = Point2D::new(0.0, -1.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        let p = Point2D::new(0.0, 1.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        let p = Point2D::new(30.0, 2.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        // These are in
+        for i in 0..n_points {
+            let p = Point2D::new(i as Float, 0.0);
+            assert_eq!(cloud.test_world_point(&p), Some(i));
+        }
+
+        /* ************** */
+        /* ALL VERTICAL */
+        /* ************** */
+        let mut cloud = PointCloud2D::new();
+        for i in 0..n_points {
+            cloud.push(Point2D::new(0.0, i as Float));
+        }
+        // These are out of the clould altogether
+        let p = Point2D::new(0.0, -10.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        let p = Point2D::new(0.0, 110.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        let p = Point2D::new(1.0, 0.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        let p = Point2D::new(-1.0, 0.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        let p = Point2D::new(30.0, 2.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        // These are in
+        for i in 0..n_points {
+            let p = Point2D::new(0.0, i as Float);
+            assert_eq!(cloud.test_world_point(&p), Some(i));
+        }
+
+        /* ************** */
+        /* 45 DEGREES */
+        /* ************** */
+
+        let mut cloud = PointCloud2D::new();
+        for i in 0..n_points {
+            cloud.push(Point2D::new(i as Float, i as Float));
+        }
+
+        // These are out of the clould altogether
+        let p = Point2D::new(0.0, -10.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        let p = Point2D::new(0.0, 110.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        let p = Point2D::new(1.0, 0.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        let p = Point2D::new(-1.0, 0.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        let p = Point2D::new(30.0, 2.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+
+        // These are in
+        for i in 0..n_points {
+            let p = Point2D::new(i as Float, i as Float);
+            assert_eq!(cloud.test_world_point(&p), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_sort_order() {
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(3.0, -1.0));
+        cloud.push(Point2D::new(-2.0, 5.0));
+        cloud.push(Point2D::new(0.5, 2.0));
+        cloud.push(Point2D::new(1.0, -3.0));
+
+        let order_x = cloud.sort_order_x();
+        let order_y = cloud.sort_order_y();
+        assert_eq!(order_x, vec![1, 2, 3, 0]);
+        assert_eq!(order_y, vec![3, 0, 2, 1]);
+
+        // They are permutations of the indexes...
+        for order in [&order_x, &order_y].iter() {
+            let mut indexes = (*order).clone();
+            indexes.sort_unstable();
+            assert_eq!(indexes, vec![0, 1, 2, 3]);
+        }
+
+        // ... that are sorted in ascending order
+        for w in order_x.windows(2) {
+            assert!(cloud.points[w[0]].x <= cloud.points[w[1]].x);
+        }
+        for w in order_y.windows(2) {
+            assert!(cloud.points[w[0]].y <= cloud.points[w[1]].y);
+        }
+
+        // Unsorted clouds do not track the order
+        let mut cloud = PointCloud2D::new_unsorted();
+        cloud.push(Point2D::new(3.0, -1.0));
+        assert!(cloud.sort_order_x().is_empty());
+        assert!(cloud.sort_order_y().is_empty());
+    }
+
+    #[test]
+    fn test_total_ordering() {
+        /* Signed zeroes */
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(0.0, 0.0));
+        // -0.0 is the same as +0.0, so both go after the existing +0.0
+        assert_eq!(cloud.find_point_position_x(-0.0).unwrap(), 1);
+        assert_eq!(cloud.find_point_position_y(-0.0).unwrap(), 1);
+        assert_eq!(cloud.find_point_position_x(0.0).unwrap(), 1);
+        assert_eq!(cloud.find_point_position_y(0.0).unwrap(), 1);
+
+        cloud.push(Point2D::new(-0.0, -0.0));
+        assert_eq!(cloud.sorted_x, vec![0, 1]);
+        assert_eq!(cloud.sorted_y, vec![0, 1]);
+        cloud.check_consistency();
+
+        // Queries find points at -0.0 as if they were at +0.0, sorted or not
+        for sorted in [true, false].iter() {
+            let mut cloud = if *sorted {
+                PointCloud2D::new()
+            } else {
+                PointCloud2D::new_unsorted()
+            };
+            cloud.push(Point2D::new(-0.0, 0.0));
+            cloud.push(Point2D::new(1.0, -0.0));
+            let origin = Point2D::new(0.0, 0.0);
+
+            assert_eq!(cloud.test_world_point_within(&origin, 0.0), Some(0));
+            assert_eq!(
+                cloud.points_in_rect(&origin, &Point2D::new(1.0, 1.0)),
+                vec![0, 1]
+            );
+            assert_eq!(
+                cloud.points_in_rect(&Point2D::new(-1.0, -1.0), &Point2D::new(0.0, 0.0)),
+                vec![0]
+            );
+            assert_eq!(cloud.points_at_x(0.0, 0.0), vec![0]);
+            assert_eq!(cloud.points_at_y(0.0, 0.0), vec![0, 1]);
+            assert_eq!(cloud.points_at_x(-0.0, 0.0), vec![0]);
+
+            // No duplicates
+            assert_eq!(cloud.push_unique(origin, 0.0), Some(0));
+            assert_eq!(cloud.push_unique(Point2D::new(1.0, 0.0), 0.0), Some(1));
+            assert_eq!(cloud.len(), 2);
+        }
+
+        /* Adjacent representable values */
+        let a: Float = 1.0;
+        let next = Float::from_bits(a.to_bits() + 1);
+        let prev = Float::from_bits(a.to_bits() - 1);
+        assert!(prev < a && a < next);
+
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(next, next));
+        cloud.push(Point2D::new(prev, prev));
+        cloud.push(Point2D::new(a, a));
+        assert_eq!(cloud.sorted_x, vec![1, 2, 0]);
+        assert_eq!(cloud.sorted_y, vec![1, 2, 0]);
+        cloud.check_consistency();
+
+        assert_eq!(cloud.find_point_position_x(a).unwrap(), 2);
+        assert_eq!(cloud.find_point_position_y(prev).unwrap(), 1);
+
+        /* Repeated values go after all the existing ones */
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(2.0, 2.0));
+        for _ in 0..8 {
+            cloud.push(Point2D::new(1.0, 1.0));
+        }
+        cloud.push(Point2D::new(0.0, 0.0));
+        assert_eq!(cloud.find_point_position_x(1.0).unwrap(), 9);
+        assert_eq!(cloud.find_point_position_y(1.0).unwrap(), 9);
+        assert_eq!(cloud.sorted_x, vec![9, 1, 2, 3, 4, 5, 6, 7, 8, 0]);
+        assert_eq!(cloud.sorted_y, vec![9, 1, 2, 3, 4, 5, 6, 7, 8, 0]);
+        cloud.check_consistency();
+    }
+
+    #[test]
+    fn test_nearest_in_halfplane() {
+        let mut sorted = PointCloud2D::new();
+        let mut unsorted = PointCloud2D::new_unsorted();
+        let mut hashed = PointCloud2D::new_unsorted();
+        hashed.use_spatial_hash(0.5);
+        for cloud in [&mut sorted, &mut unsorted, &mut hashed].iter_mut() {
+            // Points on both sides of the line y = x
+            cloud.push(Point2D::new(0.0, 0.2)); // above (i.e., left of the line going up)
+            cloud.push(Point2D::new(0.1, 0.0)); // below, but closer to the origin
+            cloud.push(Point2D::new(5.0, 5.0)); // on the line, far away
+        }
+
+        for cloud in [&sorted, &unsorted, &hashed].iter() {
+            let origin = Point2D::new(0.0, 0.0);
+            let a = Point2D::new(-1.0, -1.0);
+            let b = Point2D::new(1.0, 1.0);
+
+            // Line going up... left side is above
+            assert_eq!(cloud.nearest_in_halfplane(&origin, &a, &b, 1.0), Some(0));
+
+            // Line going down... left side is below
+            assert_eq!(cloud.nearest_in_halfplane(&origin, &b, &a, 1.0), Some(1));
+
+            // Too far
+            assert_eq!(cloud.nearest_in_halfplane(&origin, &a, &b, 0.15), None);
+            assert_eq!(cloud.nearest_in_halfplane(&origin, &a, &b, -1.0), None);
+
+            // Points exactly on the line are excluded
+            let p = Point2D::new(5.0, 5.0);
+            assert_eq!(cloud.nearest_in_halfplane(&p, &a, &b, 1.0), None);
+            assert_eq!(cloud.nearest_in_halfplane(&p, &b, &a, 1.0), None);
+
+            // Coincident points match with a zero distance, like in test_world_point_within()
+            let p = Point2D::new(0.0, 0.2);
+            assert_eq!(cloud.nearest_in_halfplane(&p, &a, &b, 0.0), Some(0));
+            assert_eq!(cloud.nearest_in_halfplane(&p, &b, &a, 0.0), None);
+            assert_eq!(cloud.nearest_in_halfplane(&origin, &a, &b, 0.0), None);
+        }
+    }
+
+    #[test]
+    fn test_nearest_neighbor_distances() {
+        // Empty and single point
+        let mut cloud = PointCloud2D::new();
+        assert!(cloud.nearest_neighbor_distances().is_empty());
+        cloud.push(Point2D::new(1.0, 1.0));
+        assert_eq!(cloud.nearest_neighbor_distances(), vec![Float::INFINITY]);
+
+        // Regular grid
+        let spacing = 0.5;
+        let mut sorted = PointCloud2D::new();
+        let mut unsorted = PointCloud2D::new_unsorted();
+        for i in 0..5 {
+            for j in 0..4 {
+                let p = Point2D::new(i as Float * spacing, j as Float * spacing);
+                sorted.push(p);
+                unsorted.push(p);
+            }
+        }
+        let distances = sorted.nearest_neighbor_distances();
+        assert_eq!(distances.len(), 20);
+        for d in distances.iter() {
+            assert!((d - spacing).abs() < 1e-9);
+        }
+        assert_eq!(distances, unsorted.nearest_neighbor_distances());
+    }
+
+    #[test]
+    fn test_ids() {
+        let mut cloud = PointCloud2D::new();
+        cloud.push_with_id(Point2D::new(2.0, 0.0), 20);
+        cloud.push(Point2D::new(1.0, 0.0));
+        cloud.push_with_id(Point2D::new(0.0, 0.0), 10);
+        cloud.check_consistency();
+
+        assert_eq!(cloud.index_of_id(20), Some(0));
+        assert_eq!(cloud.index_of_id(10), Some(2));
+        assert_eq!(cloud.index_of_id(30), None);
+
+        // Moving points around (i.e., changing the sorting) does not affect IDs
+        cloud.update_point(2, Point2D::new(5.0, 5.0));
+        assert_eq!(cloud.index_of_id(10), Some(2));
+        assert_eq!(cloud.points[2], Point2D::new(5.0, 5.0));
+
+        // Unsorted clouds also keep IDs
+        let mut cloud = PointCloud2D::new_unsorted();
+        cloud.push(Point2D::new(1.0, 0.0));
+        cloud.push_with_id(Point2D::new(0.0, 0.0), 7);
+        assert_eq!(cloud.index_of_id(7), Some(1));
+    }
+
+    #[test]
+    fn test_min_area_rect() {
+        fn area(r: &(Point2D, Point2D, Point2D, Point2D)) -> Float {
+            r.0.distance_to(&r.1) * r.1.distance_to(&r.2)
+        }
+        fn contains(r: &(Point2D, Point2D, Point2D, Point2D), p: &Point2D) -> bool {
+            [r.0, r.1, r.2, r.3]
+                .iter()
+                .any(|c| c.squared_distance_to(p) < 1e-8)
+        }
+
+        // Not enough points
+        let mut cloud = PointCloud2D::new();
+        assert!(cloud.min_area_rect().is_none());
+        cloud.push(Point2D::new(0., 0.));
+        cloud.push(Point2D::new(1., 0.));
+        assert!(cloud.min_area_rect().is_none());
+        // Collinear
+        cloud.push(Point2D::new(2., 0.));
+        assert!(cloud.min_area_rect().is_none());
+
+        // Axis aligned rectangle (with some inner points)
+        let corners = [
+            Point2D::new(0., 0.),
+            Point2D::new(4., 0.),
+            Point2D::new(4., 2.),
+            Point2D::new(0., 2.),
+        ];
+        let mut cloud = PointCloud2D::new();
+        for p in corners.iter() {
+            cloud.push(*p);
+        }
+        cloud.push(Point2D::new(1., 1.));
+        cloud.push(Point2D::new(2., 0.)); // on an edge
+        let rect = cloud.min_area_rect().unwrap();
+        assert!((area(&rect) - 8.).abs() < 1e-4);
+        for p in corners.iter() {
+            assert!(contains(&rect, p));
+        }
+
+        // The same, rotated 30 degrees around (1,1)
+        let pivot = Point2D::new(1., 1.);
+        let (sin, cos) = (30. as Float).to_radians().sin_cos();
+        let rotate = |p: &Point2D| {
+            let (dx, dy) = (p.x - pivot.x, p.y - pivot.y);
+            Point2D::new(pivot.x + dx * cos - dy * sin, pivot.y + dx * sin + dy * cos)
+        };
+        let mut cloud = PointCloud2D::new();
+        for p in corners.iter() {
+            cloud.push(rotate(p));
+        }
+        cloud.push(rotate(&Point2D::new(3., 1.5)));
+        cloud.push(rotate(&Point2D::new(0.5, 0.2)));
+        let rect = cloud.min_area_rect().unwrap();
+        assert!((area(&rect) - 8.).abs() < 1e-4);
+        for p in corners.iter() {
+            assert!(contains(&rect, &rotate(p)));
+        }
+    }
+
+    #[test]
+    fn test_connections() {
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(0., 0.));
+        cloud.push(Point2D::new(1., 0.));
+        cloud.push(Point2D::new(1., 1.));
+        assert!(cloud.connections().is_empty());
+
+        // Connect, in any order
+        assert!(cloud.connect(0, 1));
+        assert!(cloud.connect(2, 1));
+        assert_eq!(cloud.connections(), &[(0, 1), (1, 2)]);
+        assert!(cloud.are_connected(1, 0));
+        assert!(!cloud.are_connected(0, 2));
+
+        // No duplicates
+        assert!(!cloud.connect(1, 0));
+        assert_eq!(cloud.connections().len(), 2);
+
+        // No self-loops, nor missing points
+        assert!(!cloud.connect(1, 1));
+        assert!(!cloud.connect(0, 3));
+        assert_eq!(cloud.connections().len(), 2);
+
+        // Disconnect
+        assert!(cloud.disconnect(1, 0));
+        assert!(!cloud.disconnect(0, 1));
+        assert_eq!(cloud.connections(), &[(1, 2)]);
+    }
+
+    #[test]
+    fn test_isolated_points() {
+        for sorted in [true, false].iter() {
+            let mut cloud = if *sorted {
+                PointCloud2D::new()
+            } else {
+                PointCloud2D::new_unsorted()
+            };
+            for i in 0..6 {
+                cloud.push(Point2D::new(i as Float, (i % 2) as Float));
+            }
+            // 1 - 2 - 4, and 0, 3 and 5 on their own
+            cloud.connect(1, 2);
+            cloud.connect(4, 2);
+            assert_eq!(cloud.isolated_points(), vec![0, 3, 5]);
+
+            assert_eq!(cloud.remove_isolated(), 3);
+            cloud.check_consistency();
+            let expected = [
+                Point2D::new(1., 1.),
+                Point2D::new(2., 0.),
+                Point2D::new(4., 0.),
+            ];
+            assert_eq!(cloud.points(), &expected[..]);
+            assert_eq!(cloud.connections(), &[(0, 1), (1, 2)]);
+            assert_eq!(cloud.test_world_point(&Point2D::new(4., 0.)), Some(2));
+            assert!(cloud.isolated_points().is_empty());
+
+            // Nothing else to remove
+            assert_eq!(cloud.remove_isolated(), 0);
+            assert_eq!(cloud.len(), 3);
+        }
+
+        // Without connections, every point is isolated
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(0., 0.));
+        cloud.push(Point2D::new(1., 0.));
+        assert_eq!(cloud.isolated_points(), vec![0, 1]);
+        assert_eq!(cloud.remove_isolated(), 2);
+        assert!(cloud.is_empty());
+    }
+
+    #[test]
+    fn test_visible_edges() {
+        let mut cloud = PointCloud2D::new();
+        for (x, y) in [
+            (0., 0.),
+            (0.5, 0.5),
+            (-5., 0.),
+            (5., 0.),
+            (-5., 5.),
+            (5., 5.),
+            (-3., 2.),
+            (3., 2.),
+            (-3., 0.),
+            (0.5, 2.),
+        ]
+        .iter()
+        {
+            cloud.push(Point2D::new(*x, *y));
+        }
+        cloud.connect(0, 1); // inside
+        cloud.connect(2, 3); // both ends out, across the canvas
+        cloud.connect(4, 5); // above the canvas
+        cloud.connect(6, 7); // above, within the X range
+        cloud.connect(8, 9); // diagonal, passing over the corner
+
+        // Shows (-1, -0.5) to (1, 0.5)
+        let vp = Viewport {
+            center: Point2D::new(0., 0.),
+            width: 2.,
+            canvas_width: 300.,
+            canvas_height: 150.,
+        };
+        let visible: Vec<(usize, usize)> = cloud
+            .visible_edges(&vp)
+            .iter()
+            .map(|i| cloud.connections()[*i])
+            .collect();
+        assert_eq!(visible, vec![(0, 1), (2, 3)]);
+
+        // Going around the canvas, when orthogonal
+        cloud.set_edge_style(EdgeStyle::Orthogonal);
+        let visible: Vec<(usize, usize)> = cloud
+            .visible_edges(&vp)
+            .iter()
+            .map(|i| cloud.connections()[*i])
+            .collect();
+        assert_eq!(visible, vec![(0, 1), (2, 3), (8, 9)]);
+
+        // Everything, zooming out
+        let vp = Viewport { width: 20., ..vp };
+        assert_eq!(cloud.visible_edges(&vp).len(), 5);
+
+        // Nothing, far away
+        let vp = Viewport {
+            center: Point2D::new(50., 0.),
+            ..vp
+        };
+        assert!(cloud.visible_edges(&vp).is_empty());
+    }
+
+    #[test]
+    fn test_point_on_edge() {
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(4., 2.));
+        cloud.push(Point2D::new(0., 0.));
+        cloud.push(Point2D::new(9., 9.));
+        assert!(cloud.point_on_edge(0, 0.5).is_none());
+
+        // Goes from the smallest index to the largest one
+        cloud.connect(1, 0);
+        assert_eq!(cloud.point_on_edge(0, 0.), Some(Point2D::new(4., 2.)));
+        assert_eq!(cloud.point_on_edge(0, 0.5), Some(Point2D::new(2., 1.)));
+        assert_eq!(cloud.point_on_edge(0, 1.), Some(Point2D::new(0., 0.)));
+        assert!(cloud.point_on_edge(1, 0.5).is_none());
+    }
+
+    #[test]
+    fn test_project_onto_line() {
+        let a = Point2D::new(0., 0.);
+        let b = Point2D::new(2., 2.);
+        let project = |x: Float, y: Float| project_onto_line(&Point2D::new(x, y), &a, &b);
+        assert_eq!(project(2., 0.), Point2D::new(1., 1.));
+        assert_eq!(project(1., 1.), Point2D::new(1., 1.));
+
+        // Beyond the ends
+        assert_eq!(project(6., 4.), Point2D::new(5., 5.));
+        assert_eq!(project(-4., 0.), Point2D::new(-2., -2.));
+
+        // Degenerate
+        assert_eq!(project_onto_line(&Point2D::new(3., 4.), &a, &a), a);
+    }
+
+    #[test]
+    fn test_segment_intersection() {
+        let p = |x: Float, y: Float| Point2D::new(x, y);
+        let (a, b) = (p(0., 0.), p(4., 4.));
+
+        // A clean crossing, in any direction
+        assert_eq!(
+            segment_intersection(&a, &b, &p(0., 4.), &p(4., 0.)),
+            Some(p(2., 2.))
+        );
+        assert_eq!(
+            segment_intersection(&b, &a, &p(4., 0.), &p(0., 4.)),
+            Some(p(2., 2.))
+        );
+
+        // Touching at the ends
+        assert_eq!(
+            segment_intersection(&a, &b, &p(4., 4.), &p(6., 0.)),
+            Some(p(4., 4.))
+        );
+
+        // Parallel, and collinear
+        assert!(segment_intersection(&a, &b, &p(1., 0.), &p(5., 4.)).is_none());
+        assert!(segment_intersection(&a, &b, &p(1., 1.), &p(5., 5.)).is_none());
+
+        // The lines cross, but not the segments
+        assert!(segment_intersection(&a, &b, &p(0., 10.), &p(10., 0.)).is_none());
+        assert!(segment_intersection(&a, &b, &p(3., 1.), &p(8., -4.)).is_none());
+    }
+
+    #[test]
+    fn test_snap_to_edge_line() {
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(0., 1.));
+        cloud.push(Point2D::new(2., 1.));
+        cloud.push(Point2D::new(3., 3.));
+        cloud.connect(0, 1);
+        cloud.connect(1, 2);
+
+        // Along the segment
+        let snapped = cloud.snap_to_edge_line(&Point2D::new(1.5, 4.), 0);
+        assert_eq!(snapped, Point2D::new(1.5, 1.));
+
+        // Beyond its ends
+        let snapped = cloud.snap_to_edge_line(&Point2D::new(7., -2.), 0);
+        assert_eq!(snapped, Point2D::new(7., 1.));
+        let snapped = cloud.snap_to_edge_line(&Point2D::new(-3., 0.), 0);
+        assert_eq!(snapped, Point2D::new(-3., 1.));
+        let snapped = cloud.snap_to_edge_line(&Point2D::new(6., 4.), 1);
+        assert!(snapped.distance_to(&Point2D::new(4., 5.)) < 1e-5);
+
+        // No such connection
+        let p = Point2D::new(6., 4.);
+        assert_eq!(cloud.snap_to_edge_line(&p, 2), p);
+    }
+
+    #[test]
+    fn test_point_in_loop() {
+        let mut cloud = PointCloud2D::new();
+        for (x, y) in [(0., 0.), (2., 2.), (2., 0.), (0., 2.)].iter() {
+            cloud.push(Point2D::new(*x, *y));
+        }
+        // Around the square, whatever the order of the points
+        cloud.connect(0, 2);
+        cloud.connect(2, 1);
+        cloud.connect(1, 3);
+        cloud.connect(3, 0);
+
+        let inside =
+            |cloud: &PointCloud2D, x: Float, y: Float| cloud.point_in_loop(&Point2D::new(x, y));
+        assert!(inside(&cloud, 1., 1.));
+        assert!(inside(&cloud, 0.1, 1.9));
+        assert!(!inside(&cloud, 3., 1.));
+        assert!(!inside(&cloud, 1., -0.5));
+
+        // The boundary is inside
+        assert!(inside(&cloud, 0., 1.));
+        assert!(inside(&cloud, 2., 2.));
+
+        // Not closed
+        cloud.disconnect(3, 0);
+        assert!(!inside(&cloud, 1., 1.));
+    }
+
+    #[test]
+    fn test_edge_intersection() {
+        let mut cloud = PointCloud2D::new();
+        for (x, y) in [
+            (0., 0.),
+            (4., 4.),
+            (0., 4.),
+            (4., 0.),
+            (1., 0.),
+            (5., 4.),
+            (9., 0.),
+        ]
+        .iter()
+        {
+            cloud.push(Point2D::new(*x, *y));
+        }
+        cloud.connect(0, 1);
+        cloud.connect(2, 3); // crosses the first one
+        cloud.connect(4, 5); // parallel to the first one
+        cloud.connect(5, 6); // its line crosses the first one beyond (4, 4)
+
+        assert_eq!(cloud.edge_intersection(0, 1), Some(Point2D::new(2., 2.)));
+        assert_eq!(cloud.edge_intersection(1, 0), Some(Point2D::new(2., 2.)));
+        assert!(cloud.edge_intersection(0, 2).is_none());
+        assert!(cloud.edge_intersection(0, 3).is_none());
+
+        // Sharing a point
+        assert_eq!(cloud.edge_intersection(2, 3), Some(Point2D::new(5., 4.)));
+
+        // No such connection
+        assert!(cloud.edge_intersection(0, 4).is_none());
+    }
+
+    #[test]
+    fn test_vertex_angle() {
+        use std::f64::consts::PI;
+        let pi = PI as Float;
+
+        let mut cloud = PointCloud2D::new();
+        for (x, y) in [(0., 0.), (2., 0.), (2., 3.), (-1., 0.), (9., 9.)].iter() {
+            cloud.push(Point2D::new(*x, *y));
+        }
+        cloud.connect(0, 1);
+        cloud.connect(1, 2);
+        cloud.connect(3, 0);
+
+        // A corner, and going straight
+        assert!((cloud.vertex_angle(1).unwrap() - pi / 2.).abs() < 1e-5);
+        assert!((cloud.vertex_angle(0).unwrap() - pi).abs() < 1e-5);
+
+        // Not two connections
+        assert!(cloud.vertex_angle(2).is_none());
+        assert!(cloud.vertex_angle(4).is_none());
+        assert!(cloud.vertex_angle(5).is_none());
+        cloud.connect(0, 4);
+        assert!(cloud.vertex_angle(0).is_none());
+    }
+
+    #[test]
+    fn test_parallel_edge_points() {
+        let mut cloud = PointCloud2D::new();
+        for (x, y) in [(0., 0.), (4., 0.), (1., 1.), (4., 5.), (4., 0.)].iter() {
+            cloud.push(Point2D::new(*x, *y));
+        }
+        cloud.connect(0, 1);
+        cloud.connect(2, 3);
+        cloud.connect(1, 4);
+
+        // Horizontal, going right, so up is to the left
+        let (a, b) = cloud.parallel_edge_points(0, 0.5).unwrap();
+        assert_eq!(a, Point2D::new(0., 0.5));
+        assert_eq!(b, Point2D::new(4., 0.5));
+        let (a, b) = cloud.parallel_edge_points(0, -2.).unwrap();
+        assert_eq!(a, Point2D::new(0., -2.));
+        assert_eq!(b, Point2D::new(4., -2.));
+
+        // Diagonal, going along (3, 4)
+        let (a, b) = cloud.parallel_edge_points(1, 5.).unwrap();
+        assert!(a.distance_to(&Point2D::new(-3., 4.)) < 1e-5);
+        assert!(b.distance_to(&Point2D::new(0., 8.)) < 1e-5);
+
+        // Coincident points, and no such connection
+        assert!(cloud.parallel_edge_points(2, 1.).is_none());
+        assert!(cloud.parallel_edge_points(3, 1.).is_none());
+    }
+
+    #[test]
+    fn test_merge_collinear_edges() {
+        // A straight chain, connected in any order
+        let mut cloud = PointCloud2D::new();
+        for (x, y) in [(0., 0.), (2., 0.), (1., 0.01), (3., 0.)].iter() {
+            cloud.push(Point2D::new(*x, *y));
+        }
+        cloud.connect(0, 2);
+        cloud.connect(1, 3);
+        cloud.connect(2, 1);
+
+        // Not quite straight
+        assert_eq!(cloud.merge_collinear_edges(0.1), 0);
+        assert_eq!(cloud.connections().len(), 3);
+
+        assert_eq!(cloud.merge_collinear_edges(2.), 2);
+        assert_eq!(cloud.len(), 2);
+        assert_eq!(cloud.points()[0], Point2D::new(0., 0.));
+        assert_eq!(cloud.points()[1], Point2D::new(3., 0.));
+        assert_eq!(cloud.connections(), &[(0, 1)]);
+
+        // An L corner
+        let mut cloud = PointCloud2D::new();
+        for (x, y) in [(0., 0.), (1., 0.), (1., 1.)].iter() {
+            cloud.push(Point2D::new(*x, *y));
+        }
+        cloud.connect(0, 1);
+        cloud.connect(1, 2);
+        assert_eq!(cloud.merge_collinear_edges(2.), 0);
+        assert_eq!(cloud.len(), 3);
+        assert_eq!(cloud.connections(), &[(0, 1), (1, 2)]);
+
+        // A flat triangle, whose ends are already connected
+        cloud.update_point(2, Point2D::new(2., 0.));
+        cloud.connect(2, 0);
+        assert_eq!(cloud.connections(), &[(0, 1), (1, 2), (0, 2)]);
+        assert_eq!(cloud.merge_collinear_edges(2.), 1);
+        assert_eq!(cloud.len(), 2);
+        assert_eq!(cloud.points()[1], Point2D::new(2., 0.));
+        assert_eq!(cloud.connections(), &[(0, 1)]);
+
+        // Merging goes on past the flat triangle
+        let mut cloud = PointCloud2D::new();
+        for (x, y) in [(0., 0.), (1., 0.), (2., 0.), (3., 0.)].iter() {
+            cloud.push(Point2D::new(*x, *y));
+        }
+        cloud.connect(0, 1);
+        cloud.connect(1, 2);
+        cloud.connect(0, 2);
+        cloud.connect(2, 3);
+        assert_eq!(cloud.merge_collinear_edges(2.), 2);
+        assert_eq!(
+            cloud.points(),
+            &[Point2D::new(0., 0.), Point2D::new(3., 0.)]
+        );
+        assert_eq!(cloud.connections(), &[(0, 1)]);
+    }
+
+    #[test]
+    fn test_spline_points() {
+        // A zigzag
+        let mut cloud = PointCloud2D::new();
+        let vertices = [(0., 0.), (1., 2.), (3., -1.), (4., 1.)];
+        for (x, y) in vertices.iter() {
+            cloud.push(Point2D::new(*x, *y));
+        }
+        for i in 0..3 {
+            cloud.connect(i, i + 1);
+        }
+
+        // Open: through every vertex, ending at the last one
+        let spline = cloud.spline_points(8);
+        assert_eq!(spline.len(), 3 * 8 + 1);
+        for (i, (x, y)) in vertices.iter().enumerate() {
+            assert!(spline[i * 8].distance_to(&Point2D::new(*x, *y)) < 1e-5);
+        }
+        // ... and not through the straight lines between them
+        let midpoint = Point2D::new(0.5, 1.);
+        assert!(spline[4].distance_to(&midpoint) > 1e-3);
+
+        // Closed: one more segment, without repeating the first vertex
+        cloud.connect(3, 0);
+        let spline = cloud.spline_points(8);
+        assert_eq!(spline.len(), 4 * 8);
+        for (i, (x, y)) in vertices.iter().enumerate() {
+            assert!(spline[i * 8].distance_to(&Point2D::new(*x, *y)) < 1e-5);
+        }
+
+        // Just the vertices
+        assert_eq!(cloud.spline_points(0).len(), 4);
+        assert_eq!(cloud.spline_points(1).len(), 4);
+
+        // Not a chain
+        assert!(PointCloud2D::new().spline_points(8).is_empty());
+    }
+
+    #[test]
+    fn test_longest_and_shortest_edge() {
+        let mut cloud = PointCloud2D::new();
+        assert!(cloud.longest_edge().is_none());
+        assert!(cloud.shortest_edge().is_none());
+
+        for (x, y) in [(0., 0.), (3., 0.), (3., 4.), (1., 1.)].iter() {
+            cloud.push(Point2D::new(*x, *y));
+        }
+        cloud.connect(0, 1); // 3
+        cloud.connect(1, 2); // 4
+        cloud.connect(2, 0); // 5
+        cloud.connect(0, 3); // 1.41
+        assert_eq!(cloud.longest_edge(), Some(2));
+        assert_eq!(cloud.shortest_edge(), Some(3));
+
+        // Ties go to the first one
+        cloud.disconnect(0, 3);
+        cloud.connect(1, 3);
+        cloud.update_point(3, Point2D::new(3., 3.));
+        assert_eq!(cloud.connections()[0], (0, 1));
+        assert_eq!(cloud.connections()[3], (1, 3));
+        assert_eq!(cloud.shortest_edge(), Some(0));
+    }
+
+    #[test]
+    fn test_bounds_aspect_ratio() {
+        let mut cloud = PointCloud2D::new();
+        assert!(cloud.bounds_aspect_ratio().is_none());
+
+        // Flat
+        cloud.push(Point2D::new(0.0, 1.0));
+        assert_eq!(cloud.bounds_aspect_ratio(), Some(Float::INFINITY));
+        cloud.push(Point2D::new(4.0, 1.0));
+        assert_eq!(cloud.bounds_aspect_ratio(), Some(Float::INFINITY));
+
+        // Wide
+        cloud.push(Point2D::new(2.0, 3.0));
+        assert_eq!(cloud.bounds_aspect_ratio(), Some(2.0));
+
+        // Square
+        cloud.push(Point2D::new(1.0, 5.0));
+        assert_eq!(cloud.bounds_aspect_ratio(), Some(1.0));
+
+        // Tall
+        cloud.push(Point2D::new(3.0, -3.0));
+        assert_eq!(cloud.bounds_aspect_ratio(), Some(0.5));
+    }
+
+    #[test]
+    fn test_elbow() {
+        let a = Point2D::new(1., 2.);
+        let b = Point2D::new(4., -3.);
+        assert_eq!(elbow(&a, &b), Point2D::new(4., 2.));
+        assert_eq!(elbow(&b, &a), Point2D::new(1., -3.));
+
+        // Already aligned
+        let c = Point2D::new(7., 2.);
+        assert_eq!(elbow(&a, &c), c);
+        let d = Point2D::new(1., 5.);
+        assert_eq!(elbow(&a, &d), a);
+    }
+
+    #[test]
+    fn test_distance_between() {
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(0.0, 0.0));
+        cloud.push(Point2D::new(3.0, 4.0));
+
+        assert_eq!(cloud.distance_between(0, 1), Some(5.0));
+        assert_eq!(cloud.distance_between(1, 0), Some(5.0));
+        assert_eq!(cloud.distance_between(1, 1), Some(0.0));
+
+        // Out of range
+        assert_eq!(cloud.distance_between(0, 2), None);
+        assert_eq!(cloud.distance_between(2, 0), None);
+    }
+
+    #[test]
+    fn test_rotate_selection() {
+        use std::f64::consts::PI;
+
+        for sorted in [true, false].iter() {
+            let mut cloud = if *sorted {
+                PointCloud2D::new()
+            } else {
+                PointCloud2D::new_unsorted()
+            };
+            cloud.push(Point2D::new(0., 0.));
+            cloud.push(Point2D::new(1., 1.));
+            cloud.push(Point2D::new(5., 3.));
+            cloud.push(Point2D::new(2., -1.));
+            cloud.set_history_depth(10);
+            cloud.update_point(0, Point2D::new(-1., 0.));
+
+            // Half a turn around (3, 1) swaps them
+            cloud.rotate_selection(vec![2, 1, 2, 9], PI as Float);
+            let p = cloud.points();
+            assert!(p[1].distance_to(&Point2D::new(5., 3.)) < 1e-5);
+            assert!(p[2].distance_to(&Point2D::new(1., 1.)) < 1e-5);
+            assert_eq!(p[0], Point2D::new(-1., 0.));
+            assert_eq!(p[3], Point2D::new(2., -1.));
+            assert_eq!(cloud.test_world_point(&Point2D::new(1., 1.)), Some(2));
+            assert_eq!(cloud.test_world_point(&Point2D::new(5., 3.)), Some(1));
+            assert!(!cloud.undo());
+
+            // Nothing selected
+            let before = cloud.points().to_vec();
+            cloud.rotate_selection(vec![], 1.);
+            cloud.rotate_selection(vec![4], 1.);
+            assert_eq!(cloud.points(), &before[..]);
+        }
+    }
+
+    #[test]
+    fn test_visible_count() {
+        use crate::drawer2d::Viewport;
+
+        // A large cloud
+        let mut sorted = PointCloud2D::new();
+        let mut unsorted = PointCloud2D::new_unsorted();
+        for i in 0..20 {
+            for j in 0..15 {
+                let p = Point2D::new(0.37 * i as Float - 3.1, 0.29 * j as Float - 2.3);
+                sorted.push(p);
+                unsorted.push(p);
+            }
+        }
+
+        let mut vp = Viewport {
+            center: Point2D::new(0., 0.),
+            width: 2.,
+            canvas_width: 300.,
+            canvas_height: 200.,
+        };
+        for center in [(0., 0.), (1.3, -0.7), (6.0, 3.0), (-50., 0.)].iter() {
+            vp.center = Point2D::new(center.0, center.1);
+            let brute_force = sorted
+                .points
+                .iter()
+                .filter(|p| vp.as_canvas_point(p).1)
+                .count();
+
+            let (min, max) = vp.world_rect();
+            assert_eq!(sorted.count_in_rect(&min, &max), brute_force);
+            assert_eq!(unsorted.count_in_rect(&min, &max), brute_force);
+        }
+    }
+
+    #[test]
+    fn test_total_turning() {
+        use std::f64::consts::PI;
+        let two_pi = 2. * PI as Float;
+
+        let square = |corners: &[(Float, Float)]| {
+            let mut cloud = PointCloud2D::new();
+            for (x, y) in corners.iter() {
+                cloud.push(Point2D::new(*x, *y));
+            }
+            for i in 0..corners.len() {
+                cloud.connect(i, (i + 1) % corners.len());
+            }
+            cloud
+        };
+
+        // Counterclockwise
+        let mut ccw = square(&[(0., 0.), (1., 0.), (1., 1.), (0., 1.)]);
+        assert!((ccw.total_turning().unwrap() - two_pi).abs() < 1e-5);
+
+        // Clockwise
+        let cw = square(&[(0., 0.), (0., 1.), (1., 1.), (1., 0.)]);
+        assert!((cw.total_turning().unwrap() + two_pi).abs() < 1e-5);
+
+        // Not closed
+        ccw.disconnect(3, 0);
+        assert!(ccw.total_turning().is_none());
+
+        // Branching
+        ccw.connect(3, 0);
+        ccw.push(Point2D::new(2., 2.));
+        ccw.connect(2, 4);
+        assert!(ccw.total_turning().is_none());
+
+        // Two separate loops
+        let mut two = square(&[(0., 0.), (1., 0.), (1., 1.), (0., 1.)]);
+        for (x, y) in [(5., 5.), (6., 5.), (6., 6.)].iter() {
+            two.push(Point2D::new(*x, *y));
+        }
+        two.connect(4, 5);
+        two.connect(5, 6);
+        two.connect(6, 4);
+        assert!(two.total_turning().is_none());
+
+        // Nothing at all
+        assert!(PointCloud2D::new().total_turning().is_none());
+    }
+
+    #[test]
+    fn test_sample_path() {
+        // A unit square, connected in order
+        let mut cloud = PointCloud2D::new();
+        for (x, y) in [(0., 0.), (1., 0.), (1., 1.), (0., 1.)].iter() {
+            cloud.push(Point2D::new(*x, *y));
+        }
+        for i in 0..4 {
+            cloud.connect(i, (i + 1) % 4);
+        }
+        let expected: Vec<Point2D> = [
+            (0., 0.),
+            (0.5, 0.),
+            (1., 0.),
+            (1., 0.5),
+            (1., 1.),
+            (0.5, 1.),
+            (0., 1.),
+            (0., 0.5),
+        ]
+        .iter()
+        .map(|(x, y)| Point2D::new(*x, *y))
+        .collect();
+        let samples = cloud.sample_path(0.5);
+        assert_eq!(samples.len(), 8);
+        for (p, q) in samples.iter().zip(expected.iter()) {
+            assert!(p.distance_to(q) < 1e-5);
+        }
+        assert_eq!(cloud.sample_path(0.3).len(), 14);
+
+        // Open, from its lowest end
+        cloud.disconnect(0, 1);
+        let samples = cloud.sample_path(0.5);
+        assert_eq!(samples.len(), 7);
+        assert_eq!(samples[0], Point2D::new(0., 0.));
+        assert!(samples[1].distance_to(&Point2D::new(0., 0.5)) < 1e-5);
+        assert!(samples[6].distance_to(&Point2D::new(1., 0.)) < 1e-5);
+        let samples = cloud.sample_path(2.);
+        assert_eq!(samples.len(), 2);
+        assert!(samples[1].distance_to(&Point2D::new(1., 1.)) < 1e-5);
+
+        // Not a chain
+        cloud.push(Point2D::new(5., 5.));
+        cloud.connect(2, 4);
+        assert!(cloud.sample_path(0.5).is_empty());
+
+        // No spacing
+        assert!(cloud.sample_path(0.).is_empty());
+        assert!(PointCloud2D::new().sample_path(0.5).is_empty());
+    }
+
+    #[test]
+    fn test_to_svg() {
+        let cloud = PointCloud2D::new();
+        let svg = cloud.to_svg(200, 100);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<circle").count(), 0);
+
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(0., 0.));
+        cloud.push(Point2D::new(10., 0.));
+        cloud.push(Point2D::new(10., 5.));
+        let svg = cloud.to_svg(200, 100);
+        assert_eq!(svg.matches("<circle").count(), 3);
+        assert_eq!(svg.matches("<line").count(), 0);
+
+        // Fitted (i.e., limited by the height)... (10,5) is the top-right corner
+        assert!(svg.contains("<circle cx=\"184\" cy=\"8\""));
+
+        // Connections, below the points
+        cloud.connect(0, 1);
+        cloud.connect(1, 2);
+        let svg = cloud.to_svg(200, 100);
+        assert_eq!(svg.matches("<line").count(), 2);
+        assert!(svg.contains("<line x1=\"184\" y1=\"92\" x2=\"184\" y2=\"8\"/>"));
+        assert!(svg.find("<line").unwrap() < svg.find("<circle").unwrap());
+
+        cloud.set_edge_style(EdgeStyle::Orthogonal);
+        let svg = cloud.to_svg(200, 100);
+        assert_eq!(svg.matches("<line").count(), 0);
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        assert_eq!(svg.matches("<circle").count(), 3);
+    }
+
+    #[test]
+    fn test_test_world_point_capped() {
+        // A degenerate cluster: all in the same X, very close in Y
+        let mut cloud = PointCloud2D::new();
+        for i in 0..11 {
+            cloud.push(Point2D::new(0.0, i as Float * 0.01));
+        }
+        let p = Point2D::new(0.0, 0.1);
+
+        // Enough candidates... the actual closest one
+        assert_eq!(cloud.test_world_point_capped(&p, 0.25, 100), Some(10));
+        assert_eq!(cloud.test_world_point_capped(&p, 0.25, 11), Some(10));
+
+        // Only the first candidates are checked
+        assert_eq!(cloud.test_world_point_capped(&p, 0.25, 1), Some(0));
+        assert_eq!(cloud.test_world_point_capped(&p, 0.25, 5), Some(4));
+        assert_eq!(cloud.test_world_point_capped(&p, 0.25, 0), None);
+
+        // Unsorted, with and without a spatial hash
+        let mut unsorted = PointCloud2D::new_unsorted();
+        for i in (0..11).rev() {
+            unsorted.push(Point2D::new(0.0, i as Float * 0.01));
+        }
+        unsorted.push(Point2D::new(9.0, 9.0));
+        assert_eq!(unsorted.test_world_point_capped(&p, 0.25, 100), Some(0));
+        assert_eq!(unsorted.test_world_point_capped(&p, 0.25, 1), Some(0));
+        assert_eq!(unsorted.test_world_point_capped(&p, 0.05, 100), Some(0));
+        let far = Point2D::new(9., 9.1);
+        assert_eq!(unsorted.test_world_point_capped(&far, 0.25, 100), Some(11));
+        assert_eq!(unsorted.test_world_point_capped(&far, 0.25, 5), None);
+        unsorted.use_spatial_hash(0.5);
+        assert_eq!(unsorted.test_world_point_capped(&p, 0.25, 100), Some(0));
+        assert_eq!(unsorted.test_world_point_capped(&far, 0.25, 100), Some(11));
+
+        // Coincident points match with a zero distance, like in test_world_point_within()
+        let on_point = Point2D::new(0.0, 5 as Float * 0.01);
+        for cloud in [&cloud, &unsorted].iter() {
+            let expected = cloud.test_world_point_within(&on_point, 0.0);
+            assert!(expected.is_some());
+            assert_eq!(cloud.test_world_point_capped(&on_point, 0.0, 100), expected);
+            let between = Point2D::new(0.0, 0.005);
+            assert_eq!(cloud.test_world_point_capped(&between, 0.0, 100), None);
+        }
+    }
+
+    #[test]
+    fn test_points_at() {
+        // 4 columns, 3 rows
+        let mut sorted = PointCloud2D::new();
+        let mut unsorted = PointCloud2D::new_unsorted();
+        for i in 0..4 {
+            for j in 0..3 {
+                let p = Point2D::new(i as Float, j as Float * 2.0);
+                sorted.push(p);
+                unsorted.push(p);
+            }
+        }
+
+        for cloud in [&sorted, &unsorted].iter() {
+            // A full column
+            let mut column = cloud.points_at_x(1.0, 1e-6);
+            column.sort_unstable();
+            assert_eq!(column, vec![3, 4, 5]);
+
+            // A full row
+            let mut row = cloud.points_at_y(2.0, 1e-6);
+            row.sort_unstable();
+            assert_eq!(row, vec![1, 4, 7, 10]);
+
+            // Nothing there
+            assert!(cloud.points_at_x(1.5, 0.1).is_empty());
+            assert!(cloud.points_at_y(1.0, 0.1).is_empty());
+
+            // A wide tolerance catches several columns
+            assert_eq!(cloud.points_at_x(1.5, 0.5).len(), 6);
+        }
+    }
+
+    #[test]
+    fn test_flash_opacity() {
+        // Start, middle and end
+        assert_eq!(flash_opacity(1000., 500., 1000.), 1.);
+        assert_eq!(flash_opacity(1000., 500., 1250.), 0.5);
+        assert_eq!(flash_opacity(1000., 500., 1500.), 0.);
+
+        // Out of the flash
+        assert_eq!(flash_opacity(1000., 500., 900.), 1.);
+        assert_eq!(flash_opacity(1000., 500., 2000.), 0.);
+
+        // No duration
+        assert_eq!(flash_opacity(1000., 0., 1000.), 0.);
+
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(0., 0.));
+        cloud.flash_point(0, 1000., 500.);
+        assert_eq!(cloud.flash, Some((0, 1000., 500.)));
+    }
+
+    #[test]
+    fn test_convex_hull_points() {
+        let mut cloud = PointCloud2D::new();
+        assert!(cloud.convex_hull_points().is_empty());
+
+        cloud.push(Point2D::new(0., 0.));
+        cloud.push(Point2D::new(2., 2.));
+        cloud.push(Point2D::new(1., 1.)); // inside
+        cloud.push(Point2D::new(2., 0.));
+        cloud.push(Point2D::new(0., 2.));
+
+        let hull = cloud.convex_hull();
+        let points = cloud.convex_hull_points();
+        assert_eq!(hull, vec![0, 3, 1, 4]);
+        assert_eq!(points.len(), hull.len());
+        for (p, i) in points.iter().zip(hull.iter()) {
+            assert_eq!(*p, cloud.points[*i]);
+        }
+    }
+
+    #[test]
+    fn test_geometry_hash() {
+        let points = [
+            Point2D::new(0., 0.),
+            Point2D::new(1., 2.),
+            Point2D::new(-3., 0.5),
+        ];
+        let mut a = PointCloud2D::new();
+        let mut b = PointCloud2D::new_unsorted();
+        for p in points.iter() {
+            a.push(*p);
+            b.push(*p);
+        }
+        assert_eq!(a.geometry_hash(), b.geometry_hash());
+
+        // A single coordinate changes
+        let original = a.geometry_hash();
+        a.update_point_y(1, 2.000001);
+        assert_ne!(a.geometry_hash(), original);
+        a.update_point_y(1, 2.);
+        assert_eq!(a.geometry_hash(), original);
+
+        // Reordering changes the hash
+        let mut c = PointCloud2D::new();
+        for p in points.iter().rev() {
+            c.push(*p);
+        }
+        assert_ne!(c.geometry_hash(), original);
+
+        // Connections change it too, regardless of their order
+        a.connect(0, 1);
+        let connected = a.geometry_hash();
+        assert_ne!(connected, original);
+        a.disconnect(1, 0);
+        assert_eq!(a.geometry_hash(), original);
+
+        a.connect(0, 1);
+        a.connect(1, 2);
+        b.connect(2, 1);
+        b.connect(0, 1);
+        assert_eq!(a.geometry_hash(), b.geometry_hash());
+        assert_ne!(a.geometry_hash(), connected);
+    }
+
+    #[test]
+    fn test_remove_point() {
+        let a = Point2D::new(2.0, 0.0);
+        let b = Point2D::new(0.0, 1.0);
+        let c = Point2D::new(1.0, -1.0);
+        let build = || {
+            let mut cloud = PointCloud2D::new();
+            cloud.push_with_id(a, 10);
+            cloud.push_with_id(b, 11);
+            cloud.push_with_id(c, 12);
+            assert_eq!(cloud.sorted_x, vec![1, 2, 0]);
+            assert_eq!(cloud.sorted_y, vec![2, 0, 1]);
+            cloud
+        };
+
+        // First
+        let mut cloud = build();
+        assert_eq!(cloud.remove_point(0), Some(a));
+        cloud.check_consistency();
+        assert_eq!(cloud.points, vec![b, c]);
+        assert_eq!(cloud.sorted_x, vec![0, 1]);
+        assert_eq!(cloud.sorted_y, vec![1, 0]);
+        assert_eq!(cloud.index_of_id(10), None);
+        assert_eq!(cloud.index_of_id(12), Some(1));
 
-        // Testing case:
-        // X axis is   ---- OP ------
-        //                (0,0)
-        //                (0,0)
-        assert_eq!(cloud.find_point_position_x(0.).unwrap(), 1);
+        // Middle
+        let mut cloud = build();
+        assert_eq!(cloud.remove_point(1), Some(b));
+        cloud.check_consistency();
+        assert_eq!(cloud.points, vec![a, c]);
+        assert_eq!(cloud.sorted_x, vec![1, 0]);
+        assert_eq!(cloud.sorted_y, vec![1, 0]);
+        assert_eq!(cloud.index_of_id(12), Some(1));
 
-        /******************************** */
-        /*  2 point cases */
-        /******************************** */
-        // X axis is | ----- O --------- O --
-        //                 (0,0)        (1,0)
-        let cloud = PointCloud2D {
-            points: vec![Point2D { x: 0.0, y: 0.0 }, Point2D { x: 1., y: 0. }],
-            positions_x: vec![0, 1],
-            positions_y: vec![0, 1],
-            sorted_x: vec![0, 1],
-            sorted_y: vec![0, 1],
-            is_sorted:true,
+        // Last
+        let mut cloud = build();
+        assert_eq!(cloud.remove_point(2), Some(c));
+        cloud.check_consistency();
+        assert_eq!(cloud.points, vec![a, b]);
+        assert_eq!(cloud.sorted_x, vec![1, 0]);
+        assert_eq!(cloud.sorted_y, vec![0, 1]);
+
+        // Out of range
+        assert_eq!(cloud.remove_point(2), None);
+        assert_eq!(cloud.points, vec![a, b]);
+
+        // Until empty
+        assert_eq!(cloud.remove_point(0), Some(a));
+        assert_eq!(cloud.remove_point(0), Some(b));
+        assert!(cloud.is_empty());
+
+        // Unsorted... the last one takes the removed one's place
+        let mut cloud = PointCloud2D::new_unsorted();
+        cloud.push_with_id(a, 10);
+        cloud.push_with_id(b, 11);
+        cloud.push_with_id(c, 12);
+        assert_eq!(cloud.remove_point(0), Some(a));
+        assert_eq!(cloud.points, vec![c, b]);
+        assert_eq!(cloud.index_of_id(12), Some(0));
+        assert!(cloud.sorted_x.is_empty());
+        assert!(cloud.sorted_y.is_empty());
+    }
+
+    #[test]
+    fn test_normalize_edges() {
+        let mut cloud = PointCloud2D::new();
+        for i in 0..4 {
+            cloud.push(Point2D::new(i as Float, 0.));
+        }
+        cloud.connect(0, 1);
+        cloud.connect(2, 3);
+        assert_eq!(cloud.normalize_edges(), 0);
+        assert_eq!(cloud.connections(), &[(0, 1), (2, 3)]);
+
+        // Reversed, repeated and self-loops
+        cloud.edges = vec![(1, 0), (2, 3), (0, 1), (3, 3), (3, 2), (2, 3), (1, 3)];
+        assert_eq!(cloud.normalize_edges(), 4);
+        assert_eq!(cloud.connections(), &[(0, 1), (2, 3), (1, 3)]);
+        assert!(cloud.are_connected(3, 1));
+        cloud.check_consistency();
+    }
+
+    #[test]
+    fn test_remove_connected_point() {
+        for cloud in [PointCloud2D::new(), PointCloud2D::new_unsorted()].iter_mut() {
+            for i in 0..4 {
+                cloud.push(Point2D::new(i as Float, 0.));
+            }
+            // 0 - 1 - 2 - 3 - 0
+            cloud.connect(0, 1);
+            cloud.connect(1, 2);
+            cloud.connect(2, 3);
+            cloud.connect(3, 0);
+
+            // The connections of the removed point are gone...
+            let p = cloud.remove_point(1).unwrap();
+            assert_eq!(p, Point2D::new(1., 0.));
+            assert_eq!(cloud.connections().len(), 2);
+
+            // ... and the rest still join the same points
+            let find = |x: Float| cloud.points().iter().position(|p| p.x == x).unwrap();
+            assert!(cloud.are_connected(find(2.), find(3.)));
+            assert!(cloud.are_connected(find(3.), find(0.)));
+            for (a, b) in cloud.connections() {
+                assert!(a < b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_update_point_x_uses_x_axis() {
+        // X axis is | -- O ---- O ---- O --------------- (on y = 10)
+        //              A(0)     B(1)    C(2)
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(0.0, 10.0));
+        cloud.push(Point2D::new(1.0, 10.0));
+        cloud.push(Point2D::new(2.0, 10.0));
+
+        // Move B far to the right... new order is [A, C, B]
+        cloud.update_point_x(1, 5.0);
+        cloud.check_consistency();
+        assert_eq!(cloud.sorted_x, vec![0, 2, 1]);
+        assert_eq!(cloud.positions_x, vec![0, 2, 1]);
+        assert_eq!(cloud.points[1], Point2D::new(5.0, 10.0));
+
+        // And back to the left of them all... new order is [B, A, C]
+        cloud.update_point_x(1, -5.0);
+        cloud.check_consistency();
+        assert_eq!(cloud.sorted_x, vec![1, 0, 2]);
+        assert_eq!(cloud.positions_x, vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn test_test_world_point_within() {
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(0.0, 0.0));
+        cloud.push(Point2D::new(1.0, 0.0));
+
+        // Matched by the default distance...
+        let p = Point2D::new(0.2, 0.0);
+        assert_eq!(cloud.test_world_point(&p), Some(0));
+        assert_eq!(cloud.test_world_point_within(&p, 0.25), Some(0));
+        // ... but not by a smaller one
+        assert_eq!(cloud.test_world_point_within(&p, 0.1), None);
+        // ... and a larger one can reach further
+        let p = Point2D::new(0.6, 0.0);
+        assert_eq!(cloud.test_world_point(&p), None);
+        assert_eq!(cloud.test_world_point_within(&p, 0.5), Some(1));
+
+        // The distance is exclusive, like in test_world_point()
+        let p = Point2D::new(0.0, 0.25);
+        assert_eq!(cloud.test_world_point(&p), None);
+        assert_eq!(cloud.test_world_point_within(&p, 0.25), None);
+        assert_eq!(cloud.test_world_point_within(&p, 0.2501), Some(0));
+
+        // Zero only matches coincident points
+        let p = Point2D::new(1.0, 0.0);
+        assert_eq!(cloud.test_world_point_within(&p, 0.0), Some(1));
+        let p = Point2D::new(1.0, 1e-9);
+        assert_eq!(cloud.test_world_point_within(&p, 0.0), None);
+        assert_eq!(cloud.test_world_point_within(&p, -1.0), None);
+
+        // Unsorted clouds check all the points
+        let mut unsorted = PointCloud2D::new_unsorted();
+        unsorted.push(Point2D::new(0.0, 0.0));
+        unsorted.push(Point2D::new(1.0, 0.0));
+        unsorted.push(Point2D::new(0.5, 0.0));
+        assert_eq!(unsorted.test_world_point(&Point2D::new(0.9, 0.1)), Some(1));
+        let p = Point2D::new(0.6, 0.);
+        assert_eq!(unsorted.test_world_point_within(&p, 0.2), Some(2));
+        let p = Point2D::new(0.5, 0.);
+        assert_eq!(unsorted.test_world_point_within(&p, 0.), Some(2));
+        assert_eq!(unsorted.test_world_point(&Point2D::new(3., 3.)), None);
+    }
+
+    #[test]
+    fn test_points_in_rect() {
+        // 5x5 grid
+        let mut sorted = PointCloud2D::new();
+        let mut unsorted = PointCloud2D::new_unsorted();
+        for i in 0..5 {
+            for j in 0..5 {
+                let p = Point2D::new(i as Float, j as Float);
+                sorted.push(p);
+                unsorted.push(p);
+            }
+        }
+        let brute_force = |min: &Point2D, max: &Point2D| -> Vec<usize> {
+            (0..25)
+                .filter(|i| {
+                    let p = sorted.points[*i];
+                    p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y
+                })
+                .collect()
         };
+
+        let rects = [
+            ((-1., -1.), (10., 10.)), // everything
+            ((0.5, 0.5), (2.5, 1.5)), // inner region
+            ((1., 1.), (3., 3.)),     // borders included
+            ((3.5, -1.), (10., 0.)),  // bottom-right corner
+            ((-1., 2.), (0., 2.5)),   // a single point
+            ((5.5, 5.5), (8., 8.)),   // nothing
+        ];
+        for (min, max) in rects.iter() {
+            let min = Point2D::new(min.0, min.1);
+            let max = Point2D::new(max.0, max.1);
+            let expected = brute_force(&min, &max);
+            assert!(!expected.is_empty() || min.x > 5.);
+
+            for cloud in [&sorted, &unsorted].iter() {
+                let mut found = cloud.points_in_rect(&min, &max);
+                found.sort_unstable();
+                assert_eq!(found, expected);
+            }
+        }
+        assert_eq!(
+            sorted
+                .points_in_rect(&Point2D::new(1., 1.), &Point2D::new(3., 3.))
+                .len(),
+            9
+        );
+
+        // Inverted and zero-area rectangles
+        let a = Point2D::new(3., 3.);
+        let b = Point2D::new(1., 1.);
+        assert!(sorted.points_in_rect(&a, &b).is_empty());
+        assert!(sorted.points_in_rect(&a, &a).is_empty());
+        assert!(sorted
+            .points_in_rect(&Point2D::new(1., 0.), &Point2D::new(1., 4.))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_len_and_get_point() {
+        let mut cloud = PointCloud2D::new();
+        assert_eq!(cloud.len(), 0);
+        assert!(cloud.get_point(0).is_none());
+
+        cloud.push(Point2D::new(3., 1.));
+        cloud.push(Point2D::new(-2., 4.));
+        assert_eq!(cloud.len(), 2);
+        assert_eq!(cloud.get_point(0), Some(Point2D::new(3., 1.)));
+        assert_eq!(cloud.get_point(1), Some(Point2D::new(-2., 4.)));
+        assert!(cloud.get_point(2).is_none());
+    }
+
+    #[test]
+    fn test_from_points() {
+        // 100 points in a shuffled order, with repeated coordinates
+        let mut points = Vec::with_capacity(100);
+        let mut random = Random::new(42);
+        for _ in 0..100 {
+            let x = (random.next_float() * 20.).floor();
+            let y = (random.next_float() * 30.).floor() / 4.;
+            points.push(Point2D::new(x, y));
+        }
+
+        let mut pushed = PointCloud2D::new();
+        for p in points.iter() {
+            pushed.push(*p);
+        }
+        let batch = PointCloud2D::from_points(points);
+        batch.check_consistency();
+
+        assert_eq!(batch.points, pushed.points);
+        assert_eq!(batch.sorted_x, pushed.sorted_x);
+        assert_eq!(batch.sorted_y, pushed.sorted_y);
+        assert_eq!(batch.positions_x, pushed.positions_x);
+        assert_eq!(batch.positions_y, pushed.positions_y);
+        assert_eq!(batch.ids, pushed.ids);
+        assert!(batch.is_sorted);
+
+        // Empty
+        let empty = PointCloud2D::from_points(Vec::new());
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_occupancy_grid() {
+        // Nothing
+        let (grid, width, height, _) = PointCloud2D::new().occupancy_grid(1.);
+        assert!(grid.is_empty());
+        assert_eq!((width, height), (0, 0));
+
+        // A single point
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(3.2, -1.5));
+        let (grid, width, height, origin) = cloud.occupancy_grid(1.);
+        assert_eq!(grid, vec![true]);
+        assert_eq!((width, height), (1, 1));
+        assert_eq!(origin, Point2D::new(3.2, -1.5));
+        assert!(cloud.occupancy_grid(0.).0.is_empty());
+
+        // A short horizontal edge, and a lonely point above
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(0., 0.));
+        cloud.push(Point2D::new(2.5, 0.));
+        cloud.push(Point2D::new(0., 2.));
+        cloud.connect(0, 1);
+        let (grid, width, height, origin) = cloud.occupancy_grid(1.);
+        assert_eq!((width, height), (3, 3));
+        assert_eq!(origin, Point2D::new(0., 0.));
+        #[rustfmt::skip]
+        assert_eq!(grid, vec![
+            true, true, true,
+            false, false, false,
+            true, false, false,
+        ]);
+
+        // A diagonal edge crosses cells in between
+        cloud.connect(1, 2);
+        let (grid, _, _, _) = cloud.occupancy_grid(1.);
+        #[rustfmt::skip]
+        assert_eq!(grid, vec![
+            true, true, true,
+            true, true, false,
+            true, false, false,
+        ]);
+
+        // Too many cells
+        let (grid, width, height, _) = cloud.occupancy_grid(1e-5);
+        assert!(grid.is_empty());
+        assert_eq!((width, height), (0, 0));
+        let (grid, width, height, _) = cloud.occupancy_grid(1e-30);
+        assert!(grid.is_empty());
+        assert_eq!((width, height), (0, 0));
+
+        // ... even if each side alone is fine
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(0., 0.));
+        cloud.push(Point2D::new(8190., 8190.));
+        assert!(cloud.occupancy_grid(1.).0.is_empty());
+        assert_eq!(cloud.occupancy_grid(2.).0.len(), MAX_GRID_CELLS);
+    }
+
+    #[test]
+    fn test_sort() {
+        let mut pushed = PointCloud2D::new();
+        let mut cloud = PointCloud2D::new_unsorted();
+        for i in 0..12 {
+            let p = Point2D::new((i * 7 % 12) as Float * 0.5, (i * 5 % 4) as Float);
+            pushed.push(p);
+            cloud.push(p);
+        }
+        assert!(cloud.sort_order_x().is_empty());
+
+        cloud.sort();
         cloud.check_consistency();
+        assert_eq!(cloud.sort_order_x(), pushed.sort_order_x());
+        assert_eq!(cloud.sort_order_y(), pushed.sort_order_y());
+        assert_eq!(cloud.positions_x, pushed.positions_x);
+        assert_eq!(cloud.positions_y, pushed.positions_y);
 
-        // Test case |   P --------- O --------- O --
-        //             (-1,0)      (0,0)        (1,0)
-        assert_eq!(cloud.find_point_position_x(-1.).unwrap(), 0);
+        // Queries work now
+        for p in pushed.points.iter() {
+            let p = Point2D::new(p.x + 0.1, p.y - 0.1);
+            assert_eq!(cloud.test_world_point(&p), pushed.test_world_point(&p));
+            assert!(cloud.test_world_point(&p).is_some());
+        }
 
-        // Test case |   -- OP --------- O --
-        //                (0,0)        (1,0)
-        //                (0,0)
-        assert_eq!(cloud.find_point_position_x(0.).unwrap(), 1);
+        // ... and pushing keeps it sorted
+        cloud.push(Point2D::new(1.2, 1.7));
+        cloud.check_consistency();
+
+        // Sorting again does nothing
+        let order = cloud.sort_order_x();
+        cloud.sort();
+        assert_eq!(cloud.sort_order_x(), order);
+    }
+
+    #[test]
+    fn test_project_drag_onto_line() {
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(0., 0.));
+        cloud.push(Point2D::new(5., 5.));
+        cloud.push(Point2D::new(-3., 2.));
+
+        let a = Point2D::new(1., 1.);
+        let b = Point2D::new(4., 2.);
+        let cursors = [(0., 0.), (10., -3.), (-7., 4.), (2.5, 1.5), (3., 30.)];
+        for (x, y) in cursors.iter() {
+            let cursor = Point2D::new(*x, *y);
+            cloud.project_drag_onto_line(2, &a, &b, &cursor);
+            let p = cloud.points[2];
+
+            // Collinear with the anchors...
+            let cross = (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x);
+            assert!(cross.abs() < 1e-5, "cross = {}", cross);
+
+            // ... and the closest point in the line to the cursor
+            let dot = (b.x - a.x) * (cursor.x - p.x) + (b.y - a.y) * (cursor.y - p.y);
+            assert!(dot.abs() < 1e-4, "dot = {}", dot);
+            cloud.check_consistency();
+        }
+
+        // Coincident anchors
+        cloud.project_drag_onto_line(0, &b, &b, &Point2D::new(9., 9.));
+        assert_eq!(cloud.points[0], b);
+    }
+
+    #[test]
+    fn test_memory_stats() {
+        use std::mem::size_of;
+
+        let mut cloud = PointCloud2D::with_capacity(10);
+        for i in 0..4 {
+            cloud.push(Point2D::new(i as Float, 0.));
+        }
+        cloud.connect(0, 1);
+
+        let point_bytes = 10 * size_of::<Point2D>();
+        let index_bytes = 10 * size_of::<usize>();
+        let id_bytes = 10 * size_of::<Option<u64>>();
+        let edge_bytes = cloud.edges.capacity() * size_of::<(usize, usize)>();
+        let expected = point_bytes + 4 * index_bytes + id_bytes + edge_bytes;
+
+        let stats = cloud.memory_stats();
+        assert!(stats.contains(&format!(
+            "points: len 4, capacity 10, {} bytes\n",
+            point_bytes
+        )));
+        assert!(stats.contains(&format!(
+            "sorted_y: len 4, capacity 10, {} bytes\n",
+            index_bytes
+        )));
+        assert!(stats.ends_with(&format!("total: {} bytes", expected)));
+    }
+
+    #[test]
+    fn test_nan_coordinates() {
+        let nan = Float::NAN;
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(1., 1.));
+        cloud.push(Point2D::new(nan, 2.));
+        cloud.push(Point2D::new(0., nan));
+        cloud.push(Point2D::new(-nan, -nan));
+        cloud.push(Point2D::new(3., 0.));
+        cloud.check_consistency();
+
+        // Positive NaN go last, negative NaN go first
+        assert_eq!(cloud.sorted_x, vec![3, 2, 0, 4, 1]);
+        assert_eq!(cloud.sorted_y, vec![3, 4, 0, 1, 2]);
+
+        // Queries ignore them
+        assert_eq!(cloud.test_world_point(&Point2D::new(1.1, 0.9)), Some(0));
+        assert_eq!(cloud.test_world_point(&Point2D::new(nan, nan)), None);
+        let (min, max) = (Point2D::new(-5., -5.), Point2D::new(5., 5.));
+        assert_eq!(cloud.points_in_rect(&min, &max), vec![0, 4]);
+
+        // Moving them around and removing them works too
+        cloud.update_point(0, Point2D::new(nan, nan));
+        cloud.check_consistency();
+        cloud.update_point(1, Point2D::new(2., 2.));
+        cloud.check_consistency();
+        assert!(cloud.remove_point(2).is_some());
+        cloud.check_consistency();
+        assert_eq!(cloud.len(), 4);
+    }
+
+    #[test]
+    fn test_split_at_median_x() {
+        // Odd count, pushed in no particular order
+        let xs = [4., 0., 3., 1., 2.];
+        let mut cloud = PointCloud2D::new();
+        for (i, x) in xs.iter().enumerate() {
+            cloud.push_with_id(Point2D::new(*x, i as Float), i as u64);
+        }
+        cloud.connect(1, 3); // 0 - 1, both on the left
+        cloud.connect(0, 2); // 4 - 3, both on the right
+        cloud.connect(3, 4); // 1 - 2, spans the split
+
+        let (left, right) = cloud.split_at_median_x();
+        left.check_consistency();
+        right.check_consistency();
+        assert_eq!(
+            left.points,
+            vec![Point2D::new(0., 1.), Point2D::new(1., 3.)]
+        );
+        assert_eq!(
+            right.points,
+            vec![
+                Point2D::new(4., 0.),
+                Point2D::new(3., 2.),
+                Point2D::new(2., 4.)
+            ]
+        );
+        assert_eq!(left.index_of_id(3), Some(1));
+        assert_eq!(right.index_of_id(4), Some(2));
+        assert_eq!(left.connections(), &[(0, 1)]);
+        assert_eq!(right.connections(), &[(0, 1)]);
+
+        // Even count, unsorted
+        let mut cloud = PointCloud2D::new_unsorted();
+        for x in [5., -1., 2., 8.].iter() {
+            cloud.push(Point2D::new(*x, 0.));
+        }
+        let (left, right) = cloud.split_at_median_x();
+        assert!(left.is_sorted && right.is_sorted);
+        assert_eq!(
+            left.points,
+            vec![Point2D::new(-1., 0.), Point2D::new(2., 0.)]
+        );
+        assert_eq!(
+            right.points,
+            vec![Point2D::new(5., 0.), Point2D::new(8., 0.)]
+        );
+        assert_eq!(left.sort_order_x(), vec![0, 1]);
+        assert_eq!(right.sort_order_x(), vec![0, 1]);
+
+        // Empty
+        let (left, right) = PointCloud2D::new().split_at_median_x();
+        assert!(left.is_empty() && right.is_empty());
+    }
+
+    #[test]
+    fn test_point_style() {
+        let mut cloud = PointCloud2D::new();
+        assert_eq!(cloud.style, PointStyle::regular());
+        assert_eq!(cloud.highlight_style, PointStyle::highlighted());
+        assert_eq!(cloud.style.radius, 5.);
+        assert_eq!(cloud.style.fill_style, "green");
+
+        cloud.set_point_style(2.5, "white", "black", 1.);
+        assert_eq!(
+            cloud.style,
+            PointStyle {
+                radius: 2.5,
+                fill_style: "white".to_string(),
+                stroke_style: "black".to_string(),
+                line_width: 1.,
+            }
+        );
+        assert_eq!(cloud.highlight_style, PointStyle::highlighted());
+
+        cloud.set_highlight_style(10., "yellow", "#333300", 2.);
+        assert_eq!(cloud.highlight_style.radius, 10.);
+        assert_eq!(cloud.highlight_style.fill_style, "yellow");
+        assert_eq!(cloud.highlight_style.stroke_style, "#333300");
+        assert_eq!(cloud.highlight_style.line_width, 2.);
+
+        // Exported as well
+        cloud.push(Point2D::new(0., 0.));
+        let svg = cloud.to_svg(100, 100);
+        assert!(svg.contains("<g fill=\"white\" stroke=\"black\" stroke-width=\"1\">"));
+        assert!(svg.contains("r=\"2.5\""));
+    }
+
+    #[test]
+    fn test_ordered_by_angle() {
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(1., 0.)); // East
+        cloud.push(Point2D::new(-1., 0.)); // West
+        cloud.push(Point2D::new(0., -1.)); // South
+        cloud.push(Point2D::new(0., 1.)); // North
+        let origin = Point2D::new(0., 0.);
+
+        // Counterclockwise, from the South
+        assert_eq!(cloud.ordered_by_angle(&origin), vec![2, 0, 3, 1]);
+
+        // The center goes first
+        cloud.push(origin);
+        assert_eq!(cloud.ordered_by_angle(&origin), vec![4, 2, 0, 3, 1]);
+
+        // Around another center, the origin is not special
+        let center = Point2D::new(0.5, 0.5);
+        assert_eq!(cloud.ordered_by_angle(&center), vec![1, 4, 2, 0, 3]);
+
+        // Same angle, index order
+        let center = Point2D::new(2., 0.);
+        assert_eq!(cloud.ordered_by_angle(&center), vec![2, 3, 0, 1, 4]);
+    }
+
+    #[test]
+    fn test_bounds() {
+        // Empty
+        assert!(PointCloud2D::new().bounds().is_none());
+        assert!(PointCloud2D::new_unsorted().bounds().is_none());
+
+        // A single point
+        let mut sorted = PointCloud2D::new();
+        let mut unsorted = PointCloud2D::new_unsorted();
+        let p = Point2D::new(2., -3.);
+        sorted.push(p);
+        unsorted.push(p);
+        assert_eq!(sorted.bounds(), Some((p, p)));
+        assert_eq!(unsorted.bounds(), Some((p, p)));
+
+        // Scattered
+        for i in 0..30 {
+            let p = Point2D::new((i * 17 % 11) as Float - 4.5, (i * 7 % 13) as Float * 0.3);
+            sorted.push(p);
+            unsorted.push(p);
+        }
+        let mut naive_min = Point2D::new(Float::INFINITY, Float::INFINITY);
+        let mut naive_max = Point2D::new(Float::NEG_INFINITY, Float::NEG_INFINITY);
+        for p in sorted.points() {
+            naive_min.x = naive_min.x.min(p.x);
+            naive_min.y = naive_min.y.min(p.y);
+            naive_max.x = naive_max.x.max(p.x);
+            naive_max.y = naive_max.y.max(p.y);
+        }
+        assert_eq!(sorted.bounds(), Some((naive_min, naive_max)));
+        assert_eq!(unsorted.bounds(), Some((naive_min, naive_max)));
+    }
+
+    #[test]
+    fn test_centroid() {
+        assert!(PointCloud2D::new().centroid().is_none());
+
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(3., 1.));
+        cloud.push(Point2D::new(5., 3.));
+        cloud.push(Point2D::new(3., 5.));
+        cloud.push(Point2D::new(1., 3.));
+        assert_eq!(cloud.centroid(), Some(Point2D::new(3., 3.)));
+
+        let mut cloud = PointCloud2D::new_unsorted();
+        cloud.push(Point2D::new(-2., 7.));
+        assert_eq!(cloud.centroid(), Some(Point2D::new(-2., 7.)));
+    }
+
+    #[test]
+    fn test_update_tolerance() {
+        let mut cloud = PointCloud2D::new();
+        cloud.push(Point2D::new(1., 1.));
+        cloud.push(Point2D::new(2., 2.));
+
+        // Jitter is ignored...
+        cloud.set_update_tolerance(1e-3);
+        cloud.update_point(0, Point2D::new(1.0004, 0.9998));
+        assert_eq!(cloud.points[0], Point2D::new(1., 1.));
+
+        // ... but actual movements are not, even if small in one axis
+        cloud.update_point(0, Point2D::new(1.5, 1.0002));
+        assert_eq!(cloud.points[0], Point2D::new(1.5, 1.0002));
+        cloud.check_consistency();
+
+        cloud.set_update_tolerance(0.);
+        cloud.update_point(1, Point2D::new(2., 2.0004));
+        assert_eq!(cloud.points[1], Point2D::new(2., 2.0004));
+        cloud.check_consistency();
+    }
+
+    #[test]
+    fn test_nearest_world_point() {
+        let n_points = 20;
+        let fixtures: [fn(Float) -> Point2D; 3] = [
+            |i| Point2D::new(i, 0.0),
+            |i| Point2D::new(0.0, i),
+            |i| Point2D::new(i, i),
+        ];
+        for fixture in fixtures.iter() {
+            let mut cloud = PointCloud2D::new();
+            for i in 0..n_points {
+                cloud.push(fixture(i as Float));
+            }
+
+            let p = Point2D::new(-10.0, 0.0);
+            assert_eq!(cloud.nearest_world_point(&p), None);
+
+            for i in 0..n_points {
+                for (dx, dy) in [(0., 0.), (0.1, 0.), (0., -0.2), (0.12, 0.05)].iter() {
+                    let target = fixture(i as Float);
+                    let p = Point2D::new(target.x + dx, target.y + dy);
+                    let (found, distance) = cloud.nearest_world_point(&p).unwrap();
+                    assert_eq!(found, i);
+                    assert_eq!(Some(found), cloud.test_world_point(&p));
+                    assert_eq!(distance, p.distance_to(&cloud.get_point(found).unwrap()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_selected_points() {
+        let mut cloud = PointCloud2D::new();
+        let a = Point2D::new(1., 2.);
+        let b = Point2D::new(-3., 0.5);
+        cloud.push(a);
+        cloud.push(b);
+
+        let found: Vec<Point2D> = cloud.selected_points(&[1, 7, 0, 2, 1]).copied().collect();
+        assert_eq!(found, vec![b, a, b]);
+        assert_eq!(cloud.selected_points(&[]).count(), 0);
+        assert_eq!(PointCloud2D::new().selected_points(&[0, 1]).count(), 0);
+    }
+
+    /// Builds `n` random points with X within `[0, width)` and
+    /// Y within `[0, height)`, rounded to multiples of `step` (so
+    /// there are repeated coordinates when it is large enough)
+    fn random_points(
+        seed: u64,
+        n: usize,
+        width: Float,
+        height: Float,
+        step: Float,
+    ) -> Vec<Point2D> {
+        let mut random = Random::new(seed);
+        (0..n)
+            .map(|_| {
+                let x = (random.next_float() * width / step).floor() * step;
+                let y = (random.next_float() * height / step).floor() * step;
+                Point2D::new(x, y)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_spatial_hash() {
+        let clouds = [
+            random_points(1, 1000, 50., 50., 0.01),
+            // Packed along the X axis
+            random_points(2, 1000, 100., 0.5, 0.01),
+            // Lots of repeated points
+            random_points(3, 1000, 10., 10., 0.25),
+        ];
+        for (i, points) in clouds.iter().enumerate() {
+            let plain = PointCloud2D::from_points(points.clone());
+            let mut hashed = PointCloud2D::from_points(points.clone());
+            hashed.use_spatial_hash(0.25 + i as Float);
+
+            let cursors = random_points(10 + i as u64, 300, 60., 60., 0.001);
+            // Repeated points might tie, and the one found first wins... so
+            // the points found need to be as close, not necessarily the same
+            let distance = |cloud: &PointCloud2D, p: &Point2D, found: Option<usize>| {
+                found.map(|i| cloud.points[i].squared_distance_to(p))
+            };
+            let compare = |plain: &PointCloud2D, hashed: &PointCloud2D| {
+                for p in cursors.iter().chain(points.iter()) {
+                    assert_eq!(
+                        hashed.nearest_world_point(p).map(|(_, d)| d),
+                        plain.nearest_world_point(p).map(|(_, d)| d)
+                    );
+                    for max_distance in [0., 0.1, 1., 3.].iter() {
+                        assert_eq!(
+                            distance(hashed, p, hashed.test_world_point_within(p, *max_distance)),
+                            distance(plain, p, plain.test_world_point_within(p, *max_distance))
+                        );
+                    }
+                }
+            };
+            compare(&plain, &hashed);
+
+            // The hash follows the changes in the points
+            let mut plain = plain;
+            for cloud in [&mut plain, &mut hashed].iter_mut() {
+                cloud.push(Point2D::new(30.01, 0.2));
+                cloud.update_point(7, Point2D::new(1.5, 0.3));
+                cloud.remove_point(3);
+            }
+            compare(&plain, &hashed);
+
+            // Back to the default search
+            hashed.use_spatial_hash(0.);
+            assert!(hashed.spatial_hash_cell_size.is_none());
+            compare(&plain, &hashed);
+        }
+    }
+
+    /// Compares the time it takes to find points with and without a spatial hash.
+    /// Run it with `cargo test -- --ignored --nocapture bench_`
+    #[test]
+    #[ignore]
+    fn bench_test_world_point() {
+        let n = 50_000;
+        let cases = [("sparse", 500.), ("dense", 20.)];
+        for (name, size) in cases.iter() {
+            let points = random_points(1, n, *size, *size, 0.001);
+            let cursors = random_points(2, 10_000, *size, *size, 0.001);
+            let mut cloud = PointCloud2D::from_points(points);
+            for cell_size in [None, Some(0.25)].iter() {
+                cloud.use_spatial_hash(cell_size.unwrap_or(0.));
+
+                let start = std::time::Instant::now();
+                cloud.test_world_point(&cursors[0]);
+                let first = start.elapsed();
+
+                let start = std::time::Instant::now();
+                let found = cursors
+                    .iter()
+                    .filter(|p| cloud.test_world_point(p).is_some())
+                    .count();
+                println!(
+                    "{} | spatial hash: {:?} | first search in {:?} | {} points found in {:?}",
+                    name,
+                    cell_size,
+                    first,
+                    found,
+                    start.elapsed()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_translate_all() {
+        let points = vec![
+            Point2D::new(3., -1.),
+            Point2D::new(-2., 4.),
+            Point2D::new(0.5, 0.5),
+            Point2D::new(3., 2.),
+            Point2D::new(-7., -1.),
+        ];
+        let mut cloud = PointCloud2D::from_points(points.clone());
+        let (sorted_x, sorted_y) = (cloud.sorted_x.clone(), cloud.sorted_y.clone());
+        let (positions_x, positions_y) = (cloud.positions_x.clone(), cloud.positions_y.clone());
+
+        cloud.translate_all(10., -2.5);
+        cloud.check_consistency();
+        for (p, moved) in points.iter().zip(cloud.points()) {
+            assert_eq!(*moved, Point2D::new(p.x + 10., p.y - 2.5));
+        }
+        assert_eq!(cloud.sorted_x, sorted_x);
+        assert_eq!(cloud.sorted_y, sorted_y);
+        assert_eq!(cloud.positions_x, positions_x);
+        assert_eq!(cloud.positions_y, positions_y);
+
+        // Same as moving them one by one
+        let mut one_by_one = PointCloud2D::from_points(points.clone());
+        for i in 0..points.len() {
+            one_by_one.translate_point(i, 10., -2.5);
+        }
+        assert_eq!(one_by_one.points(), cloud.points());
+        assert_eq!(one_by_one.sorted_x, cloud.sorted_x);
+        assert_eq!(one_by_one.sorted_y, cloud.sorted_y);
+
+        // Still searchable
+        assert_eq!(cloud.test_world_point(&Point2D::new(10.5, -2.)), Some(2));
+        assert_eq!(cloud.test_world_point(&Point2D::new(0.5, 0.5)), None);
+    }
+
+    #[test]
+    fn test_json() {
+        let points = [
+            Point2D::new(3., -1.),
+            Point2D::new(-2., 4.25),
+            Point2D::new(0.1, 1e-7),
+            Point2D::new(3., 2.),
+        ];
+        for is_sorted in [true, false].iter() {
+            let mut cloud = if *is_sorted {
+                PointCloud2D::new()
+            } else {
+                PointCloud2D::new_unsorted()
+            };
+            for p in points.iter() {
+                cloud.push(*p);
+            }
+
+            let loaded = PointCloud2D::from_json(&cloud.to_json()).unwrap();
+            loaded.check_consistency();
+            assert_eq!(loaded.points(), cloud.points());
+            assert_eq!(loaded.is_sorted, *is_sorted);
+            assert_eq!(loaded.sorted_x, cloud.sorted_x);
+            assert_eq!(loaded.sorted_y, cloud.sorted_y);
+            assert_eq!(loaded.ids.len(), points.len());
+        }
+
+        let loaded = PointCloud2D::from_json(&PointCloud2D::new().to_json()).unwrap();
+        assert_eq!(loaded.len(), 0);
+
+        // Nonsense
+        let bad = [
+            "",
+            "[]",
+            "{",
+            "{\"points\": [], \"is_sorted\": 1}",
+            "{\"points\": [{\"x\": 1}], \"is_sorted\": true}",
+            "{\"points\": [{\"x\": \"a\", \"y\": 2}], \"is_sorted\": true}",
+            "{\"points\": [{\"x\": null, \"y\": 2}], \"is_sorted\": true}",
+            "{\"is_sorted\": true}",
+        ];
+        for s in bad.iter() {
+            assert!(PointCloud2D::from_json(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_json_connections() {
+        for is_sorted in [true, false].iter() {
+            let mut cloud = if *is_sorted {
+                PointCloud2D::new()
+            } else {
+                PointCloud2D::new_unsorted()
+            };
+            for (x, y) in [(3., -1.), (-2., 4.25), (0.1, 1e-7), (3., 2.)].iter() {
+                cloud.push(Point2D::new(*x, *y));
+            }
+            cloud.connect(0, 3);
+            cloud.connect(2, 1);
+            cloud.connect(3, 2);
+
+            let loaded = PointCloud2D::from_json(&cloud.to_json()).unwrap();
+            loaded.check_consistency();
+            assert_eq!(loaded.points(), cloud.points());
+            assert_eq!(loaded.connections(), cloud.connections());
+            assert_eq!(loaded.to_json(), cloud.to_json());
+        }
+
+        // Files without connections (i.e., older ones) still load
+        let old = "{\"points\": [{\"x\": 0, \"y\": 0}], \"is_sorted\": true}";
+        let loaded = PointCloud2D::from_json(old).unwrap();
+        assert!(loaded.connections().is_empty());
+
+        // Written by something else: cleaned up with `normalize_edges()`
+        let messy = "{\"points\": [{\"x\": 0, \"y\": 0}, {\"x\": 1, \"y\": 0}], \"is_sorted\": false, \"edges\": [[1, 0], [0, 1], [1, 1]]}";
+        let loaded = PointCloud2D::from_json(messy).unwrap();
+        assert_eq!(loaded.connections(), &[(0, 1)]);
+
+        // Connecting points that do not exist
+        let bad = "{\"points\": [{\"x\": 0, \"y\": 0}], \"is_sorted\": true, \"edges\": [[0, 1]]}";
+        assert!(PointCloud2D::from_json(bad).is_err());
+    }
+
+    #[test]
+    fn test_json_ids() {
+        for is_sorted in [true, false].iter() {
+            let mut cloud = if *is_sorted {
+                PointCloud2D::new()
+            } else {
+                PointCloud2D::new_unsorted()
+            };
+            cloud.push_with_id(Point2D::new(3., -1.), 7);
+            cloud.push(Point2D::new(-2., 4.25));
+            cloud.push_with_id(Point2D::new(0.1, 1e-7), u64::MAX);
 
-        // Test case |   ----- O ----- P --------- O --
-        //                   (0,0)    (0.5,0)    (1,0)
-        assert_eq!(cloud.find_point_position_x(0.5).unwrap(), 1);
+            let loaded = PointCloud2D::from_json(&cloud.to_json()).unwrap();
+            loaded.check_consistency();
+            assert_eq!(loaded.ids, cloud.ids);
+            assert_eq!(loaded.index_of_id(7), Some(0));
+            assert_eq!(loaded.index_of_id(u64::MAX), Some(2));
+            assert_eq!(loaded.to_json(), cloud.to_json());
+        }
 
-        // Test case |   -- O --------- OP--
-        //                (0,0)        (1,0)
-        //                             (1,0)
-        assert_eq!(cloud.find_point_position_x(1.).unwrap(), 2);
+        // Files without IDs (i.e., older ones) still load, without IDs
+        let old = "{\"points\": [{\"x\": 0, \"y\": 0}, {\"x\": 1, \"y\": 0}], \"is_sorted\": true}";
+        let loaded = PointCloud2D::from_json(old).unwrap();
+        assert_eq!(loaded.ids, vec![None, None]);
 
-        // Test case |   -- O --------- O ------- P
-        //                (0,0)        (1,0)     (2,0)
-        assert_eq!(cloud.find_point_position_x(2.).unwrap(), 2);
+        // As many IDs as points
+        let bad = "{\"points\": [{\"x\": 0, \"y\": 0}], \"is_sorted\": true, \"ids\": [1, null]}";
+        assert!(PointCloud2D::from_json(bad).is_err());
     }
 
     #[test]
-    fn test_find_point_position_y() {
-        /******************************** */
-        /* Empty case... should return 0. */
-        /******************************** */
-        let cloud = PointCloud2D::new();
-        cloud.check_consistency();
-        assert_eq!(cloud.find_point_position_y(1.).unwrap(), 0);
+    fn test_merge() {
+        let first = [
+            Point2D::new(3., -1.),
+            Point2D::new(-2., 4.),
+            Point2D::new(0.5, 2.),
+        ];
+        let second = [
+            Point2D::new(3., 2.),
+            Point2D::new(-7., -1.),
+            Point2D::new(0.5, 0.5),
+        ];
+        let build = |points: &[Point2D], is_sorted: bool| {
+            let mut cloud = if is_sorted {
+                PointCloud2D::new()
+            } else {
+                PointCloud2D::new_unsorted()
+            };
+            for p in points.iter() {
+                cloud.push(*p);
+            }
+            cloud
+        };
 
-        /******************************** */
-        /* 1 point case. */
-        /******************************** */
+        let sortings = [(true, true), (true, false), (false, true), (false, false)];
+        for (sorted_a, sorted_b) in sortings.iter() {
+            let mut merged = build(&first, *sorted_a);
+            let mut other = build(&second, *sorted_b);
+            other.connect(0, 2);
+            merged.connect(0, 1);
+            merged.merge(&other);
+            merged.check_consistency();
 
-        // Y axis is | ----- O ------
-        //                 (0,0)
-        let cloud = PointCloud2D {
-            points: vec![Point2D { x: 0.0, y: 0.0 }],
-            positions_x: vec![0],
-            positions_y: vec![0],
-            sorted_x: vec![0],
-            sorted_y: vec![0],
-            is_sorted:true,
-        };
+            let all: Vec<Point2D> = first.iter().chain(second.iter()).copied().collect();
+            let pushed = build(&all, *sorted_a && *sorted_b);
+            assert_eq!(merged.points(), pushed.points());
+            assert_eq!(merged.is_sorted, pushed.is_sorted);
+            assert_eq!(merged.sorted_x, pushed.sorted_x);
+            assert_eq!(merged.sorted_y, pushed.sorted_y);
+            assert_eq!(merged.positions_x, pushed.positions_x);
+            assert_eq!(merged.positions_y, pushed.positions_y);
+            assert_eq!(merged.connections(), &[(0, 1), (3, 5)]);
+            if merged.is_sorted {
+                assert_eq!(merged.test_world_point(&Point2D::new(0.5, 0.6)), Some(5));
+            }
+        }
+
+        // Merging nothing
+        let mut cloud = build(&first, true);
+        cloud.merge(&PointCloud2D::new());
+        assert_eq!(cloud.points(), &first);
         cloud.check_consistency();
+    }
 
-        // Testing case:
-        // Y axis is   - P ---------- O ------
-        //            (0,-1)        (0,0)
-        assert_eq!(cloud.find_point_position_y(-1.).unwrap(), 0);
+    #[test]
+    fn test_point_coordinates() {
+        let mut cloud = PointCloud2D::new();
+        assert!(cloud.point_xs().is_empty());
+        assert!(cloud.point_ys().is_empty());
+        assert_eq!(cloud.points_count(), 0);
 
-        // Testing case:
-        // Y axis is   ---- O ------ P
-        //                (0,0)    (0,1)
-        assert_eq!(cloud.find_point_position_y(1.).unwrap(), 1);
+        cloud.push(Point2D::new(3., -1.));
+        cloud.push(Point2D::new(-2., 4.));
+        cloud.push(Point2D::new(0.5, 2.));
+        assert_eq!(cloud.point_xs(), vec![3., -2., 0.5]);
+        assert_eq!(cloud.point_ys(), vec![-1., 4., 2.]);
+        assert_eq!(cloud.points_count(), 3);
 
-        // Testing case:
-        // Y axis is   ---- OP ------
-        //                (0,0)
-        //                (0,0)
-        assert_eq!(cloud.find_point_position_y(0.).unwrap(), 1);
+        // Still aligned after removing a point
+        cloud.remove_point(0);
+        assert_eq!(cloud.point_xs(), vec![-2., 0.5]);
+        assert_eq!(cloud.point_ys(), vec![4., 2.]);
+        assert_eq!(cloud.points_count(), 2);
+    }
 
-        /******************************** */
-        /*  2 point cases */
-        /******************************** */
-        // X axis is | ----- O --------- O --
-        //                 (0,0)        (0,1)
-        let cloud = PointCloud2D {
-            points: vec![Point2D { x: 0.0, y: 0.0 }, Point2D { x: 0., y: 1. }],
-            positions_x: vec![0, 1],
-            positions_y: vec![0, 1],
-            sorted_x: vec![0, 1],
-            sorted_y: vec![0, 1],
-            is_sorted:true,
-        };
-        cloud.check_consistency();
+    #[test]
+    fn test_undo_redo() {
+        for is_sorted in [true, false].iter() {
+            let mut cloud = if *is_sorted {
+                PointCloud2D::new()
+            } else {
+                PointCloud2D::new_unsorted()
+            };
 
-        // Test case |   P --------- O --------- O --
-        //             (0,-1)      (0,0)        (0,1)
-        assert_eq!(cloud.find_point_position_y(-1.).unwrap(), 0);
+            // Nothing is recorded by default
+            cloud.push(Point2D::new(1., 1.));
+            assert!(!cloud.undo());
+            cloud.remove_point(0);
 
-        // Test case |   -- OP --------- O --
-        //                (0,0)        (0,1)
-        //                (0,0)
-        assert_eq!(cloud.find_point_position_y(0.).unwrap(), 1);
+            cloud.set_history_depth(10);
+            let mut states = vec![cloud.points().to_vec()];
+            let mut snapshot = |cloud: &PointCloud2D| {
+                cloud.check_consistency();
+                states.push(cloud.points().to_vec());
+            };
 
-        // Test case |   ----- O ----- P --------- O --
-        //                   (0,0)  (0,0.5)      (0,1)
-        assert_eq!(cloud.find_point_position_y(0.5).unwrap(), 1);
+            cloud.push(Point2D::new(3., -1.));
+            snapshot(&cloud);
+            cloud.push_with_id(Point2D::new(-2., 4.), 99);
+            snapshot(&cloud);
+            cloud.push(Point2D::new(0.5, 2.));
+            snapshot(&cloud);
+            cloud.push(Point2D::new(7., 7.));
+            snapshot(&cloud);
+            cloud.connect(0, 1);
+            cloud.connect(1, 3);
+            cloud.connect(2, 3);
+            cloud.update_point(1, Point2D::new(10., -3.));
+            snapshot(&cloud);
+            cloud.update_point_x(2, -4.);
+            snapshot(&cloud);
+            cloud.translate_point(0, 0.5, 0.5);
+            snapshot(&cloud);
+            cloud.translate_all(1., 2.);
+            snapshot(&cloud);
+            cloud.remove_point(1);
+            snapshot(&cloud);
 
-        // Test case |   -- O --------- OP--
-        //                (0,0)        (0,1)
-        //                             (0,1)
-        assert_eq!(cloud.find_point_position_y(1.).unwrap(), 2);
+            // Undo everything
+            for state in states.iter().rev().skip(1) {
+                assert!(cloud.undo());
+                cloud.check_consistency();
+                assert_eq!(cloud.points(), &state[..]);
+                if cloud.len() == 4 {
+                    // The removed point is back, with its ID and connections
+                    let mut connections = cloud.connections().to_vec();
+                    connections.sort_unstable();
+                    assert_eq!(connections, vec![(0, 1), (1, 3), (2, 3)]);
+                    assert_eq!(cloud.index_of_id(99), Some(1));
+                }
+            }
+            assert!(!cloud.undo());
+            assert!(cloud.is_empty());
 
-        // Test case |   -- O --------- O ------- P
-        //                (0,0)       (0,1)     (0,2)
-        assert_eq!(cloud.find_point_position_y(2.).unwrap(), 2);
+            // And redo it
+            for state in states.iter().skip(1) {
+                assert!(cloud.redo());
+                cloud.check_consistency();
+                assert_eq!(cloud.points(), &state[..]);
+            }
+            assert!(!cloud.redo());
+            assert_eq!(cloud.index_of_id(99), None);
+
+            // Connections are not recorded, so those of the points
+            // that were pushed again are gone
+            assert!(cloud.connections().is_empty());
+
+            // A new change forgets what was undone
+            assert!(cloud.undo());
+            cloud.push(Point2D::new(0., 0.));
+            assert!(!cloud.redo());
+
+            // Only the latest changes are kept
+            cloud.set_history_depth(2);
+            assert!(cloud.undo());
+            assert!(cloud.undo());
+            assert!(!cloud.undo());
+        }
     }
 
     #[test]
-    fn test_push() {
-        /******************************** */
-        /* Empty case... should return 0. */
-        /******************************** */
-        let mut cloud = PointCloud2D::new();
-        cloud.check_consistency();
-        let p = Point2D { x: 0., y: 0. };
-        cloud.push(p);
-        assert_eq!(cloud.sorted_x, vec![0]);
-        assert_eq!(cloud.sorted_y, vec![0]);
-        assert_eq!(cloud.points, vec![p]);
-        cloud.check_consistency();
+    fn test_clear() {
+        for is_sorted in [true, false].iter() {
+            let new_cloud = || {
+                if *is_sorted {
+                    PointCloud2D::new()
+                } else {
+                    PointCloud2D::new_unsorted()
+                }
+            };
+            let points = [
+                Point2D::new(3., -1.),
+                Point2D::new(-2., 4.),
+                Point2D::new(0.5, 2.),
+            ];
 
-        /******************************** */
-        /* 1 point case. */
-        /******************************** */
+            let mut cloud = new_cloud();
+            for p in points.iter() {
+                cloud.push(*p);
+            }
+            cloud.connect(0, 1);
+            cloud.flash_point(2, 0., 100.);
+            let capacity = cloud.points.capacity();
 
-        // X axis is | ----- O ------
-        //                 (0,0)
-        let mut cloud = PointCloud2D {
-            points: vec![Point2D { x: 0.0, y: 0.0 }],
-            positions_x: vec![0],
-            positions_y: vec![0],
-            sorted_x: vec![0],
-            sorted_y: vec![0],
-            is_sorted:true,
-        };
-        cloud.check_consistency();
+            cloud.clear();
+            assert!(cloud.is_empty());
+            assert!(cloud.connections().is_empty());
+            assert!(cloud.flash.is_none());
+            assert_eq!(cloud.is_sorted, *is_sorted);
+            assert_eq!(cloud.points.capacity(), capacity);
+            cloud.check_consistency();
 
-        // Testing case:
-        // X axis is   - P ---------- O ------
-        //            (-1,0)        (0,0)
-        let p = Point2D { x: -1.0, y: 0.0 };
-        cloud.push(p);
-        assert_eq!(cloud.sorted_x, vec![1, 0]);
-        assert_eq!(cloud.sorted_y, vec![0, 1]);
-        assert_eq!(cloud.points, vec![Point2D { x: 0.0, y: 0.0 }, p]);
-        cloud.check_consistency();
+            // Like a new one
+            let mut fresh = new_cloud();
+            for p in points.iter().rev() {
+                cloud.push(*p);
+                fresh.push(*p);
+            }
+            cloud.check_consistency();
+            assert_eq!(cloud.points(), fresh.points());
+            assert_eq!(cloud.sorted_x, fresh.sorted_x);
+            assert_eq!(cloud.sorted_y, fresh.sorted_y);
+            assert_eq!(cloud.positions_x, fresh.positions_x);
+            assert_eq!(cloud.positions_y, fresh.positions_y);
+            assert_eq!(cloud.ids, fresh.ids);
+        }
     }
 
     #[test]
-    fn test_update_point() {
-        let a = Point2D { x: 0.0, y: 0.0 };
-        let b = Point2D { x: 1.0, y: 0.0 };
-        let c = Point2D { x: 2.0, y: 0.0 };
+    fn test_snap() {
+        for sorted in [true, false].iter() {
+            let mut cloud = if *sorted {
+                PointCloud2D::new()
+            } else {
+                PointCloud2D::new_unsorted()
+            };
+            cloud.push(Point2D::new(1.1, 2.3));
+            cloud.push(Point2D::new(-4., 0.4));
 
-        /******************************** */
-        /*  3 points cases */
-        /******************************** */
-        // X axis is | ----- O --------- O -------- O
-        //                 A(0,0)        B(1,0)     C(2,0)
-        let mut cloud = PointCloud2D {
-            points: vec![a, b, c],
-            positions_x: vec![0, 1, 2],
-            positions_y: vec![0, 1, 2],
-            sorted_x: vec![0, 1, 2],
-            sorted_y: vec![0, 1, 2],
-            is_sorted:true,
-        };
-        cloud.check_consistency();
+            // Close to a point
+            let p = Point2D::new(1.2, 2.2);
+            assert_eq!(cloud.snap(&p, 0.25, 1.), Point2D::new(1.1, 2.3));
+            assert_eq!(cloud.snap(&p, 0.25, 0.), Point2D::new(1.1, 2.3));
 
-        // Move A to the left (nothing should happen)
-        let new_a = Point2D { x: -1., y: 0. };
-        cloud.update_point(0, new_a);
-        assert_eq!(cloud.positions_x, vec![0, 1, 2]);
-        assert_eq!(cloud.sorted_x, vec![0, 1, 2]);
-        assert_eq!(cloud.points[0], new_a);        
-        // Does not change (we did not even touched sorted_y and positions_y)
-        assert_eq!(cloud.positions_y, vec![0,1,2]);
+            // Too far from it, so it goes to the grid
+            assert_eq!(cloud.snap(&p, 0.1, 1.), Point2D::new(1., 2.));
+            assert_eq!(cloud.snap(&p, 0., 0.5), Point2D::new(1., 2.));
+            let p = Point2D::new(-3.4, 0.9);
+            assert_eq!(cloud.snap(&p, 0.5, 1.), Point2D::new(-3., 1.));
 
-        // Move A to the very right... new order is [b,c,a]
-        let new_a = Point2D { x: 12., y: 0. };
-        cloud.update_point(0, new_a);
-        assert_eq!(cloud.positions_x, vec![2, 0, 1]);
-        assert_eq!(cloud.sorted_x, vec![1, 2, 0]);        
-        assert_eq!(cloud.sorted_y, vec![0, 1, 2]);
-        assert_eq!(cloud.positions_y, vec![0, 1, 2]);
-        assert_eq!(cloud.points[0], new_a);
+            // Nothing to snap to
+            assert_eq!(cloud.snap(&p, 0., 0.), p);
+            assert_eq!(cloud.snap(&p, -1., -1.), p);
+        }
+        let p = Point2D::new(-3.4, 0.9);
+        assert_eq!(PointCloud2D::new().snap(&p, 0.5, 0.), p);
+        assert_eq!(PointCloud2D::new_unsorted().snap(&p, 0.5, 0.), p);
     }
 
     #[test]
-    fn test_test_point() {
-        let n_points = 20;
+    fn test_convex_hull() {
+        // A square with a point inside
+        let cloud = PointCloud2D::from_points(vec![
+            Point2D::new(1., 1.),
+            Point2D::new(0., 0.),
+            Point2D::new(2., 0.),
+            Point2D::new(2., 2.),
+            Point2D::new(0., 2.),
+        ]);
+        let hull = cloud.convex_hull();
+        assert_eq!(hull.len(), 4);
+        assert!(!hull.contains(&0));
 
-        /* ************** */
-        /* ALL HORIZONTAL */
-        /* ************** */
-        let mut cloud = PointCloud2D::new();
-        for i in 0..n_points {
-            cloud.push(Point2D::new(i as Float, 0.0));
+        // Counter-clockwise, starting anywhere
+        let start = hull.iter().position(|i| *i == 1).unwrap();
+        let ordered: Vec<usize> = (0..4).map(|k| hull[(start + k) % 4]).collect();
+        assert_eq!(ordered, vec![1, 2, 3, 4]);
+
+        // Collinear points only keep the ends
+        let mut cloud = PointCloud2D::new_unsorted();
+        for x in [3., 0., 1., 2.].iter() {
+            cloud.push(Point2D::new(*x, *x));
         }
-        // These are out of the clould altogether
-        let p = Point2D::new(-10.0, 0.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+        let mut hull = cloud.convex_hull();
+        hull.sort_unstable();
+        assert_eq!(hull, vec![0, 1]);
 
-        let p = Point2D::new(100.0, 0.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+        // Also on the edges of a triangle
+        cloud.push(Point2D::new(3., 0.));
+        cloud.push(Point2D::new(1.5, 0.));
+        assert_eq!(cloud.convex_hull().len(), 3);
 
-        let p = Point2D::new(0.0, -1.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+        // Too few points
+        let mut cloud = PointCloud2D::new();
+        assert!(cloud.convex_hull().is_empty());
+        cloud.push(Point2D::new(0., 0.));
+        cloud.push(Point2D::new(1., 0.));
+        assert_eq!(cloud.convex_hull(), vec![0, 1]);
+    }
 
-        let p = Point2D::new(0.0, 1.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+    #[test]
+    fn test_nearest_segment() {
+        let cloud = PointCloud2D::from_points(vec![
+            Point2D::new(0., 0.),
+            Point2D::new(4., 0.),
+            Point2D::new(4., 4.),
+            Point2D::new(0., 4.),
+        ]);
+        let segments = [(0, 1), (1, 2), (2, 3), (3, 0)];
 
-        let p = Point2D::new(30.0, 2.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+        // Near the middle of a segment, far from its ends
+        let p = Point2D::new(2., 0.2);
+        assert_eq!(cloud.nearest_segment(&p, &segments, 0.25), Some(0));
+        let p = Point2D::new(3.9, 2.);
+        assert_eq!(cloud.nearest_segment(&p, &segments, 0.25), Some(1));
 
-        // These are in
-        for i in 0..n_points {
-            let p = Point2D::new(i as Float, 0.0);
-            assert_eq!(cloud.test_world_point(&p), Some(i));
-        }
+        // Beyond the end of a segment, the distance is to that end
+        let segments_beyond = [(0, 1)];
+        let p = Point2D::new(4.2, 0.);
+        assert_eq!(cloud.nearest_segment(&p, &segments_beyond, 0.25), Some(0));
+        let p = Point2D::new(4.2, 0.2);
+        assert_eq!(cloud.nearest_segment(&p, &segments_beyond, 0.25), None);
+        assert_eq!(cloud.nearest_segment(&p, &segments_beyond, 0.3), Some(0));
 
-        /* ************** */
-        /* ALL VERTICAL */
-        /* ************** */
-        let mut cloud = PointCloud2D::new();
-        for i in 0..n_points {
-            cloud.push(Point2D::new(0.0, i as Float));
-        }
-        // These are out of the clould altogether
-        let p = Point2D::new(0.0, -10.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+        // Outside the threshold
+        let p = Point2D::new(2., 2.);
+        assert_eq!(cloud.nearest_segment(&p, &segments, 1.), None);
+        assert_eq!(cloud.nearest_segment(&p, &segments, 2.), Some(0));
 
-        let p = Point2D::new(0.0, 110.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+        // Invalid segments are ignored
+        let p = Point2D::new(2., 0.);
+        assert_eq!(cloud.nearest_segment(&p, &[(0, 9), (0, 1)], 1.), Some(1));
+        assert_eq!(cloud.nearest_segment(&p, &[], 1.), None);
+    }
 
-        let p = Point2D::new(1.0, 0.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+    #[test]
+    fn test_remove_points_in_rect() {
+        for sorted in [true, false].iter() {
+            let mut cloud = if *sorted {
+                PointCloud2D::new()
+            } else {
+                PointCloud2D::new_unsorted()
+            };
+            // A 4x4 grid, going row by row
+            for y in 0..4 {
+                for x in 0..4 {
+                    cloud.push(Point2D::new(x as Float, y as Float));
+                }
+            }
+            cloud.push_with_id(Point2D::new(3.5, 3.5), 7);
+            cloud.connect(0, 15); // removed
+            cloud.connect(15, 16); // kept
+            cloud.flash_point(16, 0., 1000.);
+            cloud.set_history_depth(10);
+            cloud.push(Point2D::new(9., 9.));
+            cloud.remove_point(17);
 
-        let p = Point2D::new(-1.0, 0.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+            // Nothing there
+            let removed = cloud.remove_points_in_rect(&Point2D::new(5., 5.), &Point2D::new(6., 6.));
+            assert_eq!(removed, 0);
+            assert_eq!(cloud.len(), 17);
 
-        let p = Point2D::new(30.0, 2.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+            // The bottom-left corner, borders included
+            let removed =
+                cloud.remove_points_in_rect(&Point2D::new(-1., -1.), &Point2D::new(1., 1.));
+            assert_eq!(removed, 4);
+            assert_eq!(cloud.len(), 13);
+            let expected: Vec<Point2D> = (0..4)
+                .flat_map(|y| (0..4).map(move |x| Point2D::new(x as Float, y as Float)))
+                .filter(|p| p.x > 1. || p.y > 1.)
+                .chain(std::iter::once(Point2D::new(3.5, 3.5)))
+                .collect();
+            assert_eq!(cloud.points(), &expected[..]);
+            assert_eq!(cloud.index_of_id(7), Some(12));
+            assert_eq!(cloud.connections(), &[(11, 12)]);
+            assert_eq!(cloud.flash, Some((12, 0., 1000.)));
+            assert_eq!(cloud.test_world_point(&Point2D::new(2., 2.)), Some(6));
+            assert!(!cloud.undo());
 
-        // These are in
-        for i in 0..n_points {
-            let p = Point2D::new(0.0, i as Float);
-            assert_eq!(cloud.test_world_point(&p), Some(i));
+            // The whole cloud
+            let removed =
+                cloud.remove_points_in_rect(&Point2D::new(-10., -10.), &Point2D::new(10., 10.));
+            assert_eq!(removed, 13);
+            assert!(cloud.is_empty());
+            assert!(cloud.connections().is_empty());
+            assert!(cloud.flash.is_none());
+            assert_eq!(cloud.is_sorted, *sorted);
         }
+    }
 
-        /* ************** */
-        /* 45 DEGREES */
-        /* ************** */
+    #[test]
+    fn test_visible_points() {
+        let points: Vec<Point2D> = (0..40)
+            .flat_map(|i| (0..30).map(move |j| (i, j)))
+            .map(|(i, j)| Point2D::new(0.25 * i as Float - 5., 0.25 * j as Float - 3.75))
+            .collect();
+        let sorted = PointCloud2D::from_points(points.clone());
+        let mut unsorted = PointCloud2D::new_unsorted();
+        for p in points {
+            unsorted.push(p);
+        }
 
-        let mut cloud = PointCloud2D::new();
-        for i in 0..n_points {
-            cloud.push(Point2D::new(i as Float, i as Float));
+        let mut vp = Viewport {
+            center: Point2D::new(0., 0.),
+            width: 2.,
+            canvas_width: 300.,
+            canvas_height: 200.,
+        };
+        // Some of these have points right on the border of the canvas
+        for (center, width) in [
+            ((0., 0.), 2.),
+            ((0., 0.), 3.),
+            ((1.3, -0.7), 0.7),
+            ((0.5, 0.5), 1.),
+            ((6.0, 3.0), 4.),
+            ((-50., 0.), 2.),
+            ((0., 0.), 100.),
+        ]
+        .iter()
+        {
+            vp.center = Point2D::new(center.0, center.1);
+            vp.width = *width;
+            let brute_force: Vec<usize> = (0..sorted.points.len())
+                .filter(|i| vp.as_canvas_point(&sorted.points[*i]).1)
+                .collect();
+
+            assert_eq!(sorted.visible_points(&vp), brute_force);
+            assert_eq!(unsorted.visible_points(&vp), brute_force);
         }
+    }
 
-        // These are out of the clould altogether
-        let p = Point2D::new(0.0, -10.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+    #[test]
+    fn test_push_unique() {
+        for sorted in [true, false].iter() {
+            let mut cloud = if *sorted {
+                PointCloud2D::new()
+            } else {
+                PointCloud2D::new_unsorted()
+            };
+            cloud.push(Point2D::new(0., 0.));
+            cloud.push(Point2D::new(2., 1.));
 
-        let p = Point2D::new(0.0, 110.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+            // An exact duplicate
+            assert_eq!(cloud.push_unique(Point2D::new(2., 1.), 0.), Some(1));
+            assert_eq!(cloud.len(), 2);
 
-        let p = Point2D::new(1.0, 0.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+            // Within the tolerance
+            assert_eq!(cloud.push_unique(Point2D::new(0.1, -0.1), 0.2), Some(0));
+            assert_eq!(cloud.push_unique(Point2D::new(0., 0.199), 0.2), Some(0));
+            assert_eq!(cloud.len(), 2);
 
-        let p = Point2D::new(-1.0, 0.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+            // Just outside of it
+            assert_eq!(cloud.push_unique(Point2D::new(0., 0.2), 0.2), Some(2));
+            assert_eq!(cloud.len(), 3);
+            assert_eq!(cloud.points[2], Point2D::new(0., 0.2));
 
-        let p = Point2D::new(30.0, 2.0);
-        assert_eq!(cloud.test_world_point(&p), None);
+            // The closest one is matched
+            assert_eq!(cloud.push_unique(Point2D::new(0., 0.15), 0.2), Some(2));
 
-        // These are in
-        for i in 0..n_points {
-            let p = Point2D::new(i as Float, i as Float);
-            assert_eq!(cloud.test_world_point(&p), Some(i));
+            // Nonsense
+            assert_eq!(cloud.push_unique(Point2D::new(5., 5.), -1.), Some(3));
+            assert_eq!(cloud.push_unique(Point2D::new(Float::NAN, 0.), 1.), None);
+            assert_eq!(cloud.len(), 4);
         }
     }
 }
@@ -0,0 +1,236 @@
+use crate::Float;
+
+use crate::point2d::Point2D;
+use crate::pointcloud2d::PointCloud2D;
+
+/// How many times a QuadNode can be subdivided. This stops the
+/// recursion when there are too many coincident points.
+const MAX_DEPTH: usize = 32;
+
+/// A node in a quadtree of the points of a PointCloud2D: an axis-aligned
+/// box that is either split into four quadrants or is a leaf holding the
+/// indexes of the points within it.
+#[derive(Clone, Debug)]
+pub struct QuadNode {
+    /// The bottom-left corner of the box
+    min: Point2D,
+
+    /// The top-right corner of the box
+    max: Point2D,
+
+    /// The indexes of the points within the box (only in leaves)
+    indexes: Vec<usize>,
+
+    /// The four quadrants of the box (empty in leaves)
+    children: Vec<QuadNode>,
+}
+
+impl QuadNode {
+    /// Builds the tree of the `indexes` of `points` within the `[min, max]` box,
+    /// subdividing it until each leaf holds at most `max_points_per_leaf` points.
+    pub(crate) fn build(
+        points: &[Point2D],
+        indexes: Vec<usize>,
+        min: Point2D,
+        max: Point2D,
+        max_points_per_leaf: usize,
+    ) -> Self {
+        Self::build_at_depth(points, indexes, min, max, max_points_per_leaf.max(1), 0)
+    }
+
+    /// Builds a node that is `depth` levels below the root
+    fn build_at_depth(
+        points: &[Point2D],
+        indexes: Vec<usize>,
+        min: Point2D,
+        max: Point2D,
+        max_points_per_leaf: usize,
+        depth: usize,
+    ) -> Self {
+        if indexes.len() <= max_points_per_leaf || depth >= MAX_DEPTH {
+            return Self {
+                min,
+                max,
+                indexes,
+                children: Vec::new(),
+            };
+        }
+
+        // Points on the middle lines go to the top and/or right quadrants
+        let mid = Point2D::new((min.x + max.x) / 2., (min.y + max.y) / 2.);
+        let mut quadrants: [Vec<usize>; 4] = Default::default();
+        for i in indexes {
+            let p = &points[i];
+            let right = (p.x >= mid.x) as usize;
+            let top = (p.y >= mid.y) as usize;
+            quadrants[2 * top + right].push(i);
+        }
+
+        let [bottom_left, bottom_right, top_left, top_right] = quadrants;
+        let children = vec![
+            (bottom_left, min, mid),
+            (
+                bottom_right,
+                Point2D::new(mid.x, min.y),
+                Point2D::new(max.x, mid.y),
+            ),
+            (
+                top_left,
+                Point2D::new(min.x, mid.y),
+                Point2D::new(mid.x, max.y),
+            ),
+            (top_right, mid, max),
+        ]
+        .into_iter()
+        .map(|(indexes, min, max)| {
+            Self::build_at_depth(points, indexes, min, max, max_points_per_leaf, depth + 1)
+        })
+        .collect();
+
+        Self {
+            min,
+            max,
+            indexes: Vec::new(),
+            children,
+        }
+    }
+
+    /// Checks whether the node has no children
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// Returns the (min, max) corners of the box
+    pub fn bounds(&self) -> (Point2D, Point2D) {
+        (self.min, self.max)
+    }
+
+    /// Borrows the quadrants of the box, which are either none or
+    /// four: bottom-left, bottom-right, top-left and top-right
+    pub fn children(&self) -> &[QuadNode] {
+        &self.children
+    }
+
+    /// Borrows the indexes of the points in the box, if this is a leaf
+    pub fn indexes(&self) -> &[usize] {
+        &self.indexes
+    }
+
+    /// Finds the points of `cloud` (i.e., the one this tree was built from)
+    /// within the rectangle going from `min` to `max`, borders included,
+    /// only descending into the boxes that overlap it.
+    pub fn query_rect(&self, cloud: &PointCloud2D, min: &Point2D, max: &Point2D) -> Vec<usize> {
+        let mut ret = Vec::new();
+        self.collect_in_rect(cloud.points(), min, max, &mut ret);
+        ret
+    }
+
+    /// Pushes into `ret` the points in this node (and its descendants)
+    /// that are within the `[min, max]` rectangle
+    fn collect_in_rect(
+        &self,
+        points: &[Point2D],
+        min: &Point2D,
+        max: &Point2D,
+        ret: &mut Vec<usize>,
+    ) {
+        let overlaps = |a_min: Float, a_max: Float, b_min: Float, b_max: Float| {
+            a_min <= b_max && b_min <= a_max
+        };
+        if !overlaps(self.min.x, self.max.x, min.x, max.x)
+            || !overlaps(self.min.y, self.max.y, min.y, max.y)
+        {
+            return;
+        }
+
+        for i in self.indexes.iter() {
+            let p = &points[*i];
+            if p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y {
+                ret.push(*i);
+            }
+        }
+        for child in self.children.iter() {
+            child.collect_in_rect(points, min, max, ret);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Random;
+
+    fn random_cloud(n: usize) -> PointCloud2D {
+        let mut random = Random::new(7);
+        let points = (0..n)
+            .map(|_| Point2D::new(random.next_float() * 100. - 50., random.next_float() * 40.))
+            .collect();
+        PointCloud2D::from_points(points)
+    }
+
+    fn count_points(node: &QuadNode) -> usize {
+        node.indexes().len() + node.children().iter().map(count_points).sum::<usize>()
+    }
+
+    #[test]
+    fn test_build_quadtree() {
+        let cloud = random_cloud(500);
+        let tree = cloud.build_quadtree(8);
+        assert!(!tree.is_leaf());
+        assert_eq!(count_points(&tree), 500);
+
+        fn check(node: &QuadNode, points: &[Point2D]) {
+            let (min, max) = node.bounds();
+            if node.is_leaf() {
+                assert!(node.indexes().len() <= 8);
+            } else {
+                assert!(node.indexes().is_empty());
+                assert_eq!(node.children().len(), 4);
+            }
+            for i in node.indexes() {
+                let p = &points[*i];
+                assert!(p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y);
+            }
+            for child in node.children() {
+                check(child, points);
+            }
+        }
+        check(&tree, cloud.points());
+
+        // Nothing to split
+        let tree = PointCloud2D::new().build_quadtree(8);
+        assert!(tree.is_leaf());
+        assert_eq!(count_points(&tree), 0);
+
+        // Coincident points cannot be split forever
+        let mut cloud = PointCloud2D::new();
+        for _ in 0..5 {
+            cloud.push(Point2D::new(1., 1.));
+        }
+        let tree = cloud.build_quadtree(2);
+        assert_eq!(count_points(&tree), 5);
+    }
+
+    #[test]
+    fn test_query_rect() {
+        let cloud = random_cloud(3000);
+        let tree = cloud.build_quadtree(16);
+
+        let rects = [
+            ((-60., -10.), (60., 50.)), // everything
+            ((-10., 5.), (12., 17.)),
+            ((30., 30.), (31., 39.)),
+            ((-50., 0.), (-49., 1.)),
+            ((70., 0.), (80., 10.)), // nothing
+        ];
+        for (min, max) in rects.iter() {
+            let min = Point2D::new(min.0, min.1);
+            let max = Point2D::new(max.0, max.1);
+            let mut found = tree.query_rect(&cloud, &min, &max);
+            found.sort_unstable();
+            let mut expected = cloud.points_in_rect(&min, &max);
+            expected.sort_unstable();
+            assert_eq!(found, expected);
+        }
+    }
+}
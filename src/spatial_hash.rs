@@ -0,0 +1,122 @@
+use crate::Float;
+
+use crate::point2d::Point2D;
+
+use std::collections::HashMap;
+
+/// A uniform grid of square cells, bucketing the indexes of the points
+/// of a PointCloud2D by the cell they fall within.
+///
+/// Cells are identified by `(floor(x / cell_size), floor(y / cell_size))`,
+/// and only the non-empty ones are stored.
+#[derive(Clone, Debug)]
+pub(crate) struct SpatialHash {
+    /// The width and height of the cells (in meters)
+    cell_size: Float,
+
+    /// The indexes of the points within each cell
+    buckets: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl SpatialHash {
+    /// Buckets all the `points` into cells of `cell_size`
+    pub(crate) fn new(points: &[Point2D], cell_size: Float) -> Self {
+        let mut ret = Self {
+            cell_size,
+            buckets: HashMap::new(),
+        };
+        for (i, p) in points.iter().enumerate() {
+            let cell = ret.cell_of(p);
+            ret.buckets.entry(cell).or_default().push(i);
+        }
+        ret
+    }
+
+    /// Finds the cell containing `p`
+    fn cell_of(&self, p: &Point2D) -> (i64, i64) {
+        // `as` saturates, so infinite coordinates go to the outermost
+        // cells and NaN ones go to the cell at the origin
+        (
+            (p.x / self.cell_size).floor() as i64,
+            (p.y / self.cell_size).floor() as i64,
+        )
+    }
+
+    /// Finds the indexes of the points that might be within `max_distance` of `p`:
+    /// those in the cells around the one containing `p`. When `max_distance` is no
+    /// larger than the cell size, that is the 3x3 neighbourhood of that cell.
+    pub(crate) fn candidates(&self, p: &Point2D, max_distance: Float) -> Vec<usize> {
+        if max_distance.is_nan() || max_distance < 0. {
+            return Vec::new();
+        }
+
+        let rings = (max_distance / self.cell_size).ceil();
+        let side = 2. * rings + 1.;
+        if side * side >= self.buckets.len() as Float {
+            // Visiting every cell around would be slower than
+            // visiting the ones that actually have points
+            return self.buckets.values().flatten().copied().collect();
+        }
+
+        let rings = rings as i64;
+        let (cx, cy) = self.cell_of(p);
+        let mut ret = Vec::new();
+        for x in cx.saturating_sub(rings)..=cx.saturating_add(rings) {
+            for y in cy.saturating_sub(rings)..=cy.saturating_add(rings) {
+                if let Some(bucket) = self.buckets.get(&(x, y)) {
+                    ret.extend_from_slice(bucket);
+                }
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        let mut points = vec![
+            Point2D::new(0.5, 0.5),
+            Point2D::new(1.5, 0.5),
+            Point2D::new(-0.5, -0.5),
+            Point2D::new(3.5, 0.5),
+            Point2D::new(0.5, 2.5),
+            Point2D::new(10., 10.),
+            Point2D::new(-10., 10.),
+            Point2D::new(10., -10.),
+            Point2D::new(-10., -10.),
+            Point2D::new(20., 20.),
+        ];
+        // A far away row, so that there are plenty of non-empty cells
+        points.extend((0..30).map(|i| Point2D::new(100. + i as Float, 50.)));
+        let hash = SpatialHash::new(&points, 1.);
+
+        // The 3x3 neighbourhood of (0, 0)
+        let mut found = hash.candidates(&Point2D::new(0.1, 0.9), 0.25);
+        found.sort_unstable();
+        assert_eq!(found, vec![0, 1, 2]);
+
+        // Two rings around it
+        let mut found = hash.candidates(&Point2D::new(0.1, 0.9), 1.5);
+        found.sort_unstable();
+        assert_eq!(found, vec![0, 1, 2, 4]);
+
+        // Only the cell itself
+        assert_eq!(hash.candidates(&Point2D::new(3.9, 0.1), 0.), vec![3]);
+
+        // Far enough to visit every non-empty cell
+        assert_eq!(
+            hash.candidates(&Point2D::new(0., 0.), 100.).len(),
+            points.len()
+        );
+
+        // Nonsense distances
+        assert!(hash.candidates(&Point2D::new(0.5, 0.5), -1.).is_empty());
+        assert!(hash
+            .candidates(&Point2D::new(0.5, 0.5), Float::NAN)
+            .is_empty());
+    }
+}
@@ -6,8 +6,25 @@ use crate::drawer2d::Drawer2D;
 ///
 /// This Trait contains the general mouse interactions... can be extended in the future.
 pub trait ToolTrait<T> {
+    /// The name of the Tool (e.g., `"pan"`), used for selecting it in the ToolBox
+    fn name(&self) -> &str;
+
     fn onmousemove(&mut self, drawable: &T, drawer: &mut Drawer2D, x: u32, y: u32);
     fn onmousedown(&mut self, drawable: &T, drawer: &mut Drawer2D, x: u32, y: u32);
     fn onmouseup(&mut self, drawable: &T, drawer: &mut Drawer2D, x: u32, y: u32);
     fn onwheel(&mut self, drawable: &T, drawer: &mut Drawer2D, dy: Float, x: u32, y: u32);
+
+    /// Reacts to a double click on (`x`, `y`). Does nothing by default.
+    fn ondblclick(&mut self, _drawable: &T, _drawer: &mut Drawer2D, _x: u32, _y: u32) {}
+
+    /// Reacts to a right click (i.e., the context menu event) on (`x`, `y`).
+    /// Does nothing by default.
+    fn oncontextmenu(&mut self, _drawable: &T, _drawer: &mut Drawer2D, _x: u32, _y: u32) {}
+
+    /// Reacts to a key being pressed, with `key` being the JavaScript
+    /// `KeyboardEvent.key` (e.g., `"Shift"` or `"Escape"`). Does nothing by default.
+    fn onkeydown(&mut self, _drawable: &T, _drawer: &mut Drawer2D, _key: &str) {}
+
+    /// Reacts to a key being released, just like `onkeydown()`. Does nothing by default.
+    fn onkeyup(&mut self, _drawable: &T, _drawer: &mut Drawer2D, _key: &str) {}
 }
@@ -8,6 +8,12 @@ pub struct ToolBox<T> {
     active_tool: Option<usize>,
 }
 
+impl<T> Default for ToolBox<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> ToolBox<T> {
     /// Creates a new empty ToolBox with no Tool selected
     pub fn new() -> Self {
@@ -19,51 +25,341 @@ impl<T> ToolBox<T> {
         }
     }
 
+    /// Adds a Tool to the ToolBox, returning its index
+    pub fn add_tool(&mut self, tool: Box<dyn ToolTrait<T>>) -> usize {
+        self.tools.push(tool);
+        self.tools.len() - 1
+    }
+
+    /// Makes the Tool with a certain index the active one.
+    ///
+    /// Returns an error if there is no such Tool
+    pub fn select_tool(&mut self, index: usize) -> Result<(), String> {
+        if index >= self.tools.len() {
+            return Err(format!(
+                "Cannot select tool {}... the ToolBox has {} tools",
+                index,
+                self.tools.len()
+            ));
+        }
+        self.active_tool = Some(index);
+        Ok(())
+    }
+
+    /// Makes the first Tool with a certain name the active one.
+    ///
+    /// Returns an error if there is no such Tool
+    pub fn select_tool_by_name(&mut self, name: &str) -> Result<(), String> {
+        match self.tools.iter().position(|t| t.name() == name) {
+            Some(i) => self.select_tool(i),
+            None => Err(format!("There is no tool called '{}' in the ToolBox", name)),
+        }
+    }
+
+    /// Lists the names of the Tools, in order
+    pub fn tool_names(&self) -> Vec<String> {
+        self.tools.iter().map(|t| t.name().to_string()).collect()
+    }
+
+    /// Gets the index of the selected Tool, if any. When None
+    /// is selected, events go to the first one.
+    pub fn active_tool_index(&self) -> Option<usize> {
+        self.active_tool
+    }
+
     /// Gets the Toolbox's active tool. If None is selected,
-    /// it returns the first one. Panics if the Toolbox has
-    /// no tools
-    fn get_active_tool(&self) -> usize {
-        match self.active_tool {
-            Some(i) => i,
-            None => {
-                if self.tools.is_empty() {
-                    panic!("ToolBox has no tools!");
-                } else {
-                    0
-                }
-            }
+    /// it returns the first one. Returns `None` if the Toolbox
+    /// has no tools
+    fn get_active_tool(&self) -> Option<usize> {
+        if self.tools.is_empty() {
+            return None;
         }
+        Some(self.active_tool.unwrap_or(0))
     }
 
     /// Calls the onmousemove event on the selected Tool.
     ///
-    /// If None is selected, calls it on the first one. Panics if Toolbox is empty.
+    /// If None is selected, calls it on the first one. Does nothing if the Toolbox is empty.
     pub fn onmousemove(&mut self, drawable: &T, drawer: &mut Drawer2D, x: u32, y: u32) {
-        let i = self.get_active_tool();
-        self.tools[i].onmousemove(drawable, drawer, x, y);
+        if let Some(i) = self.get_active_tool() {
+            self.tools[i].onmousemove(drawable, drawer, x, y);
+        }
     }
 
     /// Calls the onmouseup event on the selected Tool.
     ///
-    /// If None is selected, calls it on the first one. Panics if Toolbox is empty.
+    /// If None is selected, calls it on the first one. Does nothing if the Toolbox is empty.
     pub fn onmouseup(&mut self, drawable: &T, drawer: &mut Drawer2D, x: u32, y: u32) {
-        let i = self.get_active_tool();
-        self.tools[i].onmouseup(drawable, drawer, x, y);
+        if let Some(i) = self.get_active_tool() {
+            self.tools[i].onmouseup(drawable, drawer, x, y);
+        }
     }
 
     /// Calls the onmousedown event on the selected Tool.
     ///
-    /// If None is selected, calls it on the first one. Panics if Toolbox is empty.
+    /// If None is selected, calls it on the first one. Does nothing if the Toolbox is empty.
     pub fn onmousedown(&mut self, drawable: &T, drawer: &mut Drawer2D, x: u32, y: u32) {
-        let i = self.get_active_tool();
-        self.tools[i].onmousedown(drawable, drawer, x, y);
+        if let Some(i) = self.get_active_tool() {
+            self.tools[i].onmousedown(drawable, drawer, x, y);
+        }
     }
 
     /// Calls the onwheel event on the selected Tool.
     ///
-    /// If None is selected, calls it on the first one. Panics if Toolbox is empty.
+    /// If None is selected, calls it on the first one. Does nothing if the Toolbox is empty.
     pub fn onwheel(&mut self, drawable: &T, drawer: &mut Drawer2D, dy: Float, x: u32, y: u32) {
-        let i = self.get_active_tool();
-        self.tools[i].onwheel(drawable, drawer, dy, x, y);
+        if let Some(i) = self.get_active_tool() {
+            self.tools[i].onwheel(drawable, drawer, dy, x, y);
+        }
+    }
+
+    /// Calls the ondblclick event on the selected Tool.
+    ///
+    /// If None is selected, calls it on the first one. Does nothing if the Toolbox is empty.
+    pub fn ondblclick(&mut self, drawable: &T, drawer: &mut Drawer2D, x: u32, y: u32) {
+        if let Some(i) = self.get_active_tool() {
+            self.tools[i].ondblclick(drawable, drawer, x, y);
+        }
+    }
+
+    /// Calls the oncontextmenu event on the selected Tool.
+    ///
+    /// If None is selected, calls it on the first one. Does nothing if the Toolbox is empty.
+    pub fn oncontextmenu(&mut self, drawable: &T, drawer: &mut Drawer2D, x: u32, y: u32) {
+        if let Some(i) = self.get_active_tool() {
+            self.tools[i].oncontextmenu(drawable, drawer, x, y);
+        }
+    }
+
+    /// Calls the onkeydown event on the selected Tool.
+    ///
+    /// If None is selected, calls it on the first one. Does nothing if the Toolbox is empty.
+    pub fn onkeydown(&mut self, drawable: &T, drawer: &mut Drawer2D, key: &str) {
+        if let Some(i) = self.get_active_tool() {
+            self.tools[i].onkeydown(drawable, drawer, key);
+        }
+    }
+
+    /// Calls the onkeyup event on the selected Tool.
+    ///
+    /// If None is selected, calls it on the first one. Does nothing if the Toolbox is empty.
+    pub fn onkeyup(&mut self, drawable: &T, drawer: &mut Drawer2D, key: &str) {
+        if let Some(i) = self.get_active_tool() {
+            self.tools[i].onkeyup(drawable, drawer, key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// A Tool that logs the events it gets, along with its name
+    struct DummyTool {
+        name: &'static str,
+        log: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl DummyTool {
+        fn boxed(name: &'static str, log: &Rc<RefCell<Vec<String>>>) -> Box<Self> {
+            Box::new(Self {
+                name,
+                log: Rc::clone(log),
+            })
+        }
+
+        fn record(&self, event: &str) {
+            self.log
+                .borrow_mut()
+                .push(format!("{}:{}", self.name, event));
+        }
+    }
+
+    impl ToolTrait<()> for DummyTool {
+        fn name(&self) -> &str {
+            self.name
+        }
+        fn onmousemove(&mut self, _drawable: &(), _drawer: &mut Drawer2D, _x: u32, _y: u32) {
+            self.record("mousemove");
+        }
+        fn onmousedown(&mut self, _drawable: &(), _drawer: &mut Drawer2D, _x: u32, _y: u32) {
+            self.record("mousedown");
+        }
+        fn onmouseup(&mut self, _drawable: &(), _drawer: &mut Drawer2D, _x: u32, _y: u32) {
+            self.record("mouseup");
+        }
+        fn onwheel(
+            &mut self,
+            _drawable: &(),
+            _drawer: &mut Drawer2D,
+            _dy: Float,
+            _x: u32,
+            _y: u32,
+        ) {
+            self.record("wheel");
+        }
+        fn ondblclick(&mut self, _drawable: &(), _drawer: &mut Drawer2D, x: u32, y: u32) {
+            self.record(&format!("dblclick {},{}", x, y));
+        }
+        fn oncontextmenu(&mut self, _drawable: &(), _drawer: &mut Drawer2D, x: u32, y: u32) {
+            self.record(&format!("contextmenu {},{}", x, y));
+        }
+        fn onkeydown(&mut self, _drawable: &(), _drawer: &mut Drawer2D, key: &str) {
+            self.record(&format!("keydown {}", key));
+        }
+        fn onkeyup(&mut self, _drawable: &(), _drawer: &mut Drawer2D, key: &str) {
+            self.record(&format!("keyup {}", key));
+        }
+    }
+
+    /// A Tool that only cares about the mouse
+    struct MouseOnlyTool;
+
+    impl ToolTrait<()> for MouseOnlyTool {
+        fn name(&self) -> &str {
+            "mouse only"
+        }
+        fn onmousemove(&mut self, _drawable: &(), _drawer: &mut Drawer2D, _x: u32, _y: u32) {}
+        fn onmousedown(&mut self, _drawable: &(), _drawer: &mut Drawer2D, _x: u32, _y: u32) {}
+        fn onmouseup(&mut self, _drawable: &(), _drawer: &mut Drawer2D, _x: u32, _y: u32) {}
+        fn onwheel(
+            &mut self,
+            _drawable: &(),
+            _drawer: &mut Drawer2D,
+            _dy: Float,
+            _x: u32,
+            _y: u32,
+        ) {
+        }
+    }
+
+    #[test]
+    fn test_select_tool() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut drawer = Drawer2D::detached();
+        let mut toolbox: ToolBox<()> = ToolBox::new();
+
+        assert_eq!(toolbox.add_tool(DummyTool::boxed("pan", &log)), 0);
+        assert_eq!(toolbox.add_tool(DummyTool::boxed("draw", &log)), 1);
+        assert_eq!(toolbox.active_tool_index(), None);
+
+        // The first one by default
+        toolbox.onmousedown(&(), &mut drawer, 1, 2);
+
+        // Then the second one
+        assert!(toolbox.select_tool(1).is_ok());
+        assert_eq!(toolbox.active_tool_index(), Some(1));
+        toolbox.onmousedown(&(), &mut drawer, 1, 2);
+        toolbox.onmousemove(&(), &mut drawer, 1, 2);
+        toolbox.onmouseup(&(), &mut drawer, 1, 2);
+        toolbox.onwheel(&(), &mut drawer, 1., 1, 2);
+
+        // Out of range
+        assert!(toolbox.select_tool(2).is_err());
+        assert_eq!(toolbox.active_tool_index(), Some(1));
+
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                "pan:mousedown",
+                "draw:mousedown",
+                "draw:mousemove",
+                "draw:mouseup",
+                "draw:wheel"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_select_tool_by_name() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut drawer = Drawer2D::detached();
+        let mut toolbox: ToolBox<()> = ToolBox::new();
+        for name in ["pan", "draw", "select", "draw"].iter() {
+            toolbox.add_tool(DummyTool::boxed(name, &log));
+        }
+        assert_eq!(toolbox.tool_names(), vec!["pan", "draw", "select", "draw"]);
+
+        assert!(toolbox.select_tool_by_name("select").is_ok());
+        assert_eq!(toolbox.active_tool_index(), Some(2));
+        toolbox.onmousedown(&(), &mut drawer, 0, 0);
+
+        // Duplicates go to the first one
+        assert!(toolbox.select_tool_by_name("draw").is_ok());
+        assert_eq!(toolbox.active_tool_index(), Some(1));
+
+        // Unknown
+        assert!(toolbox.select_tool_by_name("erase").is_err());
+        assert_eq!(toolbox.active_tool_index(), Some(1));
+
+        toolbox.onmousedown(&(), &mut drawer, 0, 0);
+        assert_eq!(*log.borrow(), vec!["select:mousedown", "draw:mousedown"]);
+    }
+
+    #[test]
+    fn test_empty_toolbox() {
+        let mut drawer = Drawer2D::detached();
+        let mut toolbox: ToolBox<()> = ToolBox::new();
+        assert_eq!(toolbox.get_active_tool(), None);
+
+        // Nothing happens... no panics
+        toolbox.onmousemove(&(), &mut drawer, 1, 2);
+        toolbox.onmousedown(&(), &mut drawer, 1, 2);
+        toolbox.onmouseup(&(), &mut drawer, 1, 2);
+        toolbox.onwheel(&(), &mut drawer, 1., 1, 2);
+        assert_eq!(toolbox.active_tool_index(), None);
+    }
+
+    #[test]
+    fn test_key_events() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut drawer = Drawer2D::detached();
+        let mut toolbox: ToolBox<()> = ToolBox::new();
+        toolbox.add_tool(DummyTool::boxed("pan", &log));
+        toolbox.add_tool(DummyTool::boxed("draw", &log));
+        toolbox.add_tool(Box::new(MouseOnlyTool));
+
+        toolbox.onkeydown(&(), &mut drawer, "Shift");
+        toolbox.select_tool(1).unwrap();
+        toolbox.onkeyup(&(), &mut drawer, "Shift");
+        toolbox.onkeydown(&(), &mut drawer, "Escape");
+
+        // Tools without key events ignore them
+        toolbox.select_tool(2).unwrap();
+        toolbox.onkeydown(&(), &mut drawer, "a");
+
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                "pan:keydown Shift",
+                "draw:keyup Shift",
+                "draw:keydown Escape"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_click_events() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut drawer = Drawer2D::detached();
+        let mut toolbox: ToolBox<()> = ToolBox::new();
+        toolbox.add_tool(Box::new(MouseOnlyTool));
+        toolbox.add_tool(DummyTool::boxed("polygon", &log));
+
+        // Ignored by the first tool
+        toolbox.ondblclick(&(), &mut drawer, 1, 2);
+        toolbox.oncontextmenu(&(), &mut drawer, 1, 2);
+
+        toolbox.select_tool_by_name("polygon").unwrap();
+        toolbox.oncontextmenu(&(), &mut drawer, 3, 4);
+        toolbox.ondblclick(&(), &mut drawer, 5, 6);
+
+        assert_eq!(
+            *log.borrow(),
+            vec!["polygon:contextmenu 3,4", "polygon:dblclick 5,6"]
+        );
     }
 }
@@ -1,5 +1,4 @@
 #[allow(dead_code)]
-
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
     // `set_panic_hook` function at least once during initialization, and then
@@ -10,3 +9,25 @@ pub fn set_panic_hook() {
     #[cfg(feature = "console_error_panic_hook")]
     console_error_panic_hook::set_once();
 }
+
+/// A tiny pseudo-random number generator (a 64-bit LCG), so that
+/// tests can build large clouds that are the same on every run
+#[cfg(test)]
+pub(crate) struct Random(u64);
+
+#[cfg(test)]
+impl Random {
+    /// Builds a generator starting at `seed`
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Returns a number within `[0, 1)`
+    pub(crate) fn next_float(&mut self) -> crate::Float {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 11) as f64 / (1u64 << 53) as f64) as crate::Float
+    }
+}